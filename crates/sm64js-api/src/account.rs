@@ -29,9 +29,17 @@ async fn get_account_info(
         if let Some(mut account_info) =
             sm64js_db::get_account_info(&conn, query.account_id, extended_info)
         {
-            if !auth_info.has_permission(&Permission::SeeIp) {
+            let with_ip = auth_info.has_permission(&Permission::SeeIp);
+            if !with_ip {
                 account_info.account.last_ip = None;
             }
+            if auth_info.has_permission(&Permission::GetPunishments) {
+                account_info.punishments = Some(sm64js_db::get_punishment_history(
+                    &conn,
+                    query.account_id,
+                    with_ip,
+                )?);
+            }
             Ok(web::Json(account_info))
         } else {
             Err(GetAccountError::NotFound)
@@ -57,14 +65,17 @@ enum GetAccountError {
     NotFound,
     #[error("[Anyhow]: {0}")]
     Anyhow(#[from] anyhow::Error),
+    #[error("[DbError]: {0}")]
+    DbError(#[from] sm64js_db::DbError),
 }
 
 impl ResponseError for GetAccountError {
     fn error_response(&self) -> HttpResponse {
-        let res = match *self {
+        let res = match self {
             Self::Unauthorized => HttpResponse::new(StatusCode::UNAUTHORIZED),
             Self::NotFound => HttpResponse::new(StatusCode::NOT_FOUND),
             Self::Anyhow(_) => HttpResponse::new(StatusCode::INTERNAL_SERVER_ERROR),
+            Self::DbError(err) => return err.error_response(),
         };
         res.set_body(Body::from(format!("{}", self)))
     }
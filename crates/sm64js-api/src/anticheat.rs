@@ -0,0 +1,53 @@
+use actix::prelude::*;
+use actix_http::ResponseError;
+use actix_web::{
+    dev::{Body, HttpServiceFactory},
+    http::StatusCode,
+    HttpResponse,
+};
+use paperclip::actix::{api_v2_errors, api_v2_operation, web, Mountable};
+use sm64js_auth::{Identity, Permission};
+use sm64js_common::FlaggedAccount;
+use sm64js_ws::Sm64JsServer;
+use thiserror::Error;
+
+pub fn service() -> impl HttpServiceFactory + Mountable {
+    web::scope("/anticheat")
+        .service(web::resource("/flagged").route(web::get().to(get_flagged_accounts)))
+}
+
+/// GET Flagged accounts
+///
+/// Returns all accounts that sent invalid movement updates since the server started,
+/// including their most recent violations as evidence.
+#[api_v2_operation(tags(Moderation))]
+async fn get_flagged_accounts(
+    identity: Identity,
+    srv: web::Data<Addr<Sm64JsServer>>,
+) -> Result<web::Json<Vec<FlaggedAccount>>, GetFlaggedAccountsError> {
+    let auth_info = identity.get_auth_info();
+    if !auth_info.has_permission(&Permission::GetFlaggedAccounts) {
+        return Err(GetFlaggedAccountsError::Unauthorized);
+    }
+
+    Ok(web::Json(srv.send(sm64js_ws::GetFlaggedAccounts).await?))
+}
+
+#[api_v2_errors(code = 401, code = 500)]
+#[derive(Debug, Error)]
+enum GetFlaggedAccountsError {
+    #[error("[Unauthorized]")]
+    Unauthorized,
+    #[error("[MailboxError]: {0}")]
+    Mailbox(#[from] MailboxError),
+}
+
+impl ResponseError for GetFlaggedAccountsError {
+    fn error_response(&self) -> HttpResponse {
+        let res = match *self {
+            Self::Unauthorized => HttpResponse::new(StatusCode::UNAUTHORIZED),
+            Self::Mailbox(_) => HttpResponse::new(StatusCode::INTERNAL_SERVER_ERROR),
+        };
+        res.set_body(Body::from(format!("{}", self)))
+    }
+}
@@ -1,40 +1,124 @@
+use actix::prelude::*;
 use actix_web::{dev::Body, http::StatusCode, HttpResponse, ResponseError};
-use paperclip::actix::{api_v2_errors, api_v2_operation, web};
+use paperclip::actix::{api_v2_errors, api_v2_operation, web, NoContent};
 use sm64js_auth::{Identity, Permission};
-use sm64js_common::{ChatHistoryData, ChatMessage, GetChat};
+use sm64js_common::{ChatCursor, ChatFilterConfigError, ChatPage, GetChat};
+use sm64js_db::{DbError, DbPool};
+use sm64js_ws::Sm64JsServer;
 use thiserror::Error;
 
+/// Maximum length of a regular expression
+const MAX_REGEX_LEN: usize = 256;
+
 /// GET Chat history data
+///
+/// Returns the most recent messages that match all given filters in chronological order.
+/// Older messages can be fetched by passing the returned `nextCursor` as `cursor`.
 #[api_v2_operation(tags(Chat))]
 pub async fn get_chat(
     query: web::Query<GetChat>,
     identity: Identity,
-    chat_history: ChatHistoryData,
-) -> Result<web::Json<Vec<ChatMessage>>, GetChatError> {
+    pool: web::Data<DbPool>,
+) -> Result<web::Json<ChatPage>, GetChatError> {
     let auth_info = identity.get_auth_info();
-    if auth_info.has_permission(&Permission::ReadChatLog) {
-        let with_ip = auth_info.has_permission(&Permission::SeeIp);
-        Ok(web::Json(chat_history.read().get_messages(
-            query.into_inner(),
-            true,
-            with_ip,
-        )))
-    } else {
-        Err(GetChatError::Unauthorized)
+    if !auth_info.has_permission(&Permission::ReadChatLog) {
+        return Err(GetChatError::Unauthorized);
+    }
+    let with_ip = auth_info.has_permission(&Permission::SeeIp);
+    if query.ip.is_some() && !with_ip {
+        return Err(GetChatError::Unauthorized);
+    }
+    if let Some(limit) = query.limit {
+        if limit == 0 || limit > 1000 {
+            return Err(GetChatError::InvalidLimit);
+        }
     }
+    if let Some(regex) = &query.regex {
+        if regex.len() > MAX_REGEX_LEN {
+            return Err(GetChatError::RegexTooLong);
+        }
+    }
+    let cursor = query
+        .cursor
+        .as_deref()
+        .map(|cursor| ChatCursor::parse(cursor).ok_or(GetChatError::InvalidCursor))
+        .transpose()?;
+
+    let conn = pool.get().unwrap();
+    let (messages, next_cursor) = sm64js_db::get_chat_messages(&conn, &query, cursor)?;
+    Ok(web::Json(ChatPage {
+        messages: messages
+            .into_iter()
+            .map(|msg| msg.into_chat_message(true, with_ip))
+            .collect(),
+        next_cursor: next_cursor.map(|cursor| cursor.to_string()),
+    }))
 }
 
-#[api_v2_errors(code = 401)]
+#[api_v2_errors(code = 400, code = 401, code = 408, code = 500)]
 #[derive(Debug, Error)]
 pub enum GetChatError {
     #[error("[Unauthorized]")]
     Unauthorized,
+    #[error("[InvalidLimit]: limit must be between 1 and 1000")]
+    InvalidLimit,
+    #[error("[InvalidCursor]")]
+    InvalidCursor,
+    #[error("[RegexTooLong]: regex must not be longer than {}", MAX_REGEX_LEN)]
+    RegexTooLong,
+    #[error("[DbError]: {0}")]
+    DbError(#[from] DbError),
 }
 
 impl ResponseError for GetChatError {
+    fn error_response(&self) -> HttpResponse {
+        let res = match self {
+            Self::Unauthorized => HttpResponse::new(StatusCode::UNAUTHORIZED),
+            Self::InvalidLimit | Self::InvalidCursor | Self::RegexTooLong => {
+                HttpResponse::new(StatusCode::BAD_REQUEST)
+            }
+            Self::DbError(err) => return err.error_response(),
+        };
+        res.set_body(Body::from(format!("{}", self)))
+    }
+}
+
+/// POST Reload chat filters
+///
+/// Reloads the chat filter config file.
+/// The current filters stay active, if the config is invalid.
+#[api_v2_operation(tags(Moderation))]
+pub async fn post_reload_filters(
+    identity: Identity,
+    srv: web::Data<Addr<Sm64JsServer>>,
+) -> Result<NoContent, ReloadChatFiltersError> {
+    let auth_info = identity.get_auth_info();
+    if !auth_info.has_permission(&Permission::ReloadChatFilters) {
+        return Err(ReloadChatFiltersError::Unauthorized);
+    }
+
+    srv.send(sm64js_ws::ReloadChatFilters).await??;
+
+    Ok(NoContent)
+}
+
+#[api_v2_errors(code = 401, code = 422, code = 500)]
+#[derive(Debug, Error)]
+pub enum ReloadChatFiltersError {
+    #[error("[Unauthorized]")]
+    Unauthorized,
+    #[error("[MailboxError]: {0}")]
+    Mailbox(#[from] MailboxError),
+    #[error("[ChatFilterConfigError]: {0}")]
+    ChatFilterConfig(#[from] ChatFilterConfigError),
+}
+
+impl ResponseError for ReloadChatFiltersError {
     fn error_response(&self) -> HttpResponse {
         let res = match *self {
             Self::Unauthorized => HttpResponse::new(StatusCode::UNAUTHORIZED),
+            Self::Mailbox(_) => HttpResponse::new(StatusCode::INTERNAL_SERVER_ERROR),
+            Self::ChatFilterConfig(_) => HttpResponse::new(StatusCode::UNPROCESSABLE_ENTITY),
         };
         res.set_body(Body::from(format!("{}", self)))
     }
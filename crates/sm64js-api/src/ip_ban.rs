@@ -4,11 +4,13 @@ use actix::prelude::*;
 use actix_http::{body::Body, client::SendRequestError, http::StatusCode, ResponseError};
 use actix_web::HttpResponse;
 use chrono::Utc;
+use diesel::Connection;
 use paperclip::actix::{api_v2_errors, api_v2_operation, web, Apiv2Schema, NoContent};
 use serde::Deserialize;
 use serde_with::skip_serializing_none;
 use sm64js_auth::{Identity, Permission};
-use sm64js_db::DbPool;
+use sm64js_common::{ModerationActionKind, ModerationSource};
+use sm64js_db::{models::NewModerationAction, DbPool};
 use sm64js_ws::{KickClientByIpAddr, Sm64JsServer};
 use thiserror::Error;
 
@@ -38,6 +40,14 @@ pub async fn post_ban(
         Err(_) => return Err(BanError::IpAddrParse),
     };
 
+    let conn = pool.get().unwrap();
+    // overwriting a ban lifts it, which requires the same permission as DELETE Ban IP address
+    if let Some(ip_ban) = sm64js_db::is_ip_banned(&conn, &query.ip)? {
+        if !auth_info.has_permission(&Permission::revoke_ban(ip_ban.expires_at)) {
+            return Err(BanError::Unauthorized);
+        }
+    }
+
     match srv
         .send(KickClientByIpAddr {
             ip: query.ip.clone(),
@@ -50,40 +60,88 @@ pub async fn post_ban(
         }
     }
 
-    let conn = pool.get().unwrap();
-
     let expires_at = query.expires_in.map(|exp| {
         Utc::now().naive_utc()
             + chrono::Duration::from_std(exp).unwrap_or_else(|_| chrono::Duration::milliseconds(0))
     });
-    sm64js_db::ban_ip(&conn, query.ip.clone(), query.reason.clone(), expires_at)?;
+    conn.transaction::<_, sm64js_db::DbError, _>(|| {
+        sm64js_db::ban_ip(&conn, query.ip.clone(), query.reason.clone(), expires_at)?;
+        sm64js_db::log_moderation_action(
+            &conn,
+            NewModerationAction {
+                target_ip: Some(query.ip.clone()),
+                expires_at,
+                reason: query.reason.clone(),
+                ..NewModerationAction::new(
+                    ModerationActionKind::IpBan,
+                    ModerationSource::Api,
+                    Some(auth_info.get_account_id()),
+                )
+            },
+        )
+    })?;
 
     actix::spawn(async move {
-        let message = format!(
-            r"reason: {}
-    expires_at: {}
-            ",
-            query.reason.clone().unwrap_or_default(),
-            expires_at.map(|exp| exp.to_string()).unwrap_or_default()
-        );
-        let author = sm64js_common::DiscordRichEmbedAuthor {
-            name: format!(
+        sm64js_common::send_discord_ip_moderation_message(
+            format!(
                 "POST Ban IP address by {}",
                 auth_info.get_discord_username().unwrap_or_default()
             ),
-            url: None,
-            icon_url: None,
-        };
-        let footer = Some(sm64js_common::DiscordRichEmbedFooter {
-            text: query.ip.clone(),
-        });
-        sm64js_common::send_discord_message(
-            "829813249520042066",
-            None,
-            message,
+            query.reason.clone(),
+            expires_at,
+            query.ip.clone(),
+        )
+        .await;
+    });
+
+    Ok(NoContent)
+}
+
+/// DELETE Lift ban of IP address
+#[api_v2_operation(tags(Moderation))]
+pub async fn delete_ban(
+    query: web::Query<DeleteIpBan>,
+    pool: web::Data<DbPool>,
+    identity: Identity,
+) -> Result<NoContent, BanError> {
+    let auth_info = identity.get_auth_info();
+
+    let conn = pool.get().unwrap();
+    let ip_ban = sm64js_db::is_ip_banned(&conn, &query.ip)?.ok_or(BanError::NotFound)?;
+    if !auth_info.has_permission(&Permission::revoke_ban(ip_ban.expires_at)) {
+        return Err(BanError::Unauthorized);
+    }
+
+    conn.transaction::<_, sm64js_db::DbError, _>(|| {
+        sm64js_db::revoke_ip_ban(
+            &conn,
+            &query.ip,
+            Some(auth_info.get_account_id()),
+            query.reason.clone(),
+        )?;
+        sm64js_db::log_moderation_action(
+            &conn,
+            NewModerationAction {
+                target_ip: Some(query.ip.clone()),
+                reason: query.reason.clone(),
+                ..NewModerationAction::new(
+                    ModerationActionKind::IpUnban,
+                    ModerationSource::Api,
+                    Some(auth_info.get_account_id()),
+                )
+            },
+        )
+    })?;
+
+    actix::spawn(async move {
+        sm64js_common::send_discord_ip_moderation_message(
+            format!(
+                "DELETE Ban IP address by {}",
+                auth_info.get_discord_username().unwrap_or_default()
+            ),
+            query.reason.clone(),
             None,
-            author,
-            footer,
+            query.ip.clone(),
         )
         .await;
     });
@@ -99,19 +157,30 @@ pub struct PostIpBan {
     /// Parses duration for temp bans, e.g. "15days". See https://docs.rs/humantime/2.1.0/humantime/index.html
     ///
     /// Keep this empty for a permanent ban.
-    /// Banning will overwrite an already existing ban, so if you want to unban someone, just set this to "0s"
+    /// Banning will overwrite an already existing ban,
+    /// if you are allowed to lift it
     #[serde(default)]
     #[serde(with = "humantime_serde")]
     expires_in: Option<Duration>,
 }
 
-#[api_v2_errors(code = 400, code = 500)]
+#[skip_serializing_none]
+#[derive(Apiv2Schema, Debug, Deserialize)]
+pub struct DeleteIpBan {
+    ip: String,
+    /// Why the ban is lifted, e.g. because of an appeal
+    reason: Option<String>,
+}
+
+#[api_v2_errors(code = 400, code = 401, code = 404, code = 500)]
 #[derive(Debug, Error)]
 pub enum BanError {
     #[error("[Unauthorized]")]
     Unauthorized,
     #[error("[IpAddrParse]")]
     IpAddrParse,
+    #[error("[NotFound]: IP address is not banned")]
+    NotFound,
     #[error("[SendRequest]: {0}")]
     SendRequest(#[from] SendRequestError),
     #[error("[MailboxError]: {0}")]
@@ -125,6 +194,7 @@ impl ResponseError for BanError {
         let res = match self {
             Self::Unauthorized => HttpResponse::new(StatusCode::UNAUTHORIZED),
             Self::IpAddrParse => HttpResponse::new(StatusCode::BAD_REQUEST),
+            Self::NotFound => HttpResponse::new(StatusCode::NOT_FOUND),
             Self::SendRequest(_) => HttpResponse::new(StatusCode::INTERNAL_SERVER_ERROR),
             Self::Mailbox(_) => HttpResponse::new(StatusCode::INTERNAL_SERVER_ERROR),
             Self::DbError(err) => return err.error_response(),
@@ -0,0 +1,82 @@
+use actix_http::ResponseError;
+use actix_web::{
+    dev::{Body, HttpServiceFactory},
+    http::StatusCode,
+    HttpResponse,
+};
+use chrono::NaiveDateTime;
+use paperclip::actix::{api_v2_errors, api_v2_operation, web, Apiv2Schema, Mountable};
+use serde::{Deserialize, Serialize};
+use sm64js_db::{DbError, DbPool};
+use thiserror::Error;
+
+pub fn service() -> impl HttpServiceFactory + Mountable {
+    web::scope("/leaderboard").service(web::resource("").route(web::get().to(get_leaderboard)))
+}
+
+/// GET Race leaderboard
+///
+/// Returns the best race times of a level. Each account is listed only once.
+#[api_v2_operation(tags(Leaderboard))]
+async fn get_leaderboard(
+    query: web::Query<GetLeaderboard>,
+    pool: web::Data<DbPool>,
+) -> Result<web::Json<Vec<LeaderboardEntry>>, GetLeaderboardError> {
+    let limit = query.limit.unwrap_or(10);
+    if limit == 0 || limit > 100 {
+        return Err(GetLeaderboardError::InvalidLimit);
+    }
+
+    let conn = pool.get().unwrap();
+    let race_times = sm64js_db::get_leaderboard(&conn, query.level as i32, limit as i64)?;
+
+    Ok(web::Json(
+        race_times
+            .into_iter()
+            .enumerate()
+            .map(|(i, race_time)| LeaderboardEntry {
+                rank: i as u32 + 1,
+                account_id: race_time.account_id,
+                player_name: race_time.player_name,
+                time_ms: race_time.time_ms as u32,
+                finished_at: race_time.finished_at,
+            })
+            .collect(),
+    ))
+}
+
+#[derive(Apiv2Schema, Debug, Deserialize)]
+pub struct GetLeaderboard {
+    level: u32,
+    /// Number of entries. Defaults to 10, maximum is 100
+    limit: Option<u32>,
+}
+
+#[derive(Apiv2Schema, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LeaderboardEntry {
+    rank: u32,
+    account_id: i32,
+    player_name: String,
+    time_ms: u32,
+    finished_at: NaiveDateTime,
+}
+
+#[api_v2_errors(code = 400, code = 500)]
+#[derive(Debug, Error)]
+enum GetLeaderboardError {
+    #[error("[InvalidLimit]: limit must be between 1 and 100")]
+    InvalidLimit,
+    #[error("[DbError]: {0}")]
+    DbError(#[from] DbError),
+}
+
+impl ResponseError for GetLeaderboardError {
+    fn error_response(&self) -> HttpResponse {
+        let res = match self {
+            Self::InvalidLimit => HttpResponse::new(StatusCode::BAD_REQUEST),
+            Self::DbError(err) => return err.error_response(),
+        };
+        res.set_body(Body::from(format!("{}", self)))
+    }
+}
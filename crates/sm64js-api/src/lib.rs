@@ -2,13 +2,21 @@
 extern crate anyhow;
 
 mod account;
+mod anticheat;
 mod ban;
 mod chat;
 mod ip_ban;
+mod leaderboard;
 mod login;
 mod logout;
+mod maintenance;
+mod metrics;
+mod moderation;
 mod mute;
 mod players;
+mod punishments;
+mod rooms;
+mod warn;
 
 use actix_web::dev;
 use paperclip::actix::{web, Mountable};
@@ -16,11 +24,34 @@ use paperclip::actix::{web, Mountable};
 pub fn service() -> impl dev::HttpServiceFactory + Mountable {
     web::scope("/api")
         .service(web::resource("/chat").route(web::get().to(chat::get_chat)))
+        .service(
+            web::resource("/chat/filters/reload").route(web::post().to(chat::post_reload_filters)),
+        )
         .service(players::service())
+        .service(rooms::service())
+        .service(leaderboard::service())
         .service(account::service())
+        .service(anticheat::service())
+        .service(metrics::service())
+        .service(maintenance::service())
         .service(login::service())
         .service(web::resource("/logout").route(web::post().to(logout::post_logout)))
-        .service(web::resource("/ban").route(web::post().to(ban::post_ban)))
-        .service(web::resource("/ipban").route(web::post().to(ip_ban::post_ban)))
-        .service(web::resource("/mute").route(web::post().to(mute::post_mute)))
+        .service(punishments::service())
+        .service(moderation::service())
+        .service(
+            web::resource("/ban")
+                .route(web::post().to(ban::post_ban))
+                .route(web::delete().to(ban::delete_ban)),
+        )
+        .service(
+            web::resource("/ipban")
+                .route(web::post().to(ip_ban::post_ban))
+                .route(web::delete().to(ip_ban::delete_ban)),
+        )
+        .service(
+            web::resource("/mute")
+                .route(web::post().to(mute::post_mute))
+                .route(web::delete().to(mute::delete_mute)),
+        )
+        .service(web::resource("/warn").route(web::post().to(warn::post_warn)))
 }
@@ -0,0 +1,78 @@
+use actix::prelude::*;
+use actix_http::ResponseError;
+use actix_web::{
+    dev::{Body, HttpServiceFactory},
+    http::StatusCode,
+    HttpResponse,
+};
+use paperclip::actix::{api_v2_errors, api_v2_operation, web, Apiv2Schema, Mountable};
+use serde::Deserialize;
+use sm64js_auth::{Identity, Permission};
+use sm64js_common::ServerStatus;
+use sm64js_ws::Sm64JsServer;
+use thiserror::Error;
+
+pub fn service() -> impl HttpServiceFactory + Mountable {
+    web::scope("/maintenance").service(
+        web::resource("")
+            .route(web::get().to(get_maintenance))
+            .route(web::post().to(post_maintenance)),
+    )
+}
+
+/// GET Server status
+///
+/// Returns whether the server is in maintenance mode or shutting down.
+#[api_v2_operation(tags(Moderation))]
+async fn get_maintenance(
+    srv: web::Data<Addr<Sm64JsServer>>,
+) -> Result<web::Json<ServerStatus>, MaintenanceError> {
+    Ok(web::Json(srv.send(sm64js_ws::GetServerStatus).await?))
+}
+
+/// POST Maintenance mode
+///
+/// While maintenance mode is enabled, only accounts with the `JoinDuringMaintenance`
+/// permission can connect and join games. Players that are already in a game stay connected.
+#[api_v2_operation(tags(Moderation))]
+async fn post_maintenance(
+    identity: Identity,
+    srv: web::Data<Addr<Sm64JsServer>>,
+    body: web::Json<PostMaintenance>,
+) -> Result<web::Json<ServerStatus>, MaintenanceError> {
+    let auth_info = identity.get_auth_info();
+    if !auth_info.has_permission(&Permission::SetMaintenanceMode) {
+        return Err(MaintenanceError::Unauthorized);
+    }
+
+    srv.send(sm64js_ws::SetMaintenanceMode {
+        enabled: body.enabled,
+    })
+    .await?;
+    Ok(web::Json(srv.send(sm64js_ws::GetServerStatus).await?))
+}
+
+#[derive(Apiv2Schema, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PostMaintenance {
+    enabled: bool,
+}
+
+#[api_v2_errors(code = 401, code = 500)]
+#[derive(Debug, Error)]
+enum MaintenanceError {
+    #[error("[Unauthorized]")]
+    Unauthorized,
+    #[error("[MailboxError]: {0}")]
+    Mailbox(#[from] MailboxError),
+}
+
+impl ResponseError for MaintenanceError {
+    fn error_response(&self) -> HttpResponse {
+        let res = match *self {
+            Self::Unauthorized => HttpResponse::new(StatusCode::UNAUTHORIZED),
+            Self::Mailbox(_) => HttpResponse::new(StatusCode::INTERNAL_SERVER_ERROR),
+        };
+        res.set_body(Body::from(format!("{}", self)))
+    }
+}
@@ -0,0 +1,45 @@
+use actix_http::ResponseError;
+use actix_web::{
+    dev::{Body, HttpServiceFactory},
+    http::StatusCode,
+    HttpResponse,
+};
+use paperclip::actix::{api_v2_errors, api_v2_operation, web, Mountable};
+use sm64js_auth::{Identity, Permission};
+use sm64js_common::ServerMetrics;
+use sm64js_ws::METRICS;
+use thiserror::Error;
+
+pub fn service() -> impl HttpServiceFactory + Mountable {
+    web::scope("/metrics").service(web::resource("").route(web::get().to(get_metrics)))
+}
+
+/// GET Server metrics
+///
+/// Returns counters of dropped messages, kicked clients and game loop timings
+/// since the server started.
+#[api_v2_operation(tags(Moderation))]
+async fn get_metrics(identity: Identity) -> Result<web::Json<ServerMetrics>, GetMetricsError> {
+    let auth_info = identity.get_auth_info();
+    if !auth_info.has_permission(&Permission::GetMetrics) {
+        return Err(GetMetricsError::Unauthorized);
+    }
+
+    Ok(web::Json(METRICS.get_snapshot()))
+}
+
+#[api_v2_errors(code = 401)]
+#[derive(Debug, Error)]
+enum GetMetricsError {
+    #[error("[Unauthorized]")]
+    Unauthorized,
+}
+
+impl ResponseError for GetMetricsError {
+    fn error_response(&self) -> HttpResponse {
+        let res = match *self {
+            Self::Unauthorized => HttpResponse::new(StatusCode::UNAUTHORIZED),
+        };
+        res.set_body(Body::from(format!("{}", self)))
+    }
+}
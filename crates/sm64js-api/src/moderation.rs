@@ -0,0 +1,65 @@
+use actix_web::{
+    dev::{Body, HttpServiceFactory},
+    http::StatusCode,
+    HttpResponse, ResponseError,
+};
+use paperclip::actix::{api_v2_errors, api_v2_operation, web, Mountable};
+use sm64js_auth::{Identity, Permission};
+use sm64js_common::{GetModerationLog, ModerationAction};
+use sm64js_db::{DbError, DbPool};
+use thiserror::Error;
+
+pub fn service() -> impl HttpServiceFactory + Mountable {
+    web::scope("/moderation").service(web::resource("/log").route(web::get().to(get_log)))
+}
+
+/// GET Moderation log
+///
+/// Returns bans, mutes, kicks, warnings and lifted punishments that match all given filters,
+/// most recent first.
+#[api_v2_operation(tags(Moderation))]
+async fn get_log(
+    query: web::Query<GetModerationLog>,
+    identity: Identity,
+    pool: web::Data<DbPool>,
+) -> Result<web::Json<Vec<ModerationAction>>, GetModerationLogError> {
+    let auth_info = identity.get_auth_info();
+    if !auth_info.has_permission(&Permission::GetModerationLog) {
+        return Err(GetModerationLogError::Unauthorized);
+    }
+    let with_ip = auth_info.has_permission(&Permission::SeeIp);
+    if query.target_ip.is_some() && !with_ip {
+        return Err(GetModerationLogError::Unauthorized);
+    }
+    if let Some(limit) = query.limit {
+        if limit == 0 || limit > 1000 {
+            return Err(GetModerationLogError::InvalidLimit);
+        }
+    }
+
+    let conn = pool.get().unwrap();
+    let actions = sm64js_db::get_moderation_log(&conn, &query, with_ip)?;
+    Ok(web::Json(actions))
+}
+
+#[api_v2_errors(code = 400, code = 401, code = 500)]
+#[derive(Debug, Error)]
+enum GetModerationLogError {
+    #[error("[Unauthorized]")]
+    Unauthorized,
+    #[error("[InvalidLimit]: limit must be between 1 and 1000")]
+    InvalidLimit,
+    #[error("[DbError]: {0}")]
+    DbError(#[from] DbError),
+}
+
+impl ResponseError for GetModerationLogError {
+    fn error_response(&self) -> HttpResponse {
+        let res = match self {
+            Self::Unauthorized => HttpResponse::new(StatusCode::UNAUTHORIZED),
+            Self::InvalidLimit => HttpResponse::new(StatusCode::BAD_REQUEST),
+            Self::DbError(err) => return err.error_response(),
+        };
+        res.set_body(Body::from(format!("{}", self)))
+    }
+}
@@ -4,12 +4,13 @@ use actix::prelude::*;
 use actix_http::{body::Body, client::SendRequestError, http::StatusCode, ResponseError};
 use actix_web::HttpResponse;
 use chrono::Utc;
+use diesel::Connection;
 use paperclip::actix::{api_v2_errors, api_v2_operation, web, Apiv2Schema, NoContent};
 use serde::Deserialize;
 use serde_with::skip_serializing_none;
 use sm64js_auth::{Identity, Permission};
-use sm64js_db::DbPool;
-use sm64js_env::REDIRECT_URI;
+use sm64js_common::{ModerationActionKind, ModerationSource};
+use sm64js_db::{models::NewModerationAction, DbPool};
 use thiserror::Error;
 
 /// POST Mute player
@@ -34,55 +35,97 @@ pub async fn post_mute(
 
     let conn = pool.get().unwrap();
     let account = sm64js_db::get_account(&conn, query.account_id)?;
+    // overwriting a mute lifts it, which requires the same permission as DELETE Mute
+    if let Some(mute) = sm64js_db::is_account_muted(&conn, account.id)? {
+        if !auth_info.has_permission(&Permission::revoke_mute(mute.expires_at)) {
+            return Err(MuteError::Unauthorized);
+        }
+    }
     let account_info = sm64js_db::get_account_info(&conn, account.id, true).unwrap();
 
     let expires_at = query.expires_in.map(|exp| {
         Utc::now().naive_utc()
             + chrono::Duration::from_std(exp).unwrap_or_else(|_| chrono::Duration::milliseconds(0))
     });
-    sm64js_db::mute_account(&conn, query.reason.clone(), expires_at, account.id)?;
+    conn.transaction::<_, sm64js_db::DbError, _>(|| {
+        sm64js_db::mute_account(&conn, query.reason.clone(), expires_at, account.id)?;
+        sm64js_db::log_moderation_action(
+            &conn,
+            NewModerationAction {
+                target_account_id: Some(account.id),
+                expires_at,
+                reason: query.reason.clone(),
+                ..NewModerationAction::new(
+                    ModerationActionKind::Mute,
+                    ModerationSource::Api,
+                    Some(auth_info.get_account_id()),
+                )
+            },
+        )
+    })?;
 
     actix::spawn(async move {
-        let message = format!(
-            r"reason: {}
-expires_at: {}
-        ",
-            query.reason.clone().unwrap_or_default(),
-            expires_at.map(|exp| exp.to_string()).unwrap_or_default()
-        );
-        let author = sm64js_common::DiscordRichEmbedAuthor {
-            name: format!(
+        sm64js_common::send_discord_moderation_message(
+            format!(
                 "POST Mute player by {}",
                 auth_info.get_discord_username().unwrap_or_default()
             ),
-            url: Some(format!(
-                "{}/api/account?account_id={}",
-                REDIRECT_URI.get().unwrap(),
-                account_info.account.id
-            )),
-            icon_url: Some(if let Some(discord) = &account_info.discord {
-                if let Some(avatar) = &discord.avatar {
-                    format!(
-                        "https://cdn.discordapp.com/avatars/{}/{}.png?size=64",
-                        discord.id, avatar
-                    )
-                } else {
-                    "https://discord.com/assets/2c21aeda16de354ba5334551a883b481.png".to_string()
-                }
-            } else {
-                "https://developers.google.com/identity/images/g-logo.png".to_string()
-            }),
-        };
-        let footer = Some(sm64js_common::DiscordRichEmbedFooter {
-            text: format!("#{}", account_info.account.id),
-        });
-        sm64js_common::send_discord_message(
-            "829813249520042066",
-            None,
-            message,
+            query.reason.clone(),
+            expires_at,
+            account_info,
+        )
+        .await;
+    });
+
+    Ok(NoContent)
+}
+
+/// DELETE Lift mute of player
+#[api_v2_operation(tags(Moderation))]
+pub async fn delete_mute(
+    query: web::Query<DeleteMute>,
+    pool: web::Data<DbPool>,
+    identity: Identity,
+) -> Result<NoContent, MuteError> {
+    let auth_info = identity.get_auth_info();
+
+    let conn = pool.get().unwrap();
+    let mute = sm64js_db::is_account_muted(&conn, query.account_id)?.ok_or(MuteError::NotFound)?;
+    if !auth_info.has_permission(&Permission::revoke_mute(mute.expires_at)) {
+        return Err(MuteError::Unauthorized);
+    }
+
+    let account_info = sm64js_db::get_account_info(&conn, query.account_id, true).unwrap();
+    conn.transaction::<_, sm64js_db::DbError, _>(|| {
+        sm64js_db::revoke_mute(
+            &conn,
+            query.account_id,
+            Some(auth_info.get_account_id()),
+            query.reason.clone(),
+        )?;
+        sm64js_db::log_moderation_action(
+            &conn,
+            NewModerationAction {
+                target_account_id: Some(query.account_id),
+                reason: query.reason.clone(),
+                ..NewModerationAction::new(
+                    ModerationActionKind::Unmute,
+                    ModerationSource::Api,
+                    Some(auth_info.get_account_id()),
+                )
+            },
+        )
+    })?;
+
+    actix::spawn(async move {
+        sm64js_common::send_discord_moderation_message(
+            format!(
+                "DELETE Mute player by {}",
+                auth_info.get_discord_username().unwrap_or_default()
+            ),
+            query.reason.clone(),
             None,
-            author,
-            footer,
+            account_info,
         )
         .await;
     });
@@ -100,17 +143,28 @@ pub struct PostBan {
     /// Parses duration for temp mutes, e.g. "15days". See https://docs.rs/humantime/2.1.0/humantime/index.html
     ///
     /// Keep this empty for a permanent mute.
-    /// Muting will overwrite an already existing mute, so if you want to unmute someone, just set this to "0s"
+    /// Muting will overwrite an already existing mute,
+    /// if you are allowed to lift it
     #[serde(default)]
     #[serde(with = "humantime_serde")]
     expires_in: Option<Duration>,
 }
 
-#[api_v2_errors(code = 400, code = 500)]
+#[skip_serializing_none]
+#[derive(Apiv2Schema, Debug, Deserialize)]
+pub struct DeleteMute {
+    account_id: i32,
+    /// Why the mute is lifted, e.g. because of an appeal
+    reason: Option<String>,
+}
+
+#[api_v2_errors(code = 400, code = 401, code = 404, code = 500)]
 #[derive(Debug, Error)]
 pub enum MuteError {
     #[error("[Unauthorized]")]
     Unauthorized,
+    #[error("[NotFound]: account is not muted")]
+    NotFound,
     #[error("[SendRequest]: {0}")]
     SendRequest(#[from] SendRequestError),
     #[error("[MailboxError]: {0}")]
@@ -123,6 +177,7 @@ impl ResponseError for MuteError {
     fn error_response(&self) -> HttpResponse {
         let res = match self {
             Self::Unauthorized => HttpResponse::new(StatusCode::UNAUTHORIZED),
+            Self::NotFound => HttpResponse::new(StatusCode::NOT_FOUND),
             Self::SendRequest(_) => HttpResponse::new(StatusCode::INTERNAL_SERVER_ERROR),
             Self::Mailbox(_) => HttpResponse::new(StatusCode::INTERNAL_SERVER_ERROR),
             Self::DbError(err) => return err.error_response(),
@@ -8,8 +8,10 @@ use actix_web::{
 use paperclip::actix::{api_v2_errors, api_v2_operation, web, Apiv2Schema, Mountable};
 use serde::Deserialize;
 use sm64js_auth::{Identity, Permission};
-use sm64js_common::PlayerInfo;
+use sm64js_common::{ChatMessage, PlayerInfo};
+use sm64js_db::{DbError, DbPool};
 use sm64js_ws::Sm64JsServer;
+use std::collections::HashMap;
 use thiserror::Error;
 
 pub fn service() -> impl HttpServiceFactory + Mountable {
@@ -24,16 +26,31 @@ async fn get_players(
     query: web::Query<GetPlayers>,
     identity: Identity,
     srv: web::Data<Addr<Sm64JsServer>>,
+    pool: web::Data<DbPool>,
 ) -> Result<web::Json<Vec<PlayerInfo>>, GetPlayerError> {
     let auth_info = identity.get_auth_info();
     if auth_info.has_permission(&Permission::GetPlayerList) {
-        Ok(web::Json(
-            srv.send(sm64js_ws::GetPlayers {
-                include_chat: query.include_chat,
-            })
+        let mut players = srv
+            .send(sm64js_ws::GetPlayers)
             .await
-            .map_err(|e| anyhow!(e))?,
-        ))
+            .map_err(|e| anyhow!(e))?;
+        if let Some(include_chat) = query.include_chat {
+            if include_chat == 0 || include_chat > 1000 {
+                return Err(GetPlayerError::InvalidLimit);
+            }
+            let conn = pool.get().unwrap();
+            let account_ids = players.iter().map(|player| player.account_id).collect();
+            let mut chat: HashMap<i32, Vec<ChatMessage>> = HashMap::new();
+            for msg in sm64js_db::get_recent_chat_messages(&conn, account_ids, include_chat)? {
+                chat.entry(msg.account_id)
+                    .or_default()
+                    .push(msg.into_chat_message(false, false));
+            }
+            for player in players.iter_mut() {
+                player.chat = Some(chat.remove(&player.account_id).unwrap_or_default());
+            }
+        }
+        Ok(web::Json(players))
     } else {
         Err(GetPlayerError::Unauthorized)
     }
@@ -45,20 +62,26 @@ pub struct GetPlayers {
     include_chat: Option<u32>,
 }
 
-#[api_v2_errors(code = 401)]
+#[api_v2_errors(code = 400, code = 401, code = 500)]
 #[derive(Debug, Error)]
 enum GetPlayerError {
     #[error("[Unauthorized]")]
     Unauthorized,
+    #[error("[InvalidLimit]: include_chat must be between 1 and 1000")]
+    InvalidLimit,
     #[error("[Anyhow]: {0}")]
     Anyhow(#[from] anyhow::Error),
+    #[error("[DbError]: {0}")]
+    DbError(#[from] DbError),
 }
 
 impl ResponseError for GetPlayerError {
     fn error_response(&self) -> HttpResponse {
-        let res = match *self {
+        let res = match self {
             Self::Unauthorized => HttpResponse::new(StatusCode::UNAUTHORIZED),
+            Self::InvalidLimit => HttpResponse::new(StatusCode::BAD_REQUEST),
             Self::Anyhow(_) => HttpResponse::new(StatusCode::INTERNAL_SERVER_ERROR),
+            Self::DbError(err) => return err.error_response(),
         };
         res.set_body(Body::from(format!("{}", self)))
     }
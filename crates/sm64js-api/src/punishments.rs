@@ -0,0 +1,65 @@
+use actix_web::{
+    dev::{Body, HttpServiceFactory},
+    http::StatusCode,
+    HttpResponse, ResponseError,
+};
+use paperclip::actix::{api_v2_errors, api_v2_operation, web, Mountable};
+use sm64js_auth::{Identity, Permission};
+use sm64js_common::{GetPunishments, Punishment};
+use sm64js_db::{DbError, DbPool};
+use thiserror::Error;
+
+pub fn service() -> impl HttpServiceFactory + Mountable {
+    web::scope("/punishments").service(web::resource("").route(web::get().to(get_punishments)))
+}
+
+/// GET Active punishments
+///
+/// Returns all bans, IP bans and mutes that have neither expired nor been lifted,
+/// most recent first.
+#[api_v2_operation(tags(Moderation))]
+async fn get_punishments(
+    query: web::Query<GetPunishments>,
+    identity: Identity,
+    pool: web::Data<DbPool>,
+) -> Result<web::Json<Vec<Punishment>>, GetPunishmentsError> {
+    let auth_info = identity.get_auth_info();
+    if !auth_info.has_permission(&Permission::GetPunishments) {
+        return Err(GetPunishmentsError::Unauthorized);
+    }
+    let with_ip = auth_info.has_permission(&Permission::SeeIp);
+    if query.ip.is_some() && !with_ip {
+        return Err(GetPunishmentsError::Unauthorized);
+    }
+    if let Some(limit) = query.limit {
+        if limit == 0 || limit > 1000 {
+            return Err(GetPunishmentsError::InvalidLimit);
+        }
+    }
+
+    let conn = pool.get().unwrap();
+    let punishments = sm64js_db::get_active_punishments(&conn, &query, with_ip)?;
+    Ok(web::Json(punishments))
+}
+
+#[api_v2_errors(code = 400, code = 401, code = 500)]
+#[derive(Debug, Error)]
+enum GetPunishmentsError {
+    #[error("[Unauthorized]")]
+    Unauthorized,
+    #[error("[InvalidLimit]: limit must be between 1 and 1000")]
+    InvalidLimit,
+    #[error("[DbError]: {0}")]
+    DbError(#[from] DbError),
+}
+
+impl ResponseError for GetPunishmentsError {
+    fn error_response(&self) -> HttpResponse {
+        let res = match self {
+            Self::Unauthorized => HttpResponse::new(StatusCode::UNAUTHORIZED),
+            Self::InvalidLimit => HttpResponse::new(StatusCode::BAD_REQUEST),
+            Self::DbError(err) => return err.error_response(),
+        };
+        res.set_body(Body::from(format!("{}", self)))
+    }
+}
@@ -0,0 +1,68 @@
+use actix::prelude::*;
+use actix_http::ResponseError;
+use actix_web::{
+    dev::{Body, HttpServiceFactory},
+    http::StatusCode,
+    HttpResponse,
+};
+use paperclip::actix::{api_v2_errors, api_v2_operation, web, Apiv2Schema, Mountable};
+use serde::Serialize;
+use sm64js_auth::{Identity, Permission};
+use sm64js_ws::{RoomConfigError, Sm64JsServer};
+use thiserror::Error;
+
+pub fn service() -> impl HttpServiceFactory + Mountable {
+    web::scope("/rooms").service(web::resource("/reload").route(web::post().to(post_reload)))
+}
+
+/// POST Reload rooms
+///
+/// Reloads the room config file and applies all changes to the running game.
+/// Players in a removed room will be moved back to the lobby.
+#[api_v2_operation(tags(Moderation))]
+async fn post_reload(
+    identity: Identity,
+    srv: web::Data<Addr<Sm64JsServer>>,
+) -> Result<web::Json<RoomsReloaded>, ReloadRoomsError> {
+    let auth_info = identity.get_auth_info();
+    if !auth_info.has_permission(&Permission::ReloadRooms) {
+        return Err(ReloadRoomsError::Unauthorized);
+    }
+
+    let reloaded = srv.send(sm64js_ws::ReloadRooms).await??;
+
+    Ok(web::Json(RoomsReloaded {
+        added: reloaded.added,
+        updated: reloaded.updated,
+        removed: reloaded.removed,
+    }))
+}
+
+#[derive(Apiv2Schema, Debug, Serialize)]
+pub struct RoomsReloaded {
+    added: Vec<u32>,
+    updated: Vec<u32>,
+    removed: Vec<u32>,
+}
+
+#[api_v2_errors(code = 401, code = 500)]
+#[derive(Debug, Error)]
+enum ReloadRoomsError {
+    #[error("[Unauthorized]")]
+    Unauthorized,
+    #[error("[MailboxError]: {0}")]
+    Mailbox(#[from] MailboxError),
+    #[error("[RoomConfigError]: {0}")]
+    RoomConfig(#[from] RoomConfigError),
+}
+
+impl ResponseError for ReloadRoomsError {
+    fn error_response(&self) -> HttpResponse {
+        let res = match *self {
+            Self::Unauthorized => HttpResponse::new(StatusCode::UNAUTHORIZED),
+            Self::Mailbox(_) => HttpResponse::new(StatusCode::INTERNAL_SERVER_ERROR),
+            Self::RoomConfig(_) => HttpResponse::new(StatusCode::INTERNAL_SERVER_ERROR),
+        };
+        res.set_body(Body::from(format!("{}", self)))
+    }
+}
@@ -0,0 +1,87 @@
+use actix::prelude::*;
+use actix_http::{body::Body, http::StatusCode, ResponseError};
+use actix_web::HttpResponse;
+use paperclip::actix::{api_v2_errors, api_v2_operation, web, Apiv2Schema, NoContent};
+use serde::Deserialize;
+use serde_with::skip_serializing_none;
+use sm64js_auth::{Identity, Permission};
+use sm64js_db::DbPool;
+use sm64js_ws::{Sm64JsServer, WarnAccount};
+use thiserror::Error;
+
+/// POST Warn player
+///
+/// The warning is shown to the player, if they are online.
+/// Too many warnings lead to an automatic mute or ban as configured in the chat filters.
+#[api_v2_operation(tags(Moderation))]
+pub async fn post_warn(
+    query: web::Query<PostWarn>,
+    pool: web::Data<DbPool>,
+    identity: Identity,
+    srv: web::Data<Addr<Sm64JsServer>>,
+) -> Result<NoContent, WarnError> {
+    let auth_info = identity.get_auth_info();
+    if !auth_info.has_permission(&Permission::WarnAccount) {
+        return Err(WarnError::Unauthorized);
+    }
+
+    let conn = pool.get().unwrap();
+    let account = sm64js_db::get_account(&conn, query.account_id)?;
+    let account_info = sm64js_db::get_account_info(&conn, account.id, true).unwrap();
+
+    srv.send(WarnAccount {
+        account_id: account.id,
+        reason: query.reason.clone(),
+        issued_by: auth_info.get_account_id(),
+    })
+    .await??;
+
+    actix::spawn(async move {
+        sm64js_common::send_discord_moderation_message(
+            format!(
+                "POST Warn player by {}",
+                auth_info.get_discord_username().unwrap_or_default()
+            ),
+            query.reason.clone(),
+            None,
+            account_info,
+        )
+        .await;
+    });
+
+    Ok(NoContent)
+}
+
+#[skip_serializing_none]
+#[derive(Apiv2Schema, Debug, Deserialize)]
+pub struct PostWarn {
+    /// You can either get the `account_id` from Discord's #in-game-chat
+    /// or from the <a href="#get-/api/players">player list</a>
+    account_id: i32,
+    reason: Option<String>,
+}
+
+#[api_v2_errors(code = 400, code = 401, code = 500)]
+#[derive(Debug, Error)]
+pub enum WarnError {
+    #[error("[Unauthorized]")]
+    Unauthorized,
+    #[error("[MailboxError]: {0}")]
+    Mailbox(#[from] MailboxError),
+    #[error("[Anyhow]: {0}")]
+    Anyhow(#[from] anyhow::Error),
+    #[error("[DbError]: {0}")]
+    DbError(#[from] sm64js_db::DbError),
+}
+
+impl ResponseError for WarnError {
+    fn error_response(&self) -> HttpResponse {
+        let res = match self {
+            Self::Unauthorized => HttpResponse::new(StatusCode::UNAUTHORIZED),
+            Self::Mailbox(_) => HttpResponse::new(StatusCode::INTERNAL_SERVER_ERROR),
+            Self::Anyhow(_) => HttpResponse::new(StatusCode::INTERNAL_SERVER_ERROR),
+            Self::DbError(err) => return err.error_response(),
+        };
+        res.set_body(Body::from(format!("{}", self)))
+    }
+}
@@ -10,7 +10,7 @@ mod identity;
 use std::collections::{HashMap, HashSet};
 
 pub use auth::Auth;
-use chrono::Duration;
+use chrono::{Duration, NaiveDateTime, Utc};
 pub use identity::Identity;
 
 #[derive(Clone, Debug)]
@@ -77,14 +77,24 @@ impl AuthInfo {
 pub enum Permission {
     GetAccount,
     GetAccountExt,
+    GetFlaggedAccounts,
+    GetMetrics,
+    GetModerationLog,
     GetPlayerList,
+    GetPunishments,
+    JoinDuringMaintenance,
+    KickPlayer,
     PermBanAccount,
     PermMuteAccount,
     ReadChatLog,
+    ReloadChatFilters,
+    ReloadRooms,
     SeeIp,
     SendAnnouncement,
+    SetMaintenanceMode,
     TempBanAccount(Duration),
     TempMuteAccount(Duration),
+    WarnAccount,
 }
 
 impl PartialEq for Permission {
@@ -93,19 +103,47 @@ impl PartialEq for Permission {
             (self, other),
             (Self::GetAccount, Self::GetAccount)
                 | (Self::GetAccountExt, Self::GetAccountExt)
+                | (Self::GetFlaggedAccounts, Self::GetFlaggedAccounts)
+                | (Self::GetMetrics, Self::GetMetrics)
+                | (Self::GetModerationLog, Self::GetModerationLog)
                 | (Self::GetPlayerList, Self::GetPlayerList)
+                | (Self::GetPunishments, Self::GetPunishments)
+                | (Self::JoinDuringMaintenance, Self::JoinDuringMaintenance)
+                | (Self::KickPlayer, Self::KickPlayer)
                 | (Self::PermBanAccount, Self::PermBanAccount)
                 | (Self::PermMuteAccount, Self::PermMuteAccount)
                 | (Self::ReadChatLog, Self::ReadChatLog)
+                | (Self::ReloadChatFilters, Self::ReloadChatFilters)
+                | (Self::ReloadRooms, Self::ReloadRooms)
                 | (Self::SeeIp, Self::SeeIp)
                 | (Self::SendAnnouncement, Self::SendAnnouncement)
+                | (Self::SetMaintenanceMode, Self::SetMaintenanceMode)
                 | (Self::TempBanAccount(_), Self::TempBanAccount(_))
                 | (Self::TempMuteAccount(_), Self::TempMuteAccount(_))
+                | (Self::WarnAccount, Self::WarnAccount)
         )
     }
 }
 
 impl Permission {
+    /// Permission that is required to lift a ban, which would last until `expires_at`
+    /// or forever, if `None`
+    pub fn revoke_ban(expires_at: Option<NaiveDateTime>) -> Self {
+        match expires_at {
+            Some(expires_at) => Self::TempBanAccount(expires_at - Utc::now().naive_utc()),
+            None => Self::PermBanAccount,
+        }
+    }
+
+    /// Permission that is required to lift a mute, which would last until `expires_at`
+    /// or forever, if `None`
+    pub fn revoke_mute(expires_at: Option<NaiveDateTime>) -> Self {
+        match expires_at {
+            Some(expires_at) => Self::TempMuteAccount(expires_at - Utc::now().naive_utc()),
+            None => Self::PermMuteAccount,
+        }
+    }
+
     fn role_has_permission(&self, role: &str) -> bool {
         ROLES_WITH_PERMISSIONS
             .get(role)
@@ -125,33 +163,52 @@ lazy_static! {
             vec![
                 Permission::GetAccount,
                 Permission::GetAccountExt,
+                Permission::GetFlaggedAccounts,
+                Permission::GetMetrics,
+                Permission::GetModerationLog,
                 Permission::GetPlayerList,
+                Permission::GetPunishments,
+                Permission::JoinDuringMaintenance,
+                Permission::KickPlayer,
                 Permission::PermBanAccount,
                 Permission::PermMuteAccount,
                 Permission::ReadChatLog,
+                Permission::ReloadChatFilters,
+                Permission::ReloadRooms,
                 Permission::SeeIp,
                 Permission::SendAnnouncement,
+                Permission::SetMaintenanceMode,
                 Permission::TempBanAccount(Duration::weeks(1000)),
                 Permission::TempMuteAccount(Duration::weeks(1000)),
+                Permission::WarnAccount,
             ],
         "780937094473318420" => // In-game Chat Moderator
             vec![
                 Permission::GetAccount,
+                Permission::GetFlaggedAccounts,
+                Permission::GetModerationLog,
                 Permission::GetPlayerList,
+                Permission::GetPunishments,
+                Permission::JoinDuringMaintenance,
+                Permission::KickPlayer,
                 Permission::PermBanAccount,
                 Permission::PermMuteAccount,
                 Permission::ReadChatLog,
                 Permission::SendAnnouncement,
                 Permission::TempBanAccount(Duration::weeks(1000)),
                 Permission::TempMuteAccount(Duration::weeks(1000)),
+                Permission::WarnAccount,
             ],
         "801876964892868659" => // Trial mod
             vec![
                 Permission::GetAccount,
                 Permission::GetPlayerList,
+                Permission::GetPunishments,
+                Permission::KickPlayer,
                 Permission::ReadChatLog,
                 Permission::TempBanAccount(Duration::days(2)),
                 Permission::TempMuteAccount(Duration::days(7)),
+                Permission::WarnAccount,
             ]
     };
 
@@ -1,12 +1,18 @@
-use crate::AccountInfo;
-use chrono::{DateTime, Duration, NaiveDateTime, Utc};
-use indexmap::IndexMap;
+use crate::{
+    chat_filter::{normalize, ChatAction, ChatFilter, ChatFiltersConfig, FilterInput},
+    AccountInfo,
+};
+use chrono::{DateTime, NaiveDateTime, Utc};
 use paperclip::actix::{web, Apiv2Schema};
 use parking_lot::RwLock;
 use rustrict::CensorStr;
 use serde::{Deserialize, Serialize};
 use serde_with::skip_serializing_none;
-use sm64js_env::REDIRECT_URI;
+use sm64js_env::CHAT_CACHE_SIZE;
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt,
+};
 
 #[derive(Apiv2Schema, Debug, Default, Deserialize)]
 pub struct GetChat {
@@ -22,24 +28,99 @@ pub struct GetChat {
         default = "crate::date_format::empty"
     )]
     pub to: Option<DateTime<Utc>>,
+    /// Number of messages per page. Defaults to 100, maximum is 1000
     pub limit: Option<u32>,
+    /// Cursor to the next page, as returned by the previous request
+    pub cursor: Option<String>,
     pub player_name: Option<String>,
     pub discord_id: Option<String>,
     pub google_id: Option<String>,
+    pub account_id: Option<i32>,
+    pub level: Option<u32>,
+    pub room_id: Option<u32>,
+    pub is_censored: Option<bool>,
+    pub is_spam: Option<bool>,
+    pub is_screaming: Option<bool>,
+    /// Requires the `SeeIp` permission
+    pub ip: Option<String>,
+    /// Case insensitive substring search
+    pub search: Option<String>,
+    /// PostgreSQL regular expression, case sensitive
+    pub regex: Option<String>,
 }
 
-pub type ChatHistoryData = web::Data<RwLock<ChatHistory>>;
+/// Position of a message in the chat history.
+///
+/// Messages are ordered by date and id, so that the cursor stays stable while new messages
+/// are added.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ChatCursor {
+    pub date_time: NaiveDateTime,
+    pub id: i32,
+}
 
-#[derive(Debug)]
-pub struct ChatHistory(IndexMap<DateTime<Utc>, ChatMessage>);
+impl ChatCursor {
+    pub fn parse(cursor: &str) -> Option<Self> {
+        let (nanos, id) = cursor.split_once('_')?;
+        let nanos: i64 = nanos.parse().ok()?;
+        let date_time = NaiveDateTime::from_timestamp_opt(
+            nanos.div_euclid(1_000_000_000),
+            nanos.rem_euclid(1_000_000_000) as u32,
+        )?;
+        Some(ChatCursor {
+            date_time,
+            id: id.parse().ok()?,
+        })
+    }
+}
 
-impl Default for ChatHistory {
-    fn default() -> Self {
-        Self(IndexMap::new())
+impl fmt::Display for ChatCursor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}_{}", self.date_time.timestamp_nanos(), self.id)
     }
 }
 
-const ALLOWED_CHARACTERS: &str = r#"
+/// Room in which a chat message has been sent
+#[derive(Clone, Debug)]
+pub struct ChatRoom {
+    pub id: u32,
+    pub level: u32,
+    pub name: String,
+}
+
+#[derive(Apiv2Schema, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChatPage {
+    pub messages: Vec<ChatMessage>,
+    /// Cursor to the page with older messages, if there are any
+    pub next_cursor: Option<String>,
+}
+
+pub type ChatHistoryData = web::Data<RwLock<ChatHistory>>;
+
+/// Maximum number of characters per chat message. Longer messages are truncated
+const MAX_MESSAGE_LEN: usize = 500;
+
+/// Maximum number of messages that wait to be written to the database.
+/// If the database is unreachable for too long, the oldest messages are dropped
+const MAX_PENDING_MESSAGES: usize = 10_000;
+
+/// Recent chat messages.
+///
+/// Only the messages within the longest window of the configured rate filters are kept
+/// in memory for spam detection.
+/// All messages are queued to be written to the database.
+#[derive(Debug, Default)]
+pub struct ChatHistory {
+    recent: VecDeque<ChatMessage>,
+    pending: Vec<ChatMessage>,
+    filters: ChatFiltersConfig,
+    /// Dates until which an account is not warned again by a filter,
+    /// keyed by account id and filter index
+    warning_cooldowns: HashMap<(i32, usize), NaiveDateTime>,
+}
+
+pub(crate) const ALLOWED_CHARACTERS: &str = r#"
 abcdefghijklmnopoqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789 ?!@#$%^&*(){}[];:'"\|/,.<>-_=+`
 😂🤣🤔🤨🙄😭😎🥶😤👍👎💀🗿🔥🎄🎃🔺🔻🤡🎪🎶🎵
 "#;
@@ -51,171 +132,197 @@ pub fn sanitize_chat(s: &str) -> String {
 }
 
 impl ChatHistory {
+    pub fn new(filters: ChatFiltersConfig) -> Self {
+        ChatHistory {
+            filters,
+            ..Default::default()
+        }
+    }
+
+    /// Replaces the filter chain. Messages that are already in the cache are kept,
+    /// warning cooldowns are reset, because they refer to the filters by index.
+    pub fn set_filters(&mut self, filters: ChatFiltersConfig) {
+        self.filters = filters;
+        self.warning_cooldowns.clear();
+    }
+
+    pub fn get_filters(&self) -> &ChatFiltersConfig {
+        &self.filters
+    }
+
+    /// Takes all messages that have not been written to the database yet.
+    pub fn take_pending(&mut self) -> Vec<ChatMessage> {
+        std::mem::take(&mut self.pending)
+    }
+
+    /// Queues messages again that could not be written to the database.
+    pub fn requeue(&mut self, mut messages: Vec<ChatMessage>) {
+        messages.append(&mut self.pending);
+        let overflow = messages.len().saturating_sub(MAX_PENDING_MESSAGES);
+        if overflow > 0 {
+            eprintln!(
+                "Dropping {} chat messages that could not be saved",
+                overflow
+            );
+            messages.drain(..overflow);
+        }
+        self.pending = messages;
+    }
+
     pub fn add_message(
         &mut self,
         message: &str,
         account_info: AccountInfo,
         player_name: String,
-        level_name: String,
+        room: Option<ChatRoom>,
         ip: String,
     ) -> ChatResult {
+        // the filters compare messages with each other, so their length has to be bounded
+        let message = match message.char_indices().nth(MAX_MESSAGE_LEN) {
+            Some((end, _)) => &message[..end],
+            None => message,
+        };
         let escaped_message = sanitize_chat(message);
         let is_escaped = escaped_message != message;
         let censored_message = escaped_message.censor();
         let is_censored = censored_message != escaped_message;
 
-        let date = Utc::now() - Duration::seconds(15);
+        let now = Utc::now();
+        self.evict(now);
+
         let account_id = account_info.account.id;
-        let is_spam = self
-            .0
+        let normalized = normalize(message);
+        let matched = self.filters.check(&FilterInput {
+            message,
+            normalized: &normalized,
+            account_id,
+            now: now.naive_utc(),
+            recent: &self.recent,
+        });
+        let is_spam = matched
             .iter()
-            .skip_while(|(k, _)| *k < &date)
-            .filter(|(k, v)| {
-                v.account_id == account_id && !self.0.get(*k).unwrap().is_spam.unwrap_or_default()
-            })
-            .count()
-            >= 3;
-
-        let date = Utc::now() - Duration::seconds(60);
-        let is_excessive_spam = self
-            .0
+            .any(|(_, config)| matches!(config.filter, ChatFilter::Spam { .. }));
+        let is_excessive_spam = matched
             .iter()
-            .skip_while(|(k, _)| *k < &date)
-            .filter(|(_, v)| v.account_id == account_id)
-            .count()
-            >= 30;
-
-        let is_screaming = if message.len() > 5 {
-            let alphabetic_count = message.chars().filter(|c| c.is_ascii_alphabetic()).count();
-            let screaming_count = message
-                .chars()
-                .filter(|c| c.is_ascii_alphabetic() && c.is_ascii_uppercase())
-                .count();
-            (screaming_count as f32 / alphabetic_count as f32) > 0.7
-        } else {
-            false
+            .any(|(_, config)| matches!(config.filter, ChatFilter::ExcessiveSpam { .. }));
+        let is_screaming = matched
+            .iter()
+            .any(|(_, config)| matches!(config.filter, ChatFilter::Screaming { .. }));
+        let warn = match matched.first() {
+            Some((i, config)) if config.warn => {
+                let key = (account_id, *i);
+                let is_cooling_down = self
+                    .warning_cooldowns
+                    .get(&key)
+                    .map(|until| *until > now.naive_utc())
+                    .unwrap_or_default();
+                if !is_cooling_down {
+                    self.warning_cooldowns
+                        .insert(key, now.naive_utc() + config.filter.warning_cooldown());
+                }
+                !is_cooling_down
+            }
+            _ => false,
         };
+        let filtered = matched.first().map(|(_, config)| {
+            let error = config.filter.get_error();
+            FilteredChat {
+                error,
+                action: config.action,
+                reply: config
+                    .reply
+                    .clone()
+                    .unwrap_or_else(|| error.get_default_reply(config.action)),
+                message: censored_message.clone(),
+                warn,
+            }
+        });
 
-        let now = Utc::now();
         let discord_id = account_info.discord.clone().map(|d| d.id);
 
-        self.0.insert(
-            now,
-            ChatMessage {
-                message: message.to_string(),
-                timestamp: now.timestamp(),
-                date_time: now.naive_utc(),
-                player_name: Some(player_name.clone()),
-                account_id,
-                discord_id,
-                google_id: account_info.google.clone().map(|d| d.sub),
-                ip: Some(ip),
-                is_escaped: if is_escaped { Some(is_escaped) } else { None },
-                is_censored: if is_censored { Some(is_censored) } else { None },
-                is_spam: if is_spam { Some(is_spam) } else { None },
-                is_excessive_spam: if is_excessive_spam {
-                    Some(is_excessive_spam)
-                } else {
-                    None
-                },
-                is_screaming: if is_screaming {
-                    Some(is_screaming)
-                } else {
-                    None
-                },
+        let chat_message = ChatMessage {
+            message: message.to_string(),
+            timestamp: now.timestamp(),
+            date_time: now.naive_utc(),
+            player_name: Some(player_name.clone()),
+            account_id,
+            discord_id,
+            google_id: account_info.google.clone().map(|d| d.sub),
+            ip: Some(ip),
+            level: room.as_ref().map(|room| room.level),
+            room_id: room.as_ref().map(|room| room.id),
+            room_name: room.as_ref().map(|room| room.name.clone()),
+            is_escaped: if is_escaped { Some(is_escaped) } else { None },
+            is_censored: if is_censored { Some(is_censored) } else { None },
+            is_spam: if is_spam { Some(is_spam) } else { None },
+            is_excessive_spam: if is_excessive_spam {
+                Some(is_excessive_spam)
+            } else {
+                None
             },
-        );
-
-        if is_excessive_spam {
-            return ChatResult::Err(ChatError::ExcessiveSpam);
-        } else if is_spam {
-            return ChatResult::Err(ChatError::Spam);
-        } else if is_screaming {
-            return ChatResult::Err(ChatError::Screaming);
+            is_screaming: if is_screaming {
+                Some(is_screaming)
+            } else {
+                None
+            },
+            normalized,
+        };
+        self.recent.push_back(chat_message.clone());
+        self.pending.push(chat_message);
+        if self.pending.len() > MAX_PENDING_MESSAGES {
+            self.requeue(vec![]);
         }
 
-        let message = message.to_string();
-        if !is_spam && !message.is_empty() {
+        let filtered = match filtered {
+            Some(filtered) if filtered.action == ChatAction::Warn => Some(filtered),
+            Some(filtered) => {
+                if filtered.action == ChatAction::Escalate {
+                    let error = filtered.error;
+                    let message = message.to_string();
+                    actix::spawn(async move {
+                        Self::send_discord_escalation(error, message, player_name, account_info)
+                            .await;
+                    });
+                }
+                return ChatResult::Filtered(filtered);
+            }
+            None => None,
+        };
+
+        if !message.is_empty() {
             let censored_message = censored_message.clone();
             actix::spawn(async move {
                 Self::send_discord_chat_message(
                     censored_message,
                     player_name,
-                    level_name,
+                    room.map(|room| room.name)
+                        .unwrap_or_else(|| "Lobby".to_string()),
                     account_info,
                 )
                 .await;
             });
         }
 
-        ChatResult::Ok((censored_message, is_spam))
+        if let Some(filtered) = filtered {
+            ChatResult::Filtered(filtered)
+        } else {
+            ChatResult::Ok(censored_message)
+        }
     }
 
-    pub fn get_messages(
-        &self,
-        query: GetChat,
-        with_player_info: bool,
-        with_ip: bool,
-    ) -> Vec<ChatMessage> {
-        let max_messages = query.limit.unwrap_or(100) as usize;
-        let mut res: Vec<ChatMessage> = vec![];
-        let mut reached = false;
-        let mut keys = self.0.keys().clone();
-        while let Some(key) = keys.next_back() {
-            if !reached {
-                if let Some(to) = query.to {
-                    if *key >= to {
-                        continue;
-                    }
-                } else {
-                    reached = true
-                }
-            }
-            if reached {
-                if let Some(from) = query.from {
-                    if *key <= from {
-                        break;
-                    }
-                }
-            }
-            let mut msg = self.0.get(key).unwrap().clone();
-            if let Some(player_name) = &query.player_name {
-                if msg.player_name.as_ref() != Some(player_name) {
-                    continue;
-                }
-            }
-            if let (Some(_), Some(_)) = (&query.discord_id, &query.google_id) {
-                //// a query should never have a discord id and a google id.
-                //// throw an error here maybe
-                continue;
-            }
-            if let Some(id1) = &query.discord_id {
-                match &msg.discord_id {
-                    Some(id2) if id1 == id2 => {}
-                    _ => continue,
-                };
-            }
-            if let Some(id1) = &query.google_id {
-                match &msg.google_id {
-                    Some(id2) if id1 == id2 => {}
-                    _ => continue,
-                };
-            }
-            if !with_player_info {
-                msg.player_name = None;
-                msg.discord_id = None;
-                msg.google_id = None;
-            }
-            if !with_ip {
-                msg.ip = None;
-            }
-            res.push(msg);
-            if res.len() >= max_messages {
+    /// Removes all messages that are too old for spam detection and expired warning cooldowns.
+    /// The cache never holds more than `CHAT_CACHE_SIZE` messages.
+    fn evict(&mut self, now: DateTime<Utc>) {
+        self.warning_cooldowns
+            .retain(|_, until| *until > now.naive_utc());
+        let date = (now - self.filters.max_window()).naive_utc();
+        let cache_size = *CHAT_CACHE_SIZE.get().unwrap();
+        while let Some(msg) = self.recent.front() {
+            if msg.date_time >= date && self.recent.len() < cache_size {
                 break;
             }
+            self.recent.pop_front();
         }
-        res.reverse();
-        res
     }
 
     async fn send_discord_chat_message(
@@ -224,26 +331,7 @@ impl ChatHistory {
         level_name: String,
         account_info: AccountInfo,
     ) {
-        let author = super::DiscordRichEmbedAuthor {
-            name: player_name,
-            url: Some(format!(
-                "{}/api/account?account_id={}",
-                REDIRECT_URI.get().unwrap(),
-                account_info.account.id
-            )),
-            icon_url: Some(if let Some(discord) = account_info.discord {
-                if let Some(avatar) = discord.avatar {
-                    format!(
-                        "https://cdn.discordapp.com/avatars/{}/{}.png?size=64",
-                        discord.id, avatar
-                    )
-                } else {
-                    "https://discord.com/assets/2c21aeda16de354ba5334551a883b481.png".to_string()
-                }
-            } else {
-                "https://developers.google.com/identity/images/g-logo.png".to_string()
-            }),
-        };
+        let author = super::get_discord_author(player_name, &account_info);
         let footer = Some(super::DiscordRichEmbedFooter {
             text: format!("#{} - {}", account_info.account.id, level_name),
         });
@@ -254,35 +342,158 @@ impl ChatHistory {
                 .await;
         }
     }
+
+    async fn send_discord_escalation(
+        error: ChatError,
+        message: String,
+        player_name: String,
+        account_info: AccountInfo,
+    ) {
+        let author = super::get_discord_author(player_name, &account_info);
+        let footer = Some(super::DiscordRichEmbedFooter {
+            text: format!("#{}", account_info.account.id),
+        });
+        let message = format!(
+            "**Chat message escalated due to {}:**\n{}",
+            error.get_reason(),
+            message.replace('*', r"\*").replace('_', r"\_")
+        );
+        super::send_discord_message(
+            super::DISCORD_MODERATION_CHANNEL_ID,
+            None,
+            message,
+            None,
+            author,
+            footer,
+        )
+        .await;
+    }
 }
 
 #[skip_serializing_none]
 #[derive(Apiv2Schema, Clone, Debug, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ChatMessage {
-    message: String,
-    timestamp: i64,
-    date_time: NaiveDateTime,
-    player_name: Option<String>,
-    account_id: i32,
-    discord_id: Option<String>,
-    google_id: Option<String>,
-    ip: Option<String>,
-    is_escaped: Option<bool>,
-    is_censored: Option<bool>,
-    is_spam: Option<bool>,
-    is_excessive_spam: Option<bool>,
-    is_screaming: Option<bool>,
+    pub message: String,
+    pub timestamp: i64,
+    pub date_time: NaiveDateTime,
+    pub player_name: Option<String>,
+    pub account_id: i32,
+    pub discord_id: Option<String>,
+    pub google_id: Option<String>,
+    pub ip: Option<String>,
+    pub level: Option<u32>,
+    pub room_id: Option<u32>,
+    pub room_name: Option<String>,
+    pub is_escaped: Option<bool>,
+    pub is_censored: Option<bool>,
+    pub is_spam: Option<bool>,
+    pub is_excessive_spam: Option<bool>,
+    pub is_screaming: Option<bool>,
+    /// Message as compared by the similarity filters.
+    /// Only set for messages that have been sent since the server started
+    #[serde(skip)]
+    pub normalized: String,
 }
 
 pub enum ChatResult {
-    Ok((String, bool)),
-    Err(ChatError),
+    Ok(String),
+    /// The message matched a filter.
+    /// It has only been delivered, if the action is [`ChatAction::Warn`]
+    Filtered(FilteredChat),
     NotFound,
 }
 
+pub struct FilteredChat {
+    pub error: ChatError,
+    pub action: ChatAction,
+    /// Reply to the sender
+    pub reply: String,
+    /// Censored message
+    pub message: String,
+    /// Whether a warning has to be recorded against the sender
+    pub warn: bool,
+}
+
+#[derive(Clone, Copy, Debug)]
 pub enum ChatError {
     Spam,
     ExcessiveSpam,
+    Repeated,
+    Raid,
     Screaming,
+    Link,
+    BlockedWord,
+    InvalidCharacters,
+}
+
+impl ChatError {
+    pub fn get_reason(&self) -> &'static str {
+        match self {
+            Self::Spam => "sending too many messages",
+            Self::ExcessiveSpam => "sending way too many messages",
+            Self::Repeated => "repeating the same message",
+            Self::Raid => "taking part in a chat raid",
+            Self::Screaming => "screaming",
+            Self::Link => "sending links",
+            Self::BlockedWord => "using blocked words",
+            Self::InvalidCharacters => "using invalid characters",
+        }
+    }
+
+    pub fn get_default_reply(&self, action: ChatAction) -> String {
+        let description = match self {
+            Self::Spam => "You have to wait longer between sending chat messages",
+            Self::ExcessiveSpam => "You are sending way too many messages",
+            Self::Repeated => "Please do not repeat the same message",
+            Self::Raid => "This message has already been sent by too many players",
+            Self::Screaming => "COULD YOU PLEASE STOP SCREAMING?",
+            Self::Link => "Links are not allowed",
+            Self::BlockedWord => "Your message contains a blocked word",
+            Self::InvalidCharacters => "Your message contains invalid characters",
+        };
+        match (action, self) {
+            (ChatAction::Mute { minutes }, _) => format!(
+                "You have been muted for {}min due to {}",
+                minutes,
+                self.get_reason()
+            ),
+            (ChatAction::Warn, _) | (_, Self::Screaming) => description.to_string(),
+            _ => format!("Chat message ignored: {}", description),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chat_cursor_round_trip() {
+        let cursor = ChatCursor {
+            date_time: NaiveDateTime::from_timestamp(1_643_000_000, 123_456_789),
+            id: 42,
+        };
+        assert_eq!(cursor.to_string(), "1643000000123456789_42");
+        assert_eq!(ChatCursor::parse(&cursor.to_string()), Some(cursor));
+    }
+
+    #[test]
+    fn chat_cursor_before_epoch() {
+        let cursor = ChatCursor {
+            date_time: NaiveDateTime::from_timestamp(-2, 500_000_000),
+            id: 1,
+        };
+        assert_eq!(cursor.to_string(), "-1500000000_1");
+        assert_eq!(ChatCursor::parse(&cursor.to_string()), Some(cursor));
+    }
+
+    #[test]
+    fn chat_cursor_rejects_invalid_cursors() {
+        for cursor in [
+            "", "_", "123", "123_", "_42", "abc_42", "123_abc", "123_4_2", "1.5_42",
+        ] {
+            assert_eq!(ChatCursor::parse(cursor), None, "{}", cursor);
+        }
+    }
 }
@@ -0,0 +1,693 @@
+use crate::{chat::ALLOWED_CHARACTERS, ChatError, ChatMessage};
+use chrono::{Duration, NaiveDateTime};
+use parking_lot::{const_mutex, Mutex};
+use rustrict::{Censor, Trie, Type};
+use serde::Deserialize;
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt,
+    fs::read_to_string,
+    io,
+    path::Path,
+};
+use thiserror::Error;
+
+/// Top level domains that are detected as links, if they are not preceded by a protocol
+const LINK_TLDS: &[&str] = &[
+    "com", "net", "org", "gg", "io", "ly", "tv", "me", "xyz", "ru", "de", "co", "info", "link",
+];
+
+/// Time span in which filters without a window warn an account at most once
+const WARNING_COOLDOWN_SECS: i64 = 60;
+
+/// Chain of filters that every chat message has to pass.
+///
+/// Filters are checked in order and the first filter that matches decides the action.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct ChatFiltersConfig {
+    pub filters: Vec<ChatFilterConfig>,
+    /// Automatic punishments for accounts that collected too many warnings,
+    /// either from moderators or from filters with `warn` enabled
+    #[serde(default)]
+    pub escalation: Vec<EscalationStep>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ChatFilterConfig {
+    pub filter: ChatFilter,
+    pub action: ChatAction,
+    /// Reply to the sender. A default reply depending on the filter is used, if not set
+    #[serde(default)]
+    pub reply: Option<String>,
+    /// Additionally records a warning against the sender, which counts towards the escalation.
+    /// An account is warned at most once within the `window_secs` of the filter,
+    /// so that a single burst of messages does not escalate
+    #[serde(default)]
+    pub warn: bool,
+}
+
+/// Punishes an account, when it received at least `warnings` warnings within `window_hours`
+#[derive(Clone, Debug, Deserialize)]
+pub struct EscalationStep {
+    pub warnings: usize,
+    pub window_hours: u32,
+    pub punishment: EscalationPunishment,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub enum EscalationPunishment {
+    Mute { minutes: u32 },
+    Ban { minutes: u32 },
+}
+
+impl EscalationPunishment {
+    /// Bans are more severe than mutes, longer punishments are more severe than shorter ones
+    fn severity(&self) -> (bool, u32) {
+        match *self {
+            Self::Mute { minutes } => (false, minutes),
+            Self::Ban { minutes } => (true, minutes),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub enum ChatFilter {
+    /// More than `max_messages` within `window_secs`.
+    /// Messages that were already detected as spam are not counted
+    Spam {
+        max_messages: usize,
+        window_secs: u32,
+    },
+    /// More than `max_messages` within `window_secs`
+    ExcessiveSpam {
+        max_messages: usize,
+        window_secs: u32,
+    },
+    /// The same or a similar message has been sent at least `max_repeats` times within
+    /// `window_secs`
+    Repeated {
+        max_repeats: usize,
+        window_secs: u32,
+        /// Edit distance relative to the length of the longer message,
+        /// up to which messages are considered similar. Only identical messages match, if 0
+        #[serde(default)]
+        max_distance_ratio: f32,
+    },
+    /// At least `min_accounts` accounts sent the same or a similar message within `window_secs`.
+    /// Only messages longer than `min_len` are checked
+    Raid {
+        min_accounts: usize,
+        window_secs: u32,
+        #[serde(default)]
+        min_len: usize,
+        #[serde(default)]
+        max_distance_ratio: f32,
+    },
+    /// Ratio of uppercase letters exceeds `ratio`.
+    /// Only messages longer than `min_len` are checked
+    Screaming { min_len: usize, ratio: f32 },
+    /// Urls and domain names
+    Link,
+    /// Case insensitive words or phrases, in addition to the profanity filter that censors messages.
+    /// Words are detected like profanity, so they can't be evaded by spacing or look-alike characters
+    Words {
+        words: Vec<String>,
+        #[serde(skip)]
+        trie: WordTrie,
+    },
+    /// Characters that are not in the whitelist.
+    /// Defaults to all characters that can be rendered by the client, if `allowed` is `None`
+    Characters { allowed: Option<String> },
+}
+
+/// Custom profanity trie, that only contains the words of a `ChatFilter::Words`.
+///
+/// A `Censor` only accepts static tries, so the trie is leaked whenever the chat filter config
+/// is loaded or reloaded. Tries are cached by their word list, so only reloads that change
+/// the words leak memory.
+#[derive(Clone, Copy, Default)]
+pub struct WordTrie(Option<&'static Trie>);
+
+/// Leaked tries by their sorted, lowercase words
+static WORD_TRIES: Mutex<Option<HashMap<Vec<String>, &'static Trie>>> = const_mutex(None);
+
+impl WordTrie {
+    fn new(words: &[String]) -> Self {
+        let mut words: Vec<_> = words.iter().map(|word| word.to_lowercase()).collect();
+        words.sort_unstable();
+        words.dedup();
+
+        let mut tries = WORD_TRIES.lock();
+        let trie = tries
+            .get_or_insert_with(HashMap::new)
+            .entry(words)
+            .or_insert_with_key(|words| {
+                let mut trie = Trie::default();
+                for word in words {
+                    trie.set(word, Type::PROFANE & Type::SEVERE);
+                }
+                Box::leak(Box::new(trie))
+            });
+        WordTrie(Some(trie))
+    }
+}
+
+impl fmt::Debug for WordTrie {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("WordTrie")
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+pub enum ChatAction {
+    /// Deliver the message and warn the sender
+    Warn,
+    /// Do not deliver the message and tell the sender why
+    Drop,
+    /// Do not deliver the message, but let the sender believe it has been sent
+    ShadowDrop,
+    /// Do not deliver the message and mute the sender
+    Mute { minutes: u32 },
+    /// Do not deliver the message and report it to the moderators
+    Escalate,
+}
+
+/// A message that has been sent by an account
+pub(crate) struct FilterInput<'a> {
+    pub message: &'a str,
+    /// See [`normalize`]
+    pub normalized: &'a str,
+    pub account_id: i32,
+    pub now: NaiveDateTime,
+    /// Recent messages of all accounts in chronological order
+    pub recent: &'a VecDeque<ChatMessage>,
+}
+
+impl ChatFiltersConfig {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, ChatFilterConfigError> {
+        let path = path.as_ref();
+        let config = read_to_string(path).map_err(|err| ChatFilterConfigError::Io {
+            path: path.display().to_string(),
+            source: err,
+        })?;
+        let mut config: Self = ron::from_str(&config)?;
+        config.validate()?;
+        for config in config.filters.iter_mut() {
+            if let ChatFilter::Words { words, trie } = &mut config.filter {
+                *trie = WordTrie::new(words);
+            }
+        }
+
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<(), ChatFilterConfigError> {
+        for (i, config) in self.filters.iter().enumerate() {
+            match &config.filter {
+                ChatFilter::Spam {
+                    max_messages,
+                    window_secs,
+                }
+                | ChatFilter::ExcessiveSpam {
+                    max_messages,
+                    window_secs,
+                } => {
+                    if *max_messages == 0 || *window_secs == 0 {
+                        return Err(ChatFilterConfigError::InvalidRate(i));
+                    }
+                }
+                ChatFilter::Repeated {
+                    max_repeats: max_messages,
+                    window_secs,
+                    max_distance_ratio,
+                }
+                | ChatFilter::Raid {
+                    min_accounts: max_messages,
+                    window_secs,
+                    max_distance_ratio,
+                    ..
+                } => {
+                    if *max_messages == 0 || *window_secs == 0 {
+                        return Err(ChatFilterConfigError::InvalidRate(i));
+                    }
+                    if !(0. ..1.).contains(max_distance_ratio) {
+                        return Err(ChatFilterConfigError::InvalidRatio(i));
+                    }
+                }
+                ChatFilter::Screaming { ratio, .. } => {
+                    if !(0. ..=1.).contains(ratio) {
+                        return Err(ChatFilterConfigError::InvalidRatio(i));
+                    }
+                }
+                ChatFilter::Link => {}
+                ChatFilter::Words { words, .. } => {
+                    if words.is_empty() || words.iter().any(|word| word.trim().is_empty()) {
+                        return Err(ChatFilterConfigError::EmptyWord(i));
+                    }
+                }
+                ChatFilter::Characters { allowed } => {
+                    if allowed.as_ref().map(String::is_empty).unwrap_or_default() {
+                        return Err(ChatFilterConfigError::EmptyWhitelist(i));
+                    }
+                }
+            }
+            if config.action == (ChatAction::Mute { minutes: 0 }) {
+                return Err(ChatFilterConfigError::InvalidMuteDuration(i));
+            }
+        }
+        for (i, step) in self.escalation.iter().enumerate() {
+            if step.warnings == 0 || step.window_hours == 0 {
+                return Err(ChatFilterConfigError::InvalidEscalationRate(i));
+            }
+            match step.punishment {
+                EscalationPunishment::Mute { minutes } | EscalationPunishment::Ban { minutes } => {
+                    if minutes == 0 {
+                        return Err(ChatFilterConfigError::InvalidEscalationDuration(i));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Time span that has to be kept in memory for the rate filters
+    pub fn max_window(&self) -> Duration {
+        self.filters
+            .iter()
+            .filter_map(|config| config.filter.window())
+            .max()
+            .unwrap_or_else(Duration::zero)
+    }
+
+    /// Time span in which warnings count towards the escalation
+    pub fn max_escalation_window(&self) -> Duration {
+        let window_hours = self
+            .escalation
+            .iter()
+            .map(|step| step.window_hours)
+            .max()
+            .unwrap_or_default();
+        Duration::hours(window_hours as i64)
+    }
+
+    /// Returns the most severe escalation step that is reached by an account.
+    ///
+    /// `warnings` are the dates of all warnings of an account within the escalation window,
+    /// including the most recent one.
+    pub fn get_escalation(
+        &self,
+        warnings: &[NaiveDateTime],
+        now: NaiveDateTime,
+    ) -> Option<&EscalationStep> {
+        self.escalation
+            .iter()
+            .filter(|step| {
+                let since = now - Duration::hours(step.window_hours as i64);
+                warnings.iter().filter(|date| **date > since).count() >= step.warnings
+            })
+            .max_by_key(|step| step.punishment.severity())
+    }
+
+    /// Returns all filters that match the message in order, together with their index
+    pub(crate) fn check(&self, input: &FilterInput) -> Vec<(usize, &ChatFilterConfig)> {
+        self.filters
+            .iter()
+            .enumerate()
+            .filter(|(_, config)| config.filter.matches(input))
+            .collect()
+    }
+}
+
+impl ChatFilter {
+    fn matches(&self, input: &FilterInput) -> bool {
+        match self {
+            Self::Spam {
+                max_messages,
+                window_secs,
+            } => {
+                let since = input.now - Duration::seconds(*window_secs as i64);
+                Self::count_recent(input, since, |msg| !msg.is_spam.unwrap_or_default())
+                    >= *max_messages
+            }
+            Self::ExcessiveSpam {
+                max_messages,
+                window_secs,
+            } => {
+                let since = input.now - Duration::seconds(*window_secs as i64);
+                Self::count_recent(input, since, |_| true) >= *max_messages
+            }
+            Self::Repeated {
+                max_repeats,
+                window_secs,
+                max_distance_ratio,
+            } => {
+                let since = input.now - Duration::seconds(*window_secs as i64);
+                Self::count_recent(input, since, |msg| {
+                    is_similar(input.normalized, &msg.normalized, *max_distance_ratio)
+                }) >= *max_repeats
+            }
+            Self::Raid {
+                min_accounts,
+                window_secs,
+                min_len,
+                max_distance_ratio,
+            } => {
+                if input.message.len() <= *min_len {
+                    return false;
+                }
+                let since = input.now - Duration::seconds(*window_secs as i64);
+                let mut accounts: Vec<i32> = input
+                    .recent
+                    .iter()
+                    .skip_while(|msg| msg.date_time < since)
+                    .filter(|msg| {
+                        msg.account_id != input.account_id
+                            && is_similar(input.normalized, &msg.normalized, *max_distance_ratio)
+                    })
+                    .map(|msg| msg.account_id)
+                    .collect();
+                accounts.sort_unstable();
+                accounts.dedup();
+                // the sender is part of the raid as well
+                accounts.len() + 1 >= *min_accounts
+            }
+            Self::Screaming { min_len, ratio } => {
+                let message = input.message;
+                if message.len() <= *min_len {
+                    return false;
+                }
+                let alphabetic_count = message.chars().filter(|c| c.is_ascii_alphabetic()).count();
+                let screaming_count = message
+                    .chars()
+                    .filter(|c| c.is_ascii_alphabetic() && c.is_ascii_uppercase())
+                    .count();
+                (screaming_count as f32 / alphabetic_count as f32) > *ratio
+            }
+            Self::Link => contains_link(input.message),
+            Self::Words { trie, .. } => trie
+                .0
+                .map(|trie| {
+                    Censor::from_str(input.message)
+                        .with_trie(trie)
+                        .analyze()
+                        .is(Type::INAPPROPRIATE)
+                })
+                .unwrap_or_default(),
+            Self::Characters { allowed } => {
+                let allowed = allowed.as_deref().unwrap_or(ALLOWED_CHARACTERS);
+                input.message.chars().any(|c| !allowed.contains(c))
+            }
+        }
+    }
+
+    fn count_recent<F>(input: &FilterInput, since: NaiveDateTime, predicate: F) -> usize
+    where
+        F: Fn(&ChatMessage) -> bool,
+    {
+        input
+            .recent
+            .iter()
+            .skip_while(|msg| msg.date_time < since)
+            .filter(|msg| msg.account_id == input.account_id && predicate(msg))
+            .count()
+    }
+
+    /// Time span the rate filters look back on
+    fn window(&self) -> Option<Duration> {
+        match self {
+            Self::Spam { window_secs, .. }
+            | Self::ExcessiveSpam { window_secs, .. }
+            | Self::Repeated { window_secs, .. }
+            | Self::Raid { window_secs, .. } => Some(Duration::seconds(*window_secs as i64)),
+            _ => None,
+        }
+    }
+
+    /// Time span in which the filter warns an account at most once
+    pub(crate) fn warning_cooldown(&self) -> Duration {
+        self.window()
+            .unwrap_or_else(|| Duration::seconds(WARNING_COOLDOWN_SECS))
+    }
+
+    pub fn get_error(&self) -> ChatError {
+        match self {
+            Self::Spam { .. } => ChatError::Spam,
+            Self::ExcessiveSpam { .. } => ChatError::ExcessiveSpam,
+            Self::Repeated { .. } => ChatError::Repeated,
+            Self::Raid { .. } => ChatError::Raid,
+            Self::Screaming { .. } => ChatError::Screaming,
+            Self::Link => ChatError::Link,
+            Self::Words { .. } => ChatError::BlockedWord,
+            Self::Characters { .. } => ChatError::InvalidCharacters,
+        }
+    }
+}
+
+/// Keeps only lowercase alphanumeric characters and collapses repeated characters,
+/// so that a message cannot be varied by punctuation, spacing or stretched words.
+/// Messages without any alphanumeric characters are only trimmed
+pub(crate) fn normalize(message: &str) -> String {
+    let mut normalized = String::with_capacity(message.len());
+    for c in message
+        .chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+    {
+        if !normalized.ends_with(c) {
+            normalized.push(c);
+        }
+    }
+    if normalized.is_empty() {
+        message.trim().to_string()
+    } else {
+        normalized
+    }
+}
+
+/// Compares two normalized messages.
+///
+/// `max_distance_ratio` is the maximum edit distance relative to the length of the longer message
+fn is_similar(a: &str, b: &str, max_distance_ratio: f32) -> bool {
+    if a == b {
+        return true;
+    }
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let max_distance = (a.len().max(b.len()) as f32 * max_distance_ratio) as usize;
+    max_distance > 0 && edit_distance(&a, &b, max_distance).is_some()
+}
+
+/// Levenshtein distance, if it does not exceed `max_distance`.
+///
+/// Only cells within `max_distance` of the diagonal are computed
+/// and the computation stops, as soon as a whole row exceeds `max_distance`.
+fn edit_distance(a: &[char], b: &[char], max_distance: usize) -> Option<usize> {
+    if a.len().max(b.len()) - a.len().min(b.len()) > max_distance {
+        return None;
+    }
+    // every distance above `max_distance` is stored as `exceeded`
+    let exceeded = max_distance + 1;
+    let mut previous: Vec<usize> = (0..=b.len()).map(|j| j.min(exceeded)).collect();
+    let mut current = vec![exceeded; b.len() + 1];
+    for (i, ca) in a.iter().enumerate() {
+        let row = i + 1;
+        let from = row.saturating_sub(max_distance).max(1);
+        let to = (row + max_distance).min(b.len());
+        current[0] = row.min(exceeded);
+        if from > 1 {
+            current[from - 1] = exceeded;
+        }
+        let mut row_min = current[from - 1];
+        for j in from..=to {
+            let substitution = previous[j - 1] + if *ca == b[j - 1] { 0 } else { 1 };
+            current[j] = substitution
+                .min(previous[j] + 1)
+                .min(current[j - 1] + 1)
+                .min(exceeded);
+            row_min = row_min.min(current[j]);
+        }
+        if to < b.len() {
+            current[to + 1] = exceeded;
+        }
+        if row_min > max_distance {
+            return None;
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+    Some(previous[b.len()]).filter(|distance| *distance <= max_distance)
+}
+
+fn contains_link(message: &str) -> bool {
+    let message = message.to_lowercase();
+    if message.contains("://") || message.contains("www.") {
+        return true;
+    }
+    message.split_whitespace().any(|word| {
+        let host = word.split('/').next().unwrap_or_default();
+        let host = host.trim_end_matches(|c: char| !c.is_ascii_alphanumeric());
+        match host.rsplit_once('.') {
+            Some((domain, tld)) => !domain.is_empty() && LINK_TLDS.contains(&tld),
+            None => false,
+        }
+    })
+}
+
+#[derive(Debug, Error)]
+pub enum ChatFilterConfigError {
+    #[error("[Io]: could not read chat filter config {path}: {source}")]
+    Io { path: String, source: io::Error },
+    #[error("[Ron]: {0}")]
+    Ron(#[from] ron::Error),
+    #[error(
+        "chat filter {0} must allow at least one message in a time window of at least one second"
+    )]
+    InvalidRate(usize),
+    #[error("chat filter {0} must have a ratio between 0 and 1")]
+    InvalidRatio(usize),
+    #[error("chat filter {0} contains an empty word")]
+    EmptyWord(usize),
+    #[error("chat filter {0} has an empty character whitelist")]
+    EmptyWhitelist(usize),
+    #[error("chat filter {0} must mute for at least one minute")]
+    InvalidMuteDuration(usize),
+    #[error(
+        "escalation step {0} must require at least one warning in a time window of at least one hour"
+    )]
+    InvalidEscalationRate(usize),
+    #[error("escalation step {0} must punish for at least one minute")]
+    InvalidEscalationDuration(usize),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize_ignores_case_punctuation_and_stretching() {
+        assert_eq!(normalize("Hello, World!!"), "heloworld");
+        assert_eq!(normalize("heeeey   THERE"), "heythere");
+        assert_eq!(normalize("h.e.l.l.o"), "helo");
+    }
+
+    #[test]
+    fn normalize_trims_messages_without_alphanumeric_characters() {
+        assert_eq!(normalize("  ?!?  "), "?!?");
+        assert_eq!(normalize(""), "");
+    }
+
+    #[test]
+    fn edit_distance_within_max_distance() {
+        let distance = |a: &str, b: &str, max_distance| {
+            let a: Vec<char> = a.chars().collect();
+            let b: Vec<char> = b.chars().collect();
+            edit_distance(&a, &b, max_distance)
+        };
+        assert_eq!(distance("kitten", "sitting", 3), Some(3));
+        assert_eq!(distance("kitten", "kitten", 0), Some(0));
+        assert_eq!(distance("", "abc", 3), Some(3));
+        assert_eq!(distance("abc", "", 3), Some(3));
+        assert_eq!(distance("flaw", "lawn", 2), Some(2));
+    }
+
+    #[test]
+    fn edit_distance_exceeding_max_distance() {
+        let distance = |a: &str, b: &str, max_distance| {
+            let a: Vec<char> = a.chars().collect();
+            let b: Vec<char> = b.chars().collect();
+            edit_distance(&a, &b, max_distance)
+        };
+        assert_eq!(distance("kitten", "sitting", 2), None);
+        assert_eq!(distance("abc", "abcdefgh", 4), None);
+        assert_eq!(distance("abcdef", "fedcba", 1), None);
+    }
+
+    #[test]
+    fn is_similar_uses_ratio_of_longer_message() {
+        assert!(is_similar("joinmyserver", "joinmyserver", 0.));
+        assert!(!is_similar("joinmyserver", "joinmyserwer", 0.));
+        assert!(is_similar("joinmyserver", "joinmyserwer", 0.1));
+        assert!(!is_similar("joinmyserver", "leavemyserver", 0.1));
+        assert!(!is_similar("hi", "ho", 0.3));
+    }
+
+    #[test]
+    fn words_filter_detects_evasions() {
+        let words = vec!["Cheese".to_string()];
+        let filter = ChatFilter::Words {
+            trie: WordTrie::new(&words),
+            words,
+        };
+        let recent = VecDeque::new();
+        let matches = |message: &str| {
+            filter.matches(&FilterInput {
+                message,
+                normalized: &normalize(message),
+                account_id: 1,
+                now: NaiveDateTime::from_timestamp(1_000_000, 0),
+                recent: &recent,
+            })
+        };
+        assert!(matches("i like cheese"));
+        assert!(matches("i like C h e e s e"));
+        assert!(!matches("i like bread"));
+    }
+
+    #[test]
+    fn word_tries_are_reused_for_identical_words() {
+        let trie = |words: &[&str]| {
+            let words: Vec<_> = words.iter().map(|word| word.to_string()).collect();
+            WordTrie::new(&words).0.unwrap()
+        };
+        assert!(std::ptr::eq(
+            trie(&["Cheese", "bread"]),
+            trie(&["bread", "cheese", "BREAD"])
+        ));
+        assert!(!std::ptr::eq(trie(&["cheese"]), trie(&["bread"])));
+    }
+
+    #[test]
+    fn get_escalation_returns_most_severe_step_reached() {
+        let step = |warnings, window_hours, punishment| EscalationStep {
+            warnings,
+            window_hours,
+            punishment,
+        };
+        let config = ChatFiltersConfig {
+            filters: vec![],
+            escalation: vec![
+                step(3, 24, EscalationPunishment::Mute { minutes: 60 }),
+                step(6, 24, EscalationPunishment::Ban { minutes: 1440 }),
+                step(4, 1, EscalationPunishment::Mute { minutes: 120 }),
+            ],
+        };
+        let now = NaiveDateTime::from_timestamp(1_000_000, 0);
+        let warnings = |count: i64, minutes_apart: i64| -> Vec<NaiveDateTime> {
+            (0..count)
+                .map(|i| now - Duration::minutes(i * minutes_apart))
+                .collect()
+        };
+        let severity = |step: Option<&EscalationStep>| step.map(|step| step.punishment.severity());
+
+        assert_eq!(severity(config.get_escalation(&warnings(2, 30), now)), None);
+        assert_eq!(
+            severity(config.get_escalation(&warnings(3, 30), now)),
+            Some((false, 60))
+        );
+        assert_eq!(
+            severity(config.get_escalation(&warnings(4, 10), now)),
+            Some((false, 120))
+        );
+        assert_eq!(
+            severity(config.get_escalation(&warnings(5, 30), now)),
+            Some((false, 60))
+        );
+        assert_eq!(
+            severity(config.get_escalation(&warnings(6, 30), now)),
+            Some((true, 1440))
+        );
+        assert_eq!(
+            severity(config.get_escalation(&warnings(9, 30), now)),
+            Some((true, 1440))
+        );
+    }
+}
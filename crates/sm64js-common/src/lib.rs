@@ -1,18 +1,26 @@
 mod chat;
+mod chat_filter;
 mod date_format;
+mod moderation;
 
 pub use chat::{
-    sanitize_chat, ChatError, ChatHistory, ChatHistoryData, ChatMessage, ChatResult, GetChat,
+    sanitize_chat, ChatCursor, ChatError, ChatHistory, ChatHistoryData, ChatMessage, ChatPage,
+    ChatResult, ChatRoom, FilteredChat, GetChat,
 };
+pub use chat_filter::{
+    ChatAction, ChatFilter, ChatFilterConfig, ChatFilterConfigError, ChatFiltersConfig,
+    EscalationPunishment, EscalationStep, WordTrie,
+};
+pub use moderation::{GetModerationLog, ModerationAction, ModerationActionKind, ModerationSource};
 
 use awc::SendClientRequest;
 use chrono::{NaiveDateTime, Utc};
 use paperclip::actix::{web::HttpRequest, Apiv2Schema};
 use prost::Message as ProstMessage;
-use serde::{Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use serde_with::skip_serializing_none;
-use sm64js_env::DISCORD_BOT_TOKEN;
-use sm64js_proto::{root_msg, sm64_js_msg, RootMsg, Sm64JsMsg};
+use sm64js_env::{DISCORD_BOT_TOKEN, REDIRECT_URI};
+use sm64js_proto::{root_msg, sm64_js_msg, ChatMsg, RootMsg, Sm64JsMsg};
 use std::time::Duration;
 
 #[derive(Clone, Debug, Deserialize)]
@@ -38,6 +46,58 @@ pub struct DiscordGuildMember {
     pub mute: bool,
 }
 
+#[derive(Apiv2Schema, Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FlaggedAccount {
+    pub account_id: i32,
+    pub total_violations: u32,
+    /// Most recent violations
+    pub violations: Vec<CheatViolation>,
+}
+
+#[derive(Apiv2Schema, Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CheatViolation {
+    pub validator: String,
+    pub evidence: String,
+    pub date_time: NaiveDateTime,
+}
+
+#[derive(Apiv2Schema, Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ServerStatus {
+    /// Only accounts with the `JoinDuringMaintenance` permission can join
+    pub maintenance: bool,
+    pub shutting_down: bool,
+}
+
+/// Server wide counters since the server started
+#[derive(Apiv2Schema, Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ServerMetrics {
+    /// Messages that could not be decoded
+    pub malformed_messages: u64,
+    pub rate_limited_mario_msgs: u64,
+    pub rate_limited_skin_msgs: u64,
+    pub rate_limited_grab_flag_msgs: u64,
+    pub rate_limited_attack_msgs: u64,
+    /// Clients that were kicked for exceeding their message budget
+    pub rate_limit_kicks: u64,
+    /// Ticks of the game loop
+    pub ticks: u64,
+    /// Ticks that have been skipped, because the game loop fell behind
+    pub skipped_ticks: u64,
+    /// Includes the time until all room actors processed the tick
+    pub avg_tick_duration_us: u64,
+    pub max_tick_duration_us: u64,
+    /// Tasks processed by all room actors
+    pub room_tasks: u64,
+    pub avg_room_task_duration_us: u64,
+    pub max_room_task_duration_us: u64,
+    /// Bytes of Mario and flag updates that have been sent to clients
+    pub broadcast_bytes: u64,
+}
+
 #[skip_serializing_none]
 #[derive(Apiv2Schema, Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
@@ -46,8 +106,17 @@ pub struct PlayerInfo {
     pub discord_id: Option<String>,
     pub google_id: Option<String>,
     pub ip: String,
-    pub level: u32,
+    /// Empty, if the room of the player could not be found
+    pub level: Option<u32>,
+    /// Empty, if the room of the player could not be found
+    pub shard: Option<u32>,
+    /// Empty, if the room of the player could not be found
+    pub room: Option<String>,
     pub name: String,
+    /// Attacks that could not have been sent by an unmodified client
+    pub rejected_attacks: u32,
+    /// Attacks that have been sent before the attack cooldown passed
+    pub cooldown_attacks: u32,
     pub chat: Option<Vec<chat::ChatMessage>>,
 }
 
@@ -57,6 +126,8 @@ pub struct AccountInfo {
     pub account: Account,
     pub discord: Option<DiscordAccount>,
     pub google: Option<GoogleAccount>,
+    /// All bans, mutes and warnings of this account, most recent first
+    pub punishments: Option<Vec<Punishment>>,
 }
 
 #[skip_serializing_none]
@@ -74,6 +145,49 @@ pub struct Account {
     pub mute_reason: Option<String>,
 }
 
+#[derive(Apiv2Schema, Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub enum PunishmentKind {
+    Ban,
+    IpBan,
+    Mute,
+    /// Warnings don't restrict the account, but too many of them lead to an automatic punishment.
+    /// They are only listed in the account history
+    Warning,
+}
+
+#[skip_serializing_none]
+#[derive(Apiv2Schema, Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Punishment {
+    pub kind: PunishmentKind,
+    /// Empty for IP bans
+    pub account_id: Option<i32>,
+    /// Requires the `SeeIp` permission
+    pub ip: Option<String>,
+    pub reason: Option<String>,
+    pub created_at: NaiveDateTime,
+    /// Permanent, if empty
+    pub expires_at: Option<NaiveDateTime>,
+    /// Set, if the punishment has been lifted early
+    pub revoked_at: Option<NaiveDateTime>,
+    /// Account id of the moderator who lifted the punishment.
+    /// Empty, if it has been overwritten by a newer punishment
+    pub revoked_by: Option<i32>,
+    pub revoke_reason: Option<String>,
+}
+
+#[derive(Apiv2Schema, Debug, Default, Deserialize)]
+pub struct GetPunishments {
+    pub kind: Option<PunishmentKind>,
+    pub account_id: Option<i32>,
+    /// Requires the `SeeIp` permission
+    pub ip: Option<String>,
+    /// Only permanent or only temporary punishments
+    pub permanent: Option<bool>,
+    /// Number of punishments. Defaults to 100, maximum is 1000
+    pub limit: Option<u32>,
+}
+
 #[skip_serializing_none]
 #[derive(Apiv2Schema, Clone, Debug, Serialize)]
 pub struct DiscordAccount {
@@ -143,6 +257,16 @@ pub fn create_uncompressed_msg(msg: sm64_js_msg::Message) -> Vec<u8> {
     msg
 }
 
+/// Chat message that is shown as sent by the server
+pub fn create_server_chat_msg(message: String) -> Vec<u8> {
+    create_uncompressed_msg(sm64_js_msg::Message::ChatMsg(ChatMsg {
+        message,
+        sender: "[Server]".to_string(),
+        is_server: true,
+        ..Default::default()
+    }))
+}
+
 pub async fn send_discord_message(
     channel_id: &str,
     message_id: Option<&str>,
@@ -192,6 +316,105 @@ pub async fn send_discord_message(
     };
 }
 
+/// Channel in which all moderation actions are logged
+pub(crate) const DISCORD_MODERATION_CHANNEL_ID: &str = "829813249520042066";
+
+pub fn get_discord_author(name: String, account_info: &AccountInfo) -> DiscordRichEmbedAuthor {
+    DiscordRichEmbedAuthor {
+        name,
+        url: Some(format!(
+            "{}/api/account?account_id={}",
+            REDIRECT_URI.get().unwrap(),
+            account_info.account.id
+        )),
+        icon_url: Some(if let Some(discord) = &account_info.discord {
+            if let Some(avatar) = &discord.avatar {
+                format!(
+                    "https://cdn.discordapp.com/avatars/{}/{}.png?size=64",
+                    discord.id, avatar
+                )
+            } else {
+                "https://discord.com/assets/2c21aeda16de354ba5334551a883b481.png".to_string()
+            }
+        } else {
+            "https://developers.google.com/identity/images/g-logo.png".to_string()
+        }),
+    }
+}
+
+/// Logs a moderation action against an account, e.g. "POST Ban player by Moderator"
+pub async fn send_discord_moderation_message(
+    action: String,
+    reason: Option<String>,
+    expires_at: Option<NaiveDateTime>,
+    account_info: AccountInfo,
+) {
+    let author = get_discord_author(action, &account_info);
+    let footer = format!("#{}", account_info.account.id);
+    send_discord_moderation_embed(author, footer, reason, expires_at).await;
+}
+
+/// Logs a moderation action against an IP address, e.g. "POST Ban IP address by Moderator"
+pub async fn send_discord_ip_moderation_message(
+    action: String,
+    reason: Option<String>,
+    expires_at: Option<NaiveDateTime>,
+    ip: String,
+) {
+    let author = DiscordRichEmbedAuthor {
+        name: action,
+        url: None,
+        icon_url: None,
+    };
+    send_discord_moderation_embed(author, ip, reason, expires_at).await;
+}
+
+async fn send_discord_moderation_embed(
+    author: DiscordRichEmbedAuthor,
+    footer: String,
+    reason: Option<String>,
+    expires_at: Option<NaiveDateTime>,
+) {
+    let message = format!(
+        r"reason: {}
+expires_at: {}
+        ",
+        reason.unwrap_or_default(),
+        expires_at.map(|exp| exp.to_string()).unwrap_or_default()
+    );
+    let footer = Some(DiscordRichEmbedFooter { text: footer });
+    send_discord_message(
+        DISCORD_MODERATION_CHANNEL_ID,
+        None,
+        message,
+        None,
+        author,
+        footer,
+    )
+    .await;
+}
+
+/// Looks up the geolocation of an IP address.
+/// Returns `None`, if the location is unknown
+pub async fn fetch_geolocation<T: DeserializeOwned>(
+    mut ip: String,
+) -> Result<Option<T>, awc::error::SendRequestError> {
+    if ip == "127.0.0.1" {
+        ip = "".to_string();
+    }
+    let request: SendClientRequest = awc::Client::default()
+        .get(format!("http://ip-api.com/json/{}?fields=205814", ip))
+        .send();
+    let mut response = request.await?;
+    if !response.status().is_success() {
+        Ok(None)
+    } else if let Ok(geolocation) = response.json().await {
+        Ok(Some(geolocation))
+    } else {
+        Ok(None)
+    }
+}
+
 pub fn get_ip_from_req(req: &HttpRequest) -> Option<String> {
     if let Some(x_real_ip) = req
         .headers()
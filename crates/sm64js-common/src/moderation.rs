@@ -0,0 +1,129 @@
+use chrono::{DateTime, NaiveDateTime, Utc};
+use paperclip::actix::Apiv2Schema;
+use serde::{Deserialize, Serialize};
+use serde_with::skip_serializing_none;
+use std::str::FromStr;
+
+#[derive(Apiv2Schema, Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub enum ModerationActionKind {
+    Ban,
+    IpBan,
+    Kick,
+    Mute,
+    Unban,
+    IpUnban,
+    Unmute,
+    Warn,
+}
+
+impl ModerationActionKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Ban => "Ban",
+            Self::IpBan => "IpBan",
+            Self::Kick => "Kick",
+            Self::Mute => "Mute",
+            Self::Unban => "Unban",
+            Self::IpUnban => "IpUnban",
+            Self::Unmute => "Unmute",
+            Self::Warn => "Warn",
+        }
+    }
+}
+
+impl FromStr for ModerationActionKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Ban" => Ok(Self::Ban),
+            "IpBan" => Ok(Self::IpBan),
+            "Kick" => Ok(Self::Kick),
+            "Mute" => Ok(Self::Mute),
+            "Unban" => Ok(Self::Unban),
+            "IpUnban" => Ok(Self::IpUnban),
+            "Unmute" => Ok(Self::Unmute),
+            "Warn" => Ok(Self::Warn),
+            _ => Err(format!("unknown moderation action {}", s)),
+        }
+    }
+}
+
+/// Where a moderation action has been issued
+#[derive(Apiv2Schema, Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub enum ModerationSource {
+    Api,
+    ChatCommand,
+    /// Automatic punishment by a chat filter, e.g. a mute due to excessive spam
+    ChatFilter,
+    /// Automatic punishment of an account that collected too many warnings
+    Escalation,
+}
+
+impl ModerationSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Api => "Api",
+            Self::ChatCommand => "ChatCommand",
+            Self::ChatFilter => "ChatFilter",
+            Self::Escalation => "Escalation",
+        }
+    }
+}
+
+impl FromStr for ModerationSource {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Api" => Ok(Self::Api),
+            "ChatCommand" => Ok(Self::ChatCommand),
+            "ChatFilter" => Ok(Self::ChatFilter),
+            "Escalation" => Ok(Self::Escalation),
+            _ => Err(format!("unknown moderation source {}", s)),
+        }
+    }
+}
+
+#[skip_serializing_none]
+#[derive(Apiv2Schema, Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ModerationAction {
+    pub id: i32,
+    pub action: ModerationActionKind,
+    pub source: ModerationSource,
+    /// Account id of the moderator. Empty for automatic actions
+    pub actor_account_id: Option<i32>,
+    /// Empty for IP bans
+    pub target_account_id: Option<i32>,
+    /// Requires the `SeeIp` permission
+    pub target_ip: Option<String>,
+    /// Only set for temporary bans and mutes
+    pub expires_at: Option<NaiveDateTime>,
+    pub reason: Option<String>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Apiv2Schema, Debug, Default, Deserialize)]
+pub struct GetModerationLog {
+    /// Format must be given as %Y-%m-%d %H:%M:%S
+    #[serde(
+        deserialize_with = "crate::date_format::deserialize_opt",
+        default = "crate::date_format::empty"
+    )]
+    pub from: Option<DateTime<Utc>>,
+    /// Format must be given as %Y-%m-%d %H:%M:%S
+    #[serde(
+        deserialize_with = "crate::date_format::deserialize_opt",
+        default = "crate::date_format::empty"
+    )]
+    pub to: Option<DateTime<Utc>>,
+    /// Number of actions. Defaults to 100, maximum is 1000
+    pub limit: Option<u32>,
+    pub action: Option<ModerationActionKind>,
+    pub source: Option<ModerationSource>,
+    pub actor_account_id: Option<i32>,
+    pub target_account_id: Option<i32>,
+    /// Requires the `SeeIp` permission
+    pub target_ip: Option<String>,
+}
@@ -0,0 +1,11 @@
+CREATE TABLE race_times (
+  id SERIAL PRIMARY KEY,
+  level INTEGER NOT NULL,
+  time_ms INTEGER NOT NULL,
+  player_name VARCHAR NOT NULL,
+  finished_at TIMESTAMP NOT NULL,
+  account_id INTEGER NOT NULL REFERENCES accounts ON DELETE CASCADE,
+  UNIQUE (level, account_id)
+);
+
+CREATE INDEX race_times_level_time_ms_idx ON race_times (level, time_ms);
@@ -0,0 +1,20 @@
+CREATE TABLE chat_messages (
+  id SERIAL PRIMARY KEY,
+  message VARCHAR NOT NULL,
+  date_time TIMESTAMP NOT NULL,
+  player_name VARCHAR,
+  account_id INTEGER NOT NULL REFERENCES accounts ON DELETE CASCADE,
+  discord_id VARCHAR,
+  google_id VARCHAR,
+  ip VARCHAR,
+  is_escaped BOOLEAN NOT NULL,
+  is_censored BOOLEAN NOT NULL,
+  is_spam BOOLEAN NOT NULL,
+  is_excessive_spam BOOLEAN NOT NULL,
+  is_screaming BOOLEAN NOT NULL
+);
+
+CREATE INDEX chat_messages_date_time_idx ON chat_messages (date_time);
+CREATE INDEX chat_messages_player_name_date_time_idx ON chat_messages (player_name, date_time);
+CREATE INDEX chat_messages_discord_id_date_time_idx ON chat_messages (discord_id, date_time);
+CREATE INDEX chat_messages_google_id_date_time_idx ON chat_messages (google_id, date_time);
@@ -0,0 +1,6 @@
+DROP INDEX chat_messages_ip_date_time_idx;
+DROP INDEX chat_messages_level_date_time_idx;
+DROP INDEX chat_messages_account_id_date_time_idx;
+
+ALTER TABLE chat_messages
+  DROP COLUMN level;
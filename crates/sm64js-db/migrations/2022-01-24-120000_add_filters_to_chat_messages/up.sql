@@ -0,0 +1,6 @@
+ALTER TABLE chat_messages
+  ADD COLUMN level INTEGER;
+
+CREATE INDEX chat_messages_account_id_date_time_idx ON chat_messages (account_id, date_time);
+CREATE INDEX chat_messages_level_date_time_idx ON chat_messages (level, date_time);
+CREATE INDEX chat_messages_ip_date_time_idx ON chat_messages (ip, date_time);
@@ -0,0 +1,5 @@
+DROP INDEX chat_messages_room_id_date_time_idx;
+
+ALTER TABLE chat_messages
+  DROP COLUMN room_name,
+  DROP COLUMN room_id;
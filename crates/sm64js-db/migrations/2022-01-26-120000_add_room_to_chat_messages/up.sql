@@ -0,0 +1,5 @@
+ALTER TABLE chat_messages
+  ADD COLUMN room_id BIGINT,
+  ADD COLUMN room_name VARCHAR;
+
+CREATE INDEX chat_messages_room_id_date_time_idx ON chat_messages (room_id, date_time);
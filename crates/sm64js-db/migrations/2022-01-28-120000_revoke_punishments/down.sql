@@ -0,0 +1,17 @@
+ALTER TABLE mutes
+  DROP COLUMN revoke_reason,
+  DROP COLUMN revoked_by,
+  DROP COLUMN revoked_at,
+  DROP COLUMN created_at;
+
+ALTER TABLE ip_bans
+  DROP COLUMN revoke_reason,
+  DROP COLUMN revoked_by,
+  DROP COLUMN revoked_at,
+  DROP COLUMN created_at;
+
+ALTER TABLE bans
+  DROP COLUMN revoke_reason,
+  DROP COLUMN revoked_by,
+  DROP COLUMN revoked_at,
+  DROP COLUMN created_at;
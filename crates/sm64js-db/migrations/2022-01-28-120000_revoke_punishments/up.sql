@@ -0,0 +1,17 @@
+ALTER TABLE bans
+  ADD COLUMN created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  ADD COLUMN revoked_at TIMESTAMP,
+  ADD COLUMN revoked_by INTEGER,
+  ADD COLUMN revoke_reason VARCHAR;
+
+ALTER TABLE ip_bans
+  ADD COLUMN created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  ADD COLUMN revoked_at TIMESTAMP,
+  ADD COLUMN revoked_by INTEGER,
+  ADD COLUMN revoke_reason VARCHAR;
+
+ALTER TABLE mutes
+  ADD COLUMN created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  ADD COLUMN revoked_at TIMESTAMP,
+  ADD COLUMN revoked_by INTEGER,
+  ADD COLUMN revoke_reason VARCHAR;
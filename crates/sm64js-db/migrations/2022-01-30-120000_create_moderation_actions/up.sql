@@ -0,0 +1,16 @@
+-- account ids are not foreign keys, so that the log is kept, even if an account is deleted
+CREATE TABLE moderation_actions (
+  id SERIAL PRIMARY KEY,
+  action VARCHAR NOT NULL,
+  source VARCHAR NOT NULL,
+  actor_account_id INTEGER,
+  target_account_id INTEGER,
+  target_ip VARCHAR,
+  expires_at TIMESTAMP,
+  reason VARCHAR,
+  created_at TIMESTAMP NOT NULL
+);
+
+CREATE INDEX moderation_actions_created_at_idx ON moderation_actions (created_at);
+CREATE INDEX moderation_actions_actor_account_id_created_at_idx ON moderation_actions (actor_account_id, created_at);
+CREATE INDEX moderation_actions_target_account_id_created_at_idx ON moderation_actions (target_account_id, created_at);
@@ -0,0 +1,9 @@
+CREATE TABLE warnings (
+  id SERIAL PRIMARY KEY,
+  account_id INTEGER NOT NULL REFERENCES accounts ON DELETE CASCADE,
+  reason VARCHAR,
+  issued_by INTEGER,
+  created_at TIMESTAMP NOT NULL
+);
+
+CREATE INDEX warnings_account_id_created_at_idx ON warnings (account_id, created_at);
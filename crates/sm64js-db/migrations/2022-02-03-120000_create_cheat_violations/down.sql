@@ -0,0 +1,2 @@
+DROP TABLE cheat_violations;
+DROP TABLE flagged_accounts
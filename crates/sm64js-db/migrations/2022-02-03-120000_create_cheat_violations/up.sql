@@ -0,0 +1,14 @@
+CREATE TABLE flagged_accounts (
+  account_id INTEGER PRIMARY KEY REFERENCES accounts ON DELETE CASCADE,
+  total_violations INTEGER NOT NULL
+);
+
+CREATE TABLE cheat_violations (
+  id SERIAL PRIMARY KEY,
+  account_id INTEGER NOT NULL REFERENCES flagged_accounts ON DELETE CASCADE,
+  validator VARCHAR NOT NULL,
+  evidence VARCHAR NOT NULL,
+  created_at TIMESTAMP NOT NULL
+);
+
+CREATE INDEX cheat_violations_account_id_created_at_idx ON cheat_violations (account_id, created_at);
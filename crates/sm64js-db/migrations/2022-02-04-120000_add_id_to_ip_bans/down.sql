@@ -0,0 +1,11 @@
+DELETE FROM ip_bans a
+  USING ip_bans b
+  WHERE a.ip = b.ip AND a.id < b.id;
+
+DROP INDEX ip_bans_ip_idx;
+
+ALTER TABLE ip_bans
+  DROP COLUMN id;
+
+ALTER TABLE ip_bans
+  ADD PRIMARY KEY (ip)
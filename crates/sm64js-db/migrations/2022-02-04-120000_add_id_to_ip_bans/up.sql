@@ -0,0 +1,7 @@
+ALTER TABLE ip_bans
+  DROP CONSTRAINT ip_bans_pkey;
+
+ALTER TABLE ip_bans
+  ADD COLUMN id SERIAL PRIMARY KEY;
+
+CREATE INDEX ip_bans_ip_idx ON ip_bans (ip);
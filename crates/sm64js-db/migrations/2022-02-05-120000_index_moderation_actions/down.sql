@@ -0,0 +1,3 @@
+DROP INDEX moderation_actions_source_created_at_idx;
+DROP INDEX moderation_actions_action_created_at_idx;
+DROP INDEX moderation_actions_target_ip_created_at_idx
@@ -0,0 +1,3 @@
+CREATE INDEX moderation_actions_target_ip_created_at_idx ON moderation_actions (target_ip, created_at);
+CREATE INDEX moderation_actions_action_created_at_idx ON moderation_actions (action, created_at);
+CREATE INDEX moderation_actions_source_created_at_idx ON moderation_actions (source, created_at);
@@ -12,12 +12,18 @@ use actix_session::Session;
 use actix_web::{dev::Body, http::StatusCode, HttpResponse, ResponseError};
 use chrono::{prelude::*, Duration};
 use diesel::{
-    pg::{upsert::on_constraint, PgConnection},
+    pg::{
+        upsert::{excluded, on_constraint},
+        PgConnection,
+    },
     prelude::*,
     r2d2::ConnectionManager,
 };
 use paperclip::actix::api_v2_errors;
-use sm64js_common::{AccountInfo, DiscordAccount, DiscordGuildMember, DiscordUser};
+use sm64js_common::{
+    AccountInfo, ChatCursor, DiscordAccount, DiscordGuildMember, DiscordUser, GetChat,
+    GetModerationLog, GetPunishments, ModerationAction, Punishment, PunishmentKind,
+};
 #[cfg(debug_assertions)]
 use sm64js_env::{
     DEV_ACCOUNT_ID, DEV_GOOGLE_ACCOUNT_ID, DEV_GOOGLE_SESSION_TOKEN, DEV_GOOGLE_TEST_USER,
@@ -304,6 +310,7 @@ pub fn get_account_info(
             },
             discord: None,
             google: None,
+            punishments: None,
         }
     };
 
@@ -380,6 +387,8 @@ pub fn update_account(
     Ok(())
 }
 
+/// Lifts all active bans of the account first, so callers have to check,
+/// if the moderator is allowed to revoke them (see `Permission::revoke_ban`).
 pub fn ban_account(
     conn: &PgConnection,
     geolocation: Option<models::NewGeolocation>,
@@ -391,10 +400,12 @@ pub fn ban_account(
     use schema::bans;
 
     if let Some(account_id) = account_id {
-        if let Some(ban) = is_account_banned(conn, account_id)? {
-            use schema::bans::dsl::*;
-            diesel::delete(bans).filter(id.eq(ban.id)).execute(conn)?;
-        }
+        revoke_ban(
+            conn,
+            account_id,
+            None,
+            Some("overwritten by a new ban".to_string()),
+        )?;
     }
 
     let new_ban = models::NewBan {
@@ -414,6 +425,8 @@ pub fn ban_account(
     Ok(ban)
 }
 
+/// Lifts the active ban of the IP address first, so callers have to check,
+/// if the moderator is allowed to revoke it (see `Permission::revoke_ban`).
 pub fn ban_ip(
     conn: &PgConnection,
     ip: String,
@@ -422,21 +435,27 @@ pub fn ban_ip(
 ) -> Result<models::IpBan> {
     use schema::ip_bans;
 
-    let ip_ban = models::IpBan {
+    revoke_ip_ban(
+        conn,
+        &ip,
+        None,
+        Some("overwritten by a new ban".to_string()),
+    )?;
+
+    let new_ip_ban = models::NewIpBan {
         ip,
         reason,
         expires_at,
     };
     let ip_ban: models::IpBan = diesel::insert_into(ip_bans::table)
-        .values(&ip_ban)
-        .on_conflict(on_constraint("ip_bans_pkey"))
-        .do_update()
-        .set(&ip_ban)
+        .values(&new_ip_ban)
         .get_result(conn)?;
 
     Ok(ip_ban)
 }
 
+/// Lifts all active mutes of the account first, so callers have to check,
+/// if the moderator is allowed to revoke them (see `Permission::revoke_mute`).
 pub fn mute_account(
     conn: &PgConnection,
     reason: Option<String>,
@@ -445,10 +464,12 @@ pub fn mute_account(
 ) -> Result<models::Mute> {
     use schema::mutes;
 
-    if let Some(ban) = is_account_muted(conn, account_id)? {
-        use schema::mutes::dsl::*;
-        diesel::delete(mutes).filter(id.eq(ban.id)).execute(conn)?;
-    }
+    revoke_mute(
+        conn,
+        account_id,
+        None,
+        Some("overwritten by a new mute".to_string()),
+    )?;
 
     let new_mute = models::NewMute {
         reason,
@@ -462,97 +483,669 @@ pub fn mute_account(
     Ok(mute)
 }
 
+/// Returns the active ban of an account, that expires last
 pub fn is_account_banned(conn: &PgConnection, account_id: i32) -> Result<Option<models::Ban>> {
-    let account = get_account(conn, account_id)?;
-
-    if let Ok(bans) = models::Ban::belonging_to(&account).load::<models::Ban>(conn) {
-        if bans.is_empty() {
-            Ok(None)
-        } else {
-            let max_ban = if let Some(max_ban) = bans.iter().find(|ban| ban.expires_at.is_none()) {
-                max_ban
+    use schema::bans::dsl;
+
+    let now = Utc::now().naive_utc();
+    let ban = dsl::bans
+        .filter(dsl::account_id.eq(account_id))
+        .filter(dsl::revoked_at.is_null())
+        .filter(dsl::expires_at.is_null().or(dsl::expires_at.gt(now)))
+        // permanent bans come first, because Postgres sorts NULL values first in descending order
+        .order(dsl::expires_at.desc())
+        .first(conn)
+        .optional()?;
+
+    Ok(ban)
+}
+
+pub fn is_ip_banned(conn: &PgConnection, ip: &str) -> Result<Option<models::IpBan>> {
+    use schema::ip_bans::dsl;
+
+    let now = Utc::now().naive_utc();
+    let ban = dsl::ip_bans
+        .filter(dsl::ip.eq(ip))
+        .filter(dsl::revoked_at.is_null())
+        .filter(dsl::expires_at.is_null().or(dsl::expires_at.gt(now)))
+        .first(conn)
+        .optional()?;
+
+    Ok(ban)
+}
+
+/// Returns the active mute of an account, that expires last
+pub fn is_account_muted(conn: &PgConnection, account_id: i32) -> Result<Option<models::Mute>> {
+    use schema::mutes::dsl;
+
+    let now = Utc::now().naive_utc();
+    let mute = dsl::mutes
+        .filter(dsl::account_id.eq(account_id))
+        .filter(dsl::revoked_at.is_null())
+        .filter(dsl::expires_at.is_null().or(dsl::expires_at.gt(now)))
+        .order(dsl::expires_at.desc())
+        .first(conn)
+        .optional()?;
+
+    Ok(mute)
+}
+
+/// Lifts all active bans of an account.
+///
+/// `revoked_by` is the account id of the moderator.
+/// Returns the ban that would have expired last or `None`, if the account was not banned.
+pub fn revoke_ban(
+    conn: &PgConnection,
+    account_id: i32,
+    revoked_by: Option<i32>,
+    revoke_reason: Option<String>,
+) -> Result<Option<models::Ban>> {
+    use schema::bans::dsl;
+
+    let now = Utc::now().naive_utc();
+    let revoked: Vec<models::Ban> = diesel::update(
+        dsl::bans
+            .filter(dsl::account_id.eq(account_id))
+            .filter(dsl::revoked_at.is_null())
+            .filter(dsl::expires_at.is_null().or(dsl::expires_at.gt(now))),
+    )
+    .set((
+        dsl::revoked_at.eq(now),
+        dsl::revoked_by.eq(revoked_by),
+        dsl::revoke_reason.eq(revoke_reason),
+    ))
+    .get_results(conn)?;
+
+    // permanent punishments expire last
+    Ok(revoked
+        .into_iter()
+        .max_by_key(|ban| (ban.expires_at.is_none(), ban.expires_at)))
+}
+
+/// Lifts the active ban of an IP address.
+///
+/// Returns `None`, if the address was not banned.
+pub fn revoke_ip_ban(
+    conn: &PgConnection,
+    ip: &str,
+    revoked_by: Option<i32>,
+    revoke_reason: Option<String>,
+) -> Result<Option<models::IpBan>> {
+    use schema::ip_bans::dsl;
+
+    let now = Utc::now().naive_utc();
+    let revoked = diesel::update(
+        dsl::ip_bans
+            .filter(dsl::ip.eq(ip))
+            .filter(dsl::revoked_at.is_null())
+            .filter(dsl::expires_at.is_null().or(dsl::expires_at.gt(now))),
+    )
+    .set((
+        dsl::revoked_at.eq(now),
+        dsl::revoked_by.eq(revoked_by),
+        dsl::revoke_reason.eq(revoke_reason),
+    ))
+    .get_result(conn)
+    .optional()?;
+
+    Ok(revoked)
+}
+
+/// Lifts all active mutes of an account.
+///
+/// `revoked_by` is the account id of the moderator.
+/// Returns the mute that would have expired last or `None`, if the account was not muted.
+pub fn revoke_mute(
+    conn: &PgConnection,
+    account_id: i32,
+    revoked_by: Option<i32>,
+    revoke_reason: Option<String>,
+) -> Result<Option<models::Mute>> {
+    use schema::mutes::dsl;
+
+    let now = Utc::now().naive_utc();
+    let revoked: Vec<models::Mute> = diesel::update(
+        dsl::mutes
+            .filter(dsl::account_id.eq(account_id))
+            .filter(dsl::revoked_at.is_null())
+            .filter(dsl::expires_at.is_null().or(dsl::expires_at.gt(now))),
+    )
+    .set((
+        dsl::revoked_at.eq(now),
+        dsl::revoked_by.eq(revoked_by),
+        dsl::revoke_reason.eq(revoke_reason),
+    ))
+    .get_results(conn)?;
+
+    // permanent punishments expire last
+    Ok(revoked
+        .into_iter()
+        .max_by_key(|mute| (mute.expires_at.is_none(), mute.expires_at)))
+}
+
+/// Lists all active bans, IP bans and mutes that match the given filters, most recent first
+pub fn get_active_punishments(
+    conn: &PgConnection,
+    query: &GetPunishments,
+    with_ip: bool,
+) -> Result<Vec<Punishment>> {
+    let limit = query.limit.unwrap_or(100) as i64;
+    let now = Utc::now().naive_utc();
+    let is_kind = |kind: PunishmentKind| query.kind.map(|k| k == kind).unwrap_or(true);
+    let mut punishments = vec![];
+
+    if is_kind(PunishmentKind::Ban) {
+        use schema::bans::dsl;
+
+        let mut bans = dsl::bans
+            .filter(dsl::revoked_at.is_null())
+            .filter(dsl::expires_at.is_null().or(dsl::expires_at.gt(now)))
+            .into_boxed();
+        if let Some(account_id) = query.account_id {
+            bans = bans.filter(dsl::account_id.eq(account_id));
+        }
+        if let Some(ip) = &query.ip {
+            bans = bans.filter(dsl::ip.eq(ip));
+        }
+        if let Some(permanent) = query.permanent {
+            bans = if permanent {
+                bans.filter(dsl::expires_at.is_null())
             } else {
-                bans.iter().max_by_key(|ban| ban.expires_at).unwrap()
+                bans.filter(dsl::expires_at.is_not_null())
             };
-            for ban in bans.iter().filter(|filtered| filtered.id != max_ban.id) {
-                use schema::bans::dsl::*;
+        }
+        let bans: Vec<models::Ban> = bans.order(dsl::created_at.desc()).limit(limit).load(conn)?;
+        punishments.extend(bans.into_iter().map(|ban| ban.into_punishment(with_ip)));
+    }
 
-                diesel::delete(bans).filter(id.eq(ban.id)).execute(conn)?;
-            }
-            if let Some(expires_at) = max_ban.expires_at {
-                if Utc::now().naive_utc() > expires_at {
-                    use schema::bans::dsl::*;
-
-                    diesel::delete(bans)
-                        .filter(id.eq(max_ban.id))
-                        .execute(conn)?;
-                    return Ok(None);
-                }
-            }
-            Ok(Some(max_ban.clone()))
+    // IP bans don't belong to an account
+    if is_kind(PunishmentKind::IpBan) && query.account_id.is_none() {
+        use schema::ip_bans::dsl;
+
+        let mut ip_bans = dsl::ip_bans
+            .filter(dsl::revoked_at.is_null())
+            .filter(dsl::expires_at.is_null().or(dsl::expires_at.gt(now)))
+            .into_boxed();
+        if let Some(ip) = &query.ip {
+            ip_bans = ip_bans.filter(dsl::ip.eq(ip));
         }
-    } else {
-        Ok(None)
+        if let Some(permanent) = query.permanent {
+            ip_bans = if permanent {
+                ip_bans.filter(dsl::expires_at.is_null())
+            } else {
+                ip_bans.filter(dsl::expires_at.is_not_null())
+            };
+        }
+        let ip_bans: Vec<models::IpBan> = ip_bans
+            .order(dsl::created_at.desc())
+            .limit(limit)
+            .load(conn)?;
+        punishments.extend(
+            ip_bans
+                .into_iter()
+                .map(|ip_ban| ip_ban.into_punishment(with_ip)),
+        );
+    }
+
+    // mutes are not stored with an IP address
+    if is_kind(PunishmentKind::Mute) && query.ip.is_none() {
+        use schema::mutes::dsl;
+
+        let mut mutes = dsl::mutes
+            .filter(dsl::revoked_at.is_null())
+            .filter(dsl::expires_at.is_null().or(dsl::expires_at.gt(now)))
+            .into_boxed();
+        if let Some(account_id) = query.account_id {
+            mutes = mutes.filter(dsl::account_id.eq(account_id));
+        }
+        if let Some(permanent) = query.permanent {
+            mutes = if permanent {
+                mutes.filter(dsl::expires_at.is_null())
+            } else {
+                mutes.filter(dsl::expires_at.is_not_null())
+            };
+        }
+        let mutes: Vec<models::Mute> = mutes
+            .order(dsl::created_at.desc())
+            .limit(limit)
+            .load(conn)?;
+        punishments.extend(mutes.into_iter().map(|mute| mute.into_punishment()));
     }
+
+    punishments.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+    punishments.truncate(limit as usize);
+
+    Ok(punishments)
 }
 
-pub fn is_ip_banned(conn: &PgConnection, ip: &str) -> Result<Option<models::IpBan>> {
-    use schema::ip_bans;
+/// Lists all bans, mutes and warnings of an account including expired and revoked ones,
+/// most recent first
+pub fn get_punishment_history(
+    conn: &PgConnection,
+    account_id: i32,
+    with_ip: bool,
+) -> Result<Vec<Punishment>> {
+    let bans: Vec<models::Ban> = {
+        use schema::bans::dsl;
+
+        dsl::bans
+            .filter(dsl::account_id.eq(account_id))
+            .load(conn)?
+    };
+    let mutes: Vec<models::Mute> = {
+        use schema::mutes::dsl;
 
-    match ip_bans::table.find(ip).first(conn) {
-        Ok(ban) => {
-            let ban: models::IpBan = ban;
-            if let Some(expires_at) = ban.expires_at {
-                if Utc::now().naive_utc() > expires_at {
-                    use schema::ip_bans::dsl::*;
-
-                    diesel::delete(ip_bans)
-                        .filter(ip.eq(ban.ip))
-                        .execute(conn)?;
-                    return Ok(None);
-                }
-            }
+        dsl::mutes
+            .filter(dsl::account_id.eq(account_id))
+            .load(conn)?
+    };
 
-            Ok(Some(ban))
-        }
-        Err(diesel::result::Error::NotFound) => Ok(None),
-        Err(err) => Err(err.into()),
+    let warnings: Vec<models::Warning> = {
+        use schema::warnings::dsl;
+
+        dsl::warnings
+            .filter(dsl::account_id.eq(account_id))
+            .load(conn)?
+    };
+
+    let mut punishments: Vec<Punishment> = bans
+        .into_iter()
+        .map(|ban| ban.into_punishment(with_ip))
+        .chain(mutes.into_iter().map(|mute| mute.into_punishment()))
+        .chain(
+            warnings
+                .into_iter()
+                .map(|warning| warning.into_punishment()),
+        )
+        .collect();
+    punishments.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+
+    Ok(punishments)
+}
+
+pub fn warn_account(
+    conn: &PgConnection,
+    account_id: i32,
+    reason: Option<String>,
+    issued_by: Option<i32>,
+) -> Result<models::Warning> {
+    use schema::warnings;
+
+    let new_warning = models::NewWarning {
+        account_id,
+        reason,
+        issued_by,
+        created_at: Utc::now().naive_utc(),
+    };
+    let warning: models::Warning = diesel::insert_into(warnings::table)
+        .values(&new_warning)
+        .get_result(conn)?;
+
+    Ok(warning)
+}
+
+/// Returns all warnings of an account that have been issued after `since`
+pub fn get_warnings_since(
+    conn: &PgConnection,
+    account_id: i32,
+    since: NaiveDateTime,
+) -> Result<Vec<models::Warning>> {
+    use schema::warnings::dsl;
+
+    let warnings = dsl::warnings
+        .filter(dsl::account_id.eq(account_id))
+        .filter(dsl::created_at.gt(since))
+        .order(dsl::created_at.asc())
+        .load(conn)?;
+
+    Ok(warnings)
+}
+
+pub fn log_moderation_action(
+    conn: &PgConnection,
+    action: models::NewModerationAction,
+) -> Result<()> {
+    use schema::moderation_actions;
+
+    diesel::insert_into(moderation_actions::table)
+        .values(&action)
+        .execute(conn)?;
+
+    Ok(())
+}
+
+/// Lists the most recent moderation actions that match all given filters
+pub fn get_moderation_log(
+    conn: &PgConnection,
+    query: &GetModerationLog,
+    with_ip: bool,
+) -> Result<Vec<ModerationAction>> {
+    use schema::moderation_actions::dsl;
+
+    let mut actions = dsl::moderation_actions.into_boxed();
+    if let Some(from) = query.from {
+        actions = actions.filter(dsl::created_at.gt(from.naive_utc()));
+    }
+    if let Some(to) = query.to {
+        actions = actions.filter(dsl::created_at.lt(to.naive_utc()));
+    }
+    if let Some(action) = query.action {
+        actions = actions.filter(dsl::action.eq(action.as_str()));
     }
+    if let Some(source) = query.source {
+        actions = actions.filter(dsl::source.eq(source.as_str()));
+    }
+    if let Some(actor_account_id) = query.actor_account_id {
+        actions = actions.filter(dsl::actor_account_id.eq(actor_account_id));
+    }
+    if let Some(target_account_id) = query.target_account_id {
+        actions = actions.filter(dsl::target_account_id.eq(target_account_id));
+    }
+    if let Some(target_ip) = &query.target_ip {
+        actions = actions.filter(dsl::target_ip.eq(target_ip));
+    }
+    let actions: Vec<models::ModerationAction> = actions
+        .order((dsl::created_at.desc(), dsl::id.desc()))
+        .limit(query.limit.unwrap_or(100) as i64)
+        .load(conn)?;
+
+    actions
+        .into_iter()
+        .map(|action| action.into_moderation_action(with_ip))
+        .collect()
 }
 
-pub fn is_account_muted(conn: &PgConnection, account_id: i32) -> Result<Option<models::Mute>> {
-    let account = get_account(conn, account_id)?;
-
-    if let Ok(mutes) = models::Mute::belonging_to(&account).load::<models::Mute>(conn) {
-        if mutes.is_empty() {
-            Ok(None)
-        } else {
-            let max_mute =
-                if let Some(max_mute) = mutes.iter().find(|mute| mute.expires_at.is_none()) {
-                    max_mute
-                } else {
-                    mutes.iter().max_by_key(|mute| mute.expires_at).unwrap()
-                };
-            for mute in mutes.iter().filter(|filtered| filtered.id != max_mute.id) {
-                use schema::mutes::dsl::*;
+/// Stores a race time, if it is the best time of this account on this level.
+///
+/// Returns whether it is a new personal best.
+pub fn record_race_time(
+    conn: &PgConnection,
+    level: i32,
+    account_id: i32,
+    player_name: String,
+    time_ms: i32,
+) -> Result<bool> {
+    use diesel::sql_types::{Int4, Timestamp, Varchar};
+
+    // a single statement, so that concurrent finishes of the same account can't conflict
+    let updated = diesel::sql_query(
+        "INSERT INTO race_times (level, time_ms, player_name, finished_at, account_id) \
+        VALUES ($1, $2, $3, $4, $5) \
+        ON CONFLICT (level, account_id) DO UPDATE SET \
+            time_ms = excluded.time_ms, \
+            player_name = excluded.player_name, \
+            finished_at = excluded.finished_at \
+        WHERE excluded.time_ms < race_times.time_ms",
+    )
+    .bind::<Int4, _>(level)
+    .bind::<Int4, _>(time_ms)
+    .bind::<Varchar, _>(player_name)
+    .bind::<Timestamp, _>(Utc::now().naive_utc())
+    .bind::<Int4, _>(account_id)
+    .execute(conn)?;
+    Ok(updated > 0)
+}
 
-                diesel::delete(mutes).filter(id.eq(mute.id)).execute(conn)?;
-            }
-            if let Some(expires_at) = max_mute.expires_at {
-                if Utc::now().naive_utc() > expires_at {
-                    use schema::mutes::dsl::*;
-
-                    diesel::delete(mutes)
-                        .filter(id.eq(max_mute.id))
-                        .execute(conn)?;
-                    return Ok(None);
-                }
+pub fn get_leaderboard(
+    conn: &PgConnection,
+    level: i32,
+    limit: i64,
+) -> Result<Vec<models::RaceTime>> {
+    use schema::race_times::dsl;
+
+    Ok(dsl::race_times
+        .filter(dsl::level.eq(level))
+        .order((dsl::time_ms.asc(), dsl::finished_at.asc()))
+        .limit(limit)
+        .load(conn)?)
+}
+
+/// Adds violations, that have not been saved yet, to the flagged accounts.
+///
+/// Only the most recent `max_evidence` violations are kept per account.
+pub fn save_flagged_accounts(
+    conn: &PgConnection,
+    flagged_accounts: Vec<sm64js_common::FlaggedAccount>,
+    max_evidence: i64,
+) -> Result<()> {
+    use schema::{cheat_violations, flagged_accounts};
+
+    conn.transaction(|| {
+        for flagged_account in flagged_accounts {
+            let account_id = flagged_account.account_id;
+            diesel::insert_into(flagged_accounts::table)
+                .values(&models::FlaggedAccount {
+                    account_id,
+                    total_violations: flagged_account.total_violations as i32,
+                })
+                .on_conflict(flagged_accounts::account_id)
+                .do_update()
+                .set(
+                    flagged_accounts::total_violations.eq(flagged_accounts::total_violations
+                        + excluded(flagged_accounts::total_violations)),
+                )
+                .execute(conn)?;
+
+            let new_violations: Vec<_> = flagged_account
+                .violations
+                .into_iter()
+                .map(|violation| models::NewCheatViolation {
+                    account_id,
+                    validator: violation.validator,
+                    evidence: violation.evidence,
+                    created_at: violation.date_time,
+                })
+                .collect();
+            diesel::insert_into(cheat_violations::table)
+                .values(&new_violations)
+                .execute(conn)?;
+
+            let outdated: Vec<i32> = cheat_violations::table
+                .select(cheat_violations::id)
+                .filter(cheat_violations::account_id.eq(account_id))
+                .order(cheat_violations::created_at.desc())
+                .offset(max_evidence)
+                .load(conn)?;
+            if !outdated.is_empty() {
+                diesel::delete(
+                    cheat_violations::table.filter(cheat_violations::id.eq_any(outdated)),
+                )
+                .execute(conn)?;
             }
-            Ok(Some(max_mute.clone()))
         }
-    } else {
-        Ok(None)
+        Ok(())
+    })
+}
+
+pub fn get_flagged_accounts(conn: &PgConnection) -> Result<Vec<sm64js_common::FlaggedAccount>> {
+    use schema::{cheat_violations, flagged_accounts};
+
+    let flagged_accounts: Vec<models::FlaggedAccount> = flagged_accounts::table.load(conn)?;
+    let violations = models::CheatViolation::belonging_to(&flagged_accounts)
+        .order(cheat_violations::created_at.asc())
+        .load::<models::CheatViolation>(conn)?
+        .grouped_by(&flagged_accounts);
+    Ok(flagged_accounts
+        .into_iter()
+        .zip(violations)
+        .map(
+            |(flagged_account, violations)| sm64js_common::FlaggedAccount {
+                account_id: flagged_account.account_id,
+                total_violations: flagged_account.total_violations as u32,
+                violations: violations.into_iter().map(Into::into).collect(),
+            },
+        )
+        .collect())
+}
+
+/// Maximum number of rows per insert statement
+const CHAT_MESSAGES_CHUNK_SIZE: usize = 1000;
+
+pub fn insert_chat_messages(
+    conn: &PgConnection,
+    messages: Vec<models::NewChatMessage>,
+) -> Result<usize> {
+    use schema::chat_messages;
+
+    conn.transaction(|| {
+        let mut inserted = 0;
+        for chunk in messages.chunks(CHAT_MESSAGES_CHUNK_SIZE) {
+            inserted += diesel::insert_into(chat_messages::table)
+                .values(chunk)
+                .execute(conn)?;
+        }
+        Ok(inserted)
+    })
+}
+
+/// Maximum duration of a chat query that filters by a regular expression
+const CHAT_REGEX_TIMEOUT_MS: u32 = 5000;
+
+/// Returns the most recent chat messages that match the query in chronological order.
+///
+/// Only messages older than `cursor` are returned, if one is given.
+/// The cursor to the next page is returned as well, if there are older messages.
+pub fn get_chat_messages(
+    conn: &PgConnection,
+    query: &GetChat,
+    cursor: Option<ChatCursor>,
+) -> Result<(Vec<models::ChatMessage>, Option<ChatCursor>)> {
+    use diesel::{dsl::sql, sql_types};
+    use schema::chat_messages::dsl;
+
+    // a message has either a discord id or a google id
+    if query.discord_id.is_some() && query.google_id.is_some() {
+        return Ok((vec![], None));
+    }
+
+    let mut messages = dsl::chat_messages.into_boxed();
+    if let Some(from) = query.from {
+        messages = messages.filter(dsl::date_time.gt(from.naive_utc()));
+    }
+    if let Some(to) = query.to {
+        messages = messages.filter(dsl::date_time.lt(to.naive_utc()));
+    }
+    if let Some(cursor) = cursor {
+        messages = messages.filter(
+            dsl::date_time.lt(cursor.date_time).or(dsl::date_time
+                .eq(cursor.date_time)
+                .and(dsl::id.lt(cursor.id))),
+        );
+    }
+    if let Some(player_name) = &query.player_name {
+        messages = messages.filter(dsl::player_name.eq(player_name));
+    }
+    if let Some(discord_id) = &query.discord_id {
+        messages = messages.filter(dsl::discord_id.eq(discord_id));
+    }
+    if let Some(google_id) = &query.google_id {
+        messages = messages.filter(dsl::google_id.eq(google_id));
+    }
+    if let Some(account_id) = query.account_id {
+        messages = messages.filter(dsl::account_id.eq(account_id));
+    }
+    if let Some(level) = query.level {
+        messages = messages.filter(dsl::level.eq(level as i32));
+    }
+    if let Some(room_id) = query.room_id {
+        messages = messages.filter(dsl::room_id.eq(i64::from(room_id)));
+    }
+    if let Some(is_censored) = query.is_censored {
+        messages = messages.filter(dsl::is_censored.eq(is_censored));
     }
+    if let Some(is_spam) = query.is_spam {
+        messages = messages.filter(dsl::is_spam.eq(is_spam));
+    }
+    if let Some(is_screaming) = query.is_screaming {
+        messages = messages.filter(dsl::is_screaming.eq(is_screaming));
+    }
+    if let Some(ip) = &query.ip {
+        messages = messages.filter(dsl::ip.eq(ip));
+    }
+    if let Some(search) = &query.search {
+        messages = messages.filter(dsl::message.ilike(format!("%{}%", escape_like(search))));
+    }
+    if let Some(regex) = &query.regex {
+        messages = messages
+            .filter(sql::<sql_types::Bool>("message ~ ").bind::<sql_types::Text, _>(regex.clone()));
+    }
+
+    let limit = query.limit.unwrap_or(100) as usize;
+    let messages = messages
+        .order((dsl::date_time.desc(), dsl::id.desc()))
+        .limit(limit as i64 + 1);
+    let mut messages: Vec<models::ChatMessage> = conn.transaction::<_, DbError, _>(|| {
+        if let Some(regex) = &query.regex {
+            // the regex is compiled by Postgres, whose syntax differs from other regex engines
+            diesel::select(sql::<sql_types::Bool>("'' ~ ").bind::<sql_types::Text, _>(regex))
+                .execute(conn)
+                .map_err(|err| match err {
+                    diesel::result::Error::DatabaseError(_, info) => {
+                        DbError::InvalidRegex(info.message().to_string())
+                    }
+                    err => err.into(),
+                })?;
+            // regular expressions can't use an index and might backtrack a lot
+            diesel::sql_query(format!(
+                "SET LOCAL statement_timeout = {}",
+                CHAT_REGEX_TIMEOUT_MS
+            ))
+            .execute(conn)?;
+        }
+        messages.load(conn).map_err(|err| match err {
+            diesel::result::Error::DatabaseError(_, info)
+                if info.message().contains("statement timeout") =>
+            {
+                DbError::QueryTimeout
+            }
+            err => err.into(),
+        })
+    })?;
+    let next_cursor = if messages.len() > limit {
+        messages.truncate(limit);
+        messages.last().map(|msg| ChatCursor {
+            date_time: msg.date_time,
+            id: msg.id,
+        })
+    } else {
+        None
+    };
+    messages.reverse();
+    Ok((messages, next_cursor))
+}
+
+/// Returns the most recent `limit` chat messages of each account in chronological order.
+pub fn get_recent_chat_messages(
+    conn: &PgConnection,
+    account_ids: Vec<i32>,
+    limit: u32,
+) -> Result<Vec<models::ChatMessage>> {
+    use diesel::sql_types::{Array, Int4, Int8};
+
+    Ok(diesel::sql_query(
+        "SELECT * FROM ( \
+            SELECT *, ROW_NUMBER() OVER ( \
+                PARTITION BY account_id ORDER BY date_time DESC, id DESC \
+            ) AS row_number FROM chat_messages WHERE account_id = ANY($1) \
+        ) AS recent_chat_messages WHERE row_number <= $2 ORDER BY date_time, id",
+    )
+    .bind::<Array<Int4>, _>(account_ids)
+    .bind::<Int8, _>(i64::from(limit))
+    .load(conn)?)
+}
+
+/// Escapes all wildcards of a LIKE pattern
+fn escape_like(s: &str) -> String {
+    s.replace('\\', r"\\")
+        .replace('%', r"\%")
+        .replace('_', r"\_")
+}
+
+/// Deletes all chat messages older than `before` and returns the number of deleted messages.
+pub fn delete_chat_messages_before(conn: &PgConnection, before: NaiveDateTime) -> Result<usize> {
+    use schema::chat_messages::dsl;
+
+    Ok(diesel::delete(dsl::chat_messages.filter(dsl::date_time.lt(before))).execute(conn)?)
 }
 
 fn add_geolocation(conn: &PgConnection, geolocation: models::NewGeolocation) -> Result<()> {
@@ -720,6 +1313,12 @@ pub enum DbError {
     Banned(models::Ban),
     #[error("[Diesel]: {0}")]
     Diesel(#[from] diesel::result::Error),
+    #[error("[InvalidModerationAction]: {0}")]
+    InvalidModerationAction(String),
+    #[error("[InvalidRegex]: {0}")]
+    InvalidRegex(String),
+    #[error("[QueryTimeout]")]
+    QueryTimeout,
 }
 
 impl ResponseError for DbError {
@@ -729,7 +1328,11 @@ impl ResponseError for DbError {
                 HttpResponse::new(StatusCode::BAD_REQUEST)
             }
             Self::Banned(_) => HttpResponse::new(StatusCode::FORBIDDEN),
-            Self::Diesel(_) => HttpResponse::new(StatusCode::INTERNAL_SERVER_ERROR),
+            Self::InvalidRegex(_) => HttpResponse::new(StatusCode::BAD_REQUEST),
+            Self::QueryTimeout => HttpResponse::new(StatusCode::REQUEST_TIMEOUT),
+            Self::Diesel(_) | Self::InvalidModerationAction(_) => {
+                HttpResponse::new(StatusCode::INTERNAL_SERVER_ERROR)
+            }
         };
         res.set_body(Body::from(format!("{}", self)))
     }
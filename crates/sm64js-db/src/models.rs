@@ -173,6 +173,29 @@ pub struct Ban {
     pub reason: Option<String>,
     pub expires_at: Option<NaiveDateTime>,
     pub account_id: Option<i32>,
+    pub created_at: NaiveDateTime,
+    #[serde(skip_serializing)]
+    pub revoked_at: Option<NaiveDateTime>,
+    #[serde(skip_serializing)]
+    pub revoked_by: Option<i32>,
+    #[serde(skip_serializing)]
+    pub revoke_reason: Option<String>,
+}
+
+impl Ban {
+    pub fn into_punishment(self, with_ip: bool) -> sm64js_common::Punishment {
+        sm64js_common::Punishment {
+            kind: sm64js_common::PunishmentKind::Ban,
+            account_id: self.account_id,
+            ip: if with_ip { Some(self.ip) } else { None },
+            reason: self.reason,
+            created_at: self.created_at,
+            expires_at: self.expires_at,
+            revoked_at: self.revoked_at,
+            revoked_by: self.revoked_by,
+            revoke_reason: self.revoke_reason,
+        }
+    }
 }
 
 // TODO implement Display trait for better human readable error message on ban
@@ -185,15 +208,45 @@ pub struct NewBan {
     pub account_id: Option<i32>,
 }
 
-#[derive(
-    AsChangeset, Associations, Clone, Debug, Identifiable, Insertable, Queryable, Serialize,
-)]
+#[derive(Associations, Clone, Debug, Identifiable, Queryable, Serialize)]
 #[serde(rename_all = "camelCase")]
-#[primary_key(ip)]
 pub struct IpBan {
     pub ip: String,
     pub reason: Option<String>,
     pub expires_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+    #[serde(skip_serializing)]
+    pub revoked_at: Option<NaiveDateTime>,
+    #[serde(skip_serializing)]
+    pub revoked_by: Option<i32>,
+    #[serde(skip_serializing)]
+    pub revoke_reason: Option<String>,
+    #[serde(skip_serializing)]
+    pub id: i32,
+}
+
+#[derive(Clone, Debug, Insertable)]
+#[table_name = "ip_bans"]
+pub struct NewIpBan {
+    pub ip: String,
+    pub reason: Option<String>,
+    pub expires_at: Option<NaiveDateTime>,
+}
+
+impl IpBan {
+    pub fn into_punishment(self, with_ip: bool) -> sm64js_common::Punishment {
+        sm64js_common::Punishment {
+            kind: sm64js_common::PunishmentKind::IpBan,
+            account_id: None,
+            ip: if with_ip { Some(self.ip) } else { None },
+            reason: self.reason,
+            created_at: self.created_at,
+            expires_at: self.expires_at,
+            revoked_at: self.revoked_at,
+            revoked_by: self.revoked_by,
+            revoke_reason: self.revoke_reason,
+        }
+    }
 }
 
 #[derive(Associations, Clone, Debug, Identifiable, Insertable, Queryable, Serialize)]
@@ -205,6 +258,29 @@ pub struct Mute {
     pub reason: Option<String>,
     pub expires_at: Option<NaiveDateTime>,
     pub account_id: i32,
+    pub created_at: NaiveDateTime,
+    #[serde(skip_serializing)]
+    pub revoked_at: Option<NaiveDateTime>,
+    #[serde(skip_serializing)]
+    pub revoked_by: Option<i32>,
+    #[serde(skip_serializing)]
+    pub revoke_reason: Option<String>,
+}
+
+impl Mute {
+    pub fn into_punishment(self) -> sm64js_common::Punishment {
+        sm64js_common::Punishment {
+            kind: sm64js_common::PunishmentKind::Mute,
+            account_id: Some(self.account_id),
+            ip: None,
+            reason: self.reason,
+            created_at: self.created_at,
+            expires_at: self.expires_at,
+            revoked_at: self.revoked_at,
+            revoked_by: self.revoked_by,
+            revoke_reason: self.revoke_reason,
+        }
+    }
 }
 
 // TODO implement Display trait for better human readable error message on ban
@@ -216,6 +292,256 @@ pub struct NewMute {
     pub account_id: i32,
 }
 
+#[derive(Associations, Clone, Debug, Identifiable, Queryable)]
+#[belongs_to(Account)]
+pub struct Warning {
+    pub id: i32,
+    pub account_id: i32,
+    pub reason: Option<String>,
+    pub issued_by: Option<i32>,
+    pub created_at: NaiveDateTime,
+}
+
+impl Warning {
+    pub fn into_punishment(self) -> sm64js_common::Punishment {
+        sm64js_common::Punishment {
+            kind: sm64js_common::PunishmentKind::Warning,
+            account_id: Some(self.account_id),
+            ip: None,
+            reason: self.reason,
+            created_at: self.created_at,
+            expires_at: None,
+            revoked_at: None,
+            revoked_by: None,
+            revoke_reason: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Insertable)]
+#[table_name = "warnings"]
+pub struct NewWarning {
+    pub account_id: i32,
+    pub reason: Option<String>,
+    pub issued_by: Option<i32>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Associations, Clone, Debug, Identifiable, Queryable, Serialize)]
+#[belongs_to(Account)]
+#[serde(rename_all = "camelCase")]
+pub struct RaceTime {
+    #[serde(skip_serializing)]
+    pub id: i32,
+    pub level: i32,
+    pub time_ms: i32,
+    pub player_name: String,
+    pub finished_at: NaiveDateTime,
+    pub account_id: i32,
+}
+
+#[derive(Associations, Clone, Debug, Identifiable, Insertable, Queryable)]
+#[belongs_to(Account)]
+#[primary_key(account_id)]
+pub struct FlaggedAccount {
+    pub account_id: i32,
+    pub total_violations: i32,
+}
+
+#[derive(Associations, Clone, Debug, Identifiable, Queryable)]
+#[belongs_to(FlaggedAccount, foreign_key = "account_id")]
+pub struct CheatViolation {
+    pub id: i32,
+    pub account_id: i32,
+    pub validator: String,
+    pub evidence: String,
+    pub created_at: NaiveDateTime,
+}
+
+impl From<CheatViolation> for sm64js_common::CheatViolation {
+    fn from(violation: CheatViolation) -> Self {
+        sm64js_common::CheatViolation {
+            validator: violation.validator,
+            evidence: violation.evidence,
+            date_time: violation.created_at,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Insertable)]
+#[table_name = "cheat_violations"]
+pub struct NewCheatViolation {
+    pub account_id: i32,
+    pub validator: String,
+    pub evidence: String,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Associations, Clone, Debug, Identifiable, Queryable, QueryableByName)]
+#[belongs_to(Account)]
+#[table_name = "chat_messages"]
+pub struct ChatMessage {
+    pub id: i32,
+    pub message: String,
+    pub date_time: NaiveDateTime,
+    pub player_name: Option<String>,
+    pub account_id: i32,
+    pub discord_id: Option<String>,
+    pub google_id: Option<String>,
+    pub ip: Option<String>,
+    pub is_escaped: bool,
+    pub is_censored: bool,
+    pub is_spam: bool,
+    pub is_excessive_spam: bool,
+    pub is_screaming: bool,
+    pub level: Option<i32>,
+    /// Room ids use the full range of `u32`
+    pub room_id: Option<i64>,
+    pub room_name: Option<String>,
+}
+
+impl ChatMessage {
+    pub fn into_chat_message(
+        self,
+        with_player_info: bool,
+        with_ip: bool,
+    ) -> sm64js_common::ChatMessage {
+        let flag = |value: bool| if value { Some(value) } else { None };
+        sm64js_common::ChatMessage {
+            message: self.message,
+            timestamp: self.date_time.timestamp(),
+            date_time: self.date_time,
+            player_name: self.player_name.filter(|_| with_player_info),
+            account_id: self.account_id,
+            discord_id: self.discord_id.filter(|_| with_player_info),
+            google_id: self.google_id.filter(|_| with_player_info),
+            ip: self.ip.filter(|_| with_ip),
+            level: self.level.map(|level| level as u32),
+            room_id: self.room_id.map(|room_id| room_id as u32),
+            room_name: self.room_name,
+            is_escaped: flag(self.is_escaped),
+            is_censored: flag(self.is_censored),
+            is_spam: flag(self.is_spam),
+            is_excessive_spam: flag(self.is_excessive_spam),
+            is_screaming: flag(self.is_screaming),
+            normalized: String::new(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Insertable)]
+#[table_name = "chat_messages"]
+pub struct NewChatMessage {
+    pub message: String,
+    pub date_time: NaiveDateTime,
+    pub player_name: Option<String>,
+    pub account_id: i32,
+    pub discord_id: Option<String>,
+    pub google_id: Option<String>,
+    pub ip: Option<String>,
+    pub is_escaped: bool,
+    pub is_censored: bool,
+    pub is_spam: bool,
+    pub is_excessive_spam: bool,
+    pub is_screaming: bool,
+    pub level: Option<i32>,
+    pub room_id: Option<i64>,
+    pub room_name: Option<String>,
+}
+
+impl From<sm64js_common::ChatMessage> for NewChatMessage {
+    fn from(msg: sm64js_common::ChatMessage) -> Self {
+        NewChatMessage {
+            message: msg.message,
+            date_time: msg.date_time,
+            player_name: msg.player_name,
+            account_id: msg.account_id,
+            discord_id: msg.discord_id,
+            google_id: msg.google_id,
+            ip: msg.ip,
+            is_escaped: msg.is_escaped.unwrap_or_default(),
+            is_censored: msg.is_censored.unwrap_or_default(),
+            is_spam: msg.is_spam.unwrap_or_default(),
+            is_excessive_spam: msg.is_excessive_spam.unwrap_or_default(),
+            is_screaming: msg.is_screaming.unwrap_or_default(),
+            level: msg.level.map(|level| level as i32),
+            room_id: msg.room_id.map(i64::from),
+            room_name: msg.room_name,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Identifiable, Queryable)]
+pub struct ModerationAction {
+    pub id: i32,
+    pub action: String,
+    pub source: String,
+    pub actor_account_id: Option<i32>,
+    pub target_account_id: Option<i32>,
+    pub target_ip: Option<String>,
+    pub expires_at: Option<NaiveDateTime>,
+    pub reason: Option<String>,
+    pub created_at: NaiveDateTime,
+}
+
+impl ModerationAction {
+    pub fn into_moderation_action(
+        self,
+        with_ip: bool,
+    ) -> Result<sm64js_common::ModerationAction, crate::DbError> {
+        Ok(sm64js_common::ModerationAction {
+            id: self.id,
+            action: self
+                .action
+                .parse()
+                .map_err(crate::DbError::InvalidModerationAction)?,
+            source: self
+                .source
+                .parse()
+                .map_err(crate::DbError::InvalidModerationAction)?,
+            actor_account_id: self.actor_account_id,
+            target_account_id: self.target_account_id,
+            target_ip: if with_ip { self.target_ip } else { None },
+            expires_at: self.expires_at,
+            reason: self.reason,
+            created_at: self.created_at,
+        })
+    }
+}
+
+#[derive(Clone, Debug, Insertable)]
+#[table_name = "moderation_actions"]
+pub struct NewModerationAction {
+    pub action: String,
+    pub source: String,
+    pub actor_account_id: Option<i32>,
+    pub target_account_id: Option<i32>,
+    pub target_ip: Option<String>,
+    pub expires_at: Option<NaiveDateTime>,
+    pub reason: Option<String>,
+    pub created_at: NaiveDateTime,
+}
+
+impl NewModerationAction {
+    /// `actor_account_id` is the account id of the moderator or `None` for automatic actions
+    pub fn new(
+        action: sm64js_common::ModerationActionKind,
+        source: sm64js_common::ModerationSource,
+        actor_account_id: Option<i32>,
+    ) -> Self {
+        NewModerationAction {
+            action: action.as_str().to_string(),
+            source: source.as_str().to_string(),
+            actor_account_id,
+            target_account_id: None,
+            target_ip: None,
+            expires_at: None,
+            reason: None,
+            created_at: Utc::now().naive_utc(),
+        }
+    }
+}
+
 #[derive(Associations, Clone, Debug, Identifiable, Insertable, Queryable)]
 #[belongs_to(DiscordSession, GoogleSession)]
 pub struct Geolocation {
@@ -13,6 +13,41 @@ table! {
         reason -> Nullable<Varchar>,
         expires_at -> Nullable<Timestamp>,
         account_id -> Nullable<Int4>,
+        created_at -> Timestamp,
+        revoked_at -> Nullable<Timestamp>,
+        revoked_by -> Nullable<Int4>,
+        revoke_reason -> Nullable<Varchar>,
+    }
+}
+
+table! {
+    chat_messages (id) {
+        id -> Int4,
+        message -> Varchar,
+        date_time -> Timestamp,
+        player_name -> Nullable<Varchar>,
+        account_id -> Int4,
+        discord_id -> Nullable<Varchar>,
+        google_id -> Nullable<Varchar>,
+        ip -> Nullable<Varchar>,
+        is_escaped -> Bool,
+        is_censored -> Bool,
+        is_spam -> Bool,
+        is_excessive_spam -> Bool,
+        is_screaming -> Bool,
+        level -> Nullable<Int4>,
+        room_id -> Nullable<Int8>,
+        room_name -> Nullable<Varchar>,
+    }
+}
+
+table! {
+    cheat_violations (id) {
+        id -> Int4,
+        account_id -> Int4,
+        validator -> Varchar,
+        evidence -> Varchar,
+        created_at -> Timestamp,
     }
 }
 
@@ -47,6 +82,13 @@ table! {
     }
 }
 
+table! {
+    flagged_accounts (account_id) {
+        account_id -> Int4,
+        total_violations -> Int4,
+    }
+}
+
 table! {
     geolocations (id) {
         id -> Int4,
@@ -84,10 +126,29 @@ table! {
 }
 
 table! {
-    ip_bans (ip) {
+    ip_bans (id) {
         ip -> Varchar,
         reason -> Nullable<Varchar>,
         expires_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+        revoked_at -> Nullable<Timestamp>,
+        revoked_by -> Nullable<Int4>,
+        revoke_reason -> Nullable<Varchar>,
+        id -> Int4,
+    }
+}
+
+table! {
+    moderation_actions (id) {
+        id -> Int4,
+        action -> Varchar,
+        source -> Varchar,
+        actor_account_id -> Nullable<Int4>,
+        target_account_id -> Nullable<Int4>,
+        target_ip -> Nullable<Varchar>,
+        expires_at -> Nullable<Timestamp>,
+        reason -> Nullable<Varchar>,
+        created_at -> Timestamp,
     }
 }
 
@@ -97,27 +158,63 @@ table! {
         reason -> Nullable<Varchar>,
         expires_at -> Nullable<Timestamp>,
         account_id -> Int4,
+        created_at -> Timestamp,
+        revoked_at -> Nullable<Timestamp>,
+        revoked_by -> Nullable<Int4>,
+        revoke_reason -> Nullable<Varchar>,
+    }
+}
+
+table! {
+    race_times (id) {
+        id -> Int4,
+        level -> Int4,
+        time_ms -> Int4,
+        player_name -> Varchar,
+        finished_at -> Timestamp,
+        account_id -> Int4,
+    }
+}
+
+table! {
+    warnings (id) {
+        id -> Int4,
+        account_id -> Int4,
+        reason -> Nullable<Varchar>,
+        issued_by -> Nullable<Int4>,
+        created_at -> Timestamp,
     }
 }
 
 joinable!(bans -> accounts (account_id));
+joinable!(chat_messages -> accounts (account_id));
+joinable!(cheat_violations -> flagged_accounts (account_id));
 joinable!(discord_accounts -> accounts (account_id));
 joinable!(discord_sessions -> discord_accounts (discord_account_id));
+joinable!(flagged_accounts -> accounts (account_id));
 joinable!(geolocations -> bans (ban_id));
 joinable!(geolocations -> discord_sessions (discord_session_id));
 joinable!(geolocations -> google_sessions (google_session_id));
 joinable!(google_accounts -> accounts (account_id));
 joinable!(google_sessions -> google_accounts (google_account_id));
 joinable!(mutes -> accounts (account_id));
+joinable!(race_times -> accounts (account_id));
+joinable!(warnings -> accounts (account_id));
 
 allow_tables_to_appear_in_same_query!(
     accounts,
     bans,
+    chat_messages,
+    cheat_violations,
     discord_accounts,
     discord_sessions,
+    flagged_accounts,
     geolocations,
     google_accounts,
     google_sessions,
     ip_bans,
+    moderation_actions,
     mutes,
+    race_times,
+    warnings,
 );
@@ -1,5 +1,5 @@
 use once_cell::sync::OnceCell;
-use std::env;
+use std::{env, fmt, str::FromStr};
 
 pub static GOOGLE_CLIENT_ID: OnceCell<String> = OnceCell::new();
 pub static GOOGLE_CLIENT_SECRET: OnceCell<String> = OnceCell::new();
@@ -10,6 +10,26 @@ pub static REDIRECT_URI: OnceCell<String> = OnceCell::new();
 pub static DATABASE_URL: OnceCell<String> = OnceCell::new();
 pub static ENABLE_PLAYER_LIST: OnceCell<bool> = OnceCell::new();
 pub static COOKIE_SAME_SITE_NONE: OnceCell<bool> = OnceCell::new();
+pub static ROOMS_CONFIG: OnceCell<String> = OnceCell::new();
+pub static ROOMS_CONFIG_WATCH: OnceCell<bool> = OnceCell::new();
+pub static ANTICHEAT_RESPONSE: OnceCell<CheatResponse> = OnceCell::new();
+pub static MAX_DECODE_ERRORS: OnceCell<u32> = OnceCell::new();
+pub static MAX_DECOMPRESSED_SIZE: OnceCell<usize> = OnceCell::new();
+pub static RATE_LIMIT_MARIO: OnceCell<u32> = OnceCell::new();
+pub static RATE_LIMIT_SKIN: OnceCell<u32> = OnceCell::new();
+pub static RATE_LIMIT_GRAB_FLAG: OnceCell<u32> = OnceCell::new();
+pub static RATE_LIMIT_ATTACK: OnceCell<u32> = OnceCell::new();
+pub static MAX_RATE_LIMIT_VIOLATIONS: OnceCell<u32> = OnceCell::new();
+pub static TICK_RATE: OnceCell<u32> = OnceCell::new();
+pub static TICK_POLICY: OnceCell<TickPolicy> = OnceCell::new();
+pub static GAME_MODES_RATE: OnceCell<u32> = OnceCell::new();
+pub static SHUTDOWN_COUNTDOWN: OnceCell<u64> = OnceCell::new();
+pub static MAINTENANCE_MODE: OnceCell<bool> = OnceCell::new();
+pub static CHAT_FLUSH_INTERVAL_MS: OnceCell<u64> = OnceCell::new();
+pub static CHAT_RETENTION_DAYS: OnceCell<i64> = OnceCell::new();
+pub static CHAT_CACHE_SIZE: OnceCell<usize> = OnceCell::new();
+pub static CHAT_FILTERS_CONFIG: OnceCell<String> = OnceCell::new();
+pub static CHAT_FILTERS_CONFIG_WATCH: OnceCell<bool> = OnceCell::new();
 
 #[cfg(debug_assertions)]
 pub static DEV_ACCOUNT_ID: i32 = -1337;
@@ -128,4 +148,112 @@ pub fn load() {
     } else {
         COOKIE_SAME_SITE_NONE.set(false).unwrap();
     }
+
+    load_var(&ROOMS_CONFIG, "ROOMS_CONFIG", "rooms.ron".to_string());
+    load_var(&ROOMS_CONFIG_WATCH, "ROOMS_CONFIG_WATCH", false);
+    load_var(
+        &ANTICHEAT_RESPONSE,
+        "ANTICHEAT_RESPONSE",
+        CheatResponse::Flag,
+    );
+    load_var(&MAX_DECODE_ERRORS, "MAX_DECODE_ERRORS", 10);
+    load_var(&MAX_DECOMPRESSED_SIZE, "MAX_DECOMPRESSED_SIZE", 256 * 1024);
+    load_var(&RATE_LIMIT_MARIO, "RATE_LIMIT_MARIO", 60);
+    load_var(&RATE_LIMIT_SKIN, "RATE_LIMIT_SKIN", 5);
+    load_var(&RATE_LIMIT_GRAB_FLAG, "RATE_LIMIT_GRAB_FLAG", 10);
+    load_var(&RATE_LIMIT_ATTACK, "RATE_LIMIT_ATTACK", 10);
+    load_var(&MAX_RATE_LIMIT_VIOLATIONS, "MAX_RATE_LIMIT_VIOLATIONS", 300);
+    load_var(&TICK_RATE, "TICK_RATE", 30);
+    load_var(&TICK_POLICY, "TICK_POLICY", TickPolicy::CatchUp);
+    load_var(&GAME_MODES_RATE, "GAME_MODES_RATE", 30);
+    load_var(&SHUTDOWN_COUNTDOWN, "SHUTDOWN_COUNTDOWN", 30);
+    load_var(&MAINTENANCE_MODE, "MAINTENANCE_MODE", false);
+    load_var(&CHAT_FLUSH_INTERVAL_MS, "CHAT_FLUSH_INTERVAL_MS", 1000);
+    load_var(&CHAT_RETENTION_DAYS, "CHAT_RETENTION_DAYS", 90);
+    load_var(&CHAT_CACHE_SIZE, "CHAT_CACHE_SIZE", 1000);
+    load_var(
+        &CHAT_FILTERS_CONFIG,
+        "CHAT_FILTERS_CONFIG",
+        "chat_filters.ron".to_string(),
+    );
+    load_var(
+        &CHAT_FILTERS_CONFIG_WATCH,
+        "CHAT_FILTERS_CONFIG_WATCH",
+        false,
+    );
+}
+
+/// Parses the environment variable `key` into `var`.
+///
+/// Falls back to `default`, if the variable is not set or invalid.
+fn load_var<T>(var: &OnceCell<T>, key: &str, default: T)
+where
+    T: FromStr,
+    T::Err: fmt::Debug,
+{
+    let value = match env::var(key) {
+        Ok(value) => value.parse().unwrap_or_else(|err| {
+            eprintln!("Invalid {}: {:?}", key, err);
+            default
+        }),
+        Err(_) => default,
+    };
+    if var.set(value).is_err() {
+        panic!("{} has already been loaded", key);
+    }
+}
+
+/// What happens to ticks of the game loop that have been missed, because a tick took too long
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TickPolicy {
+    /// Run missed ticks immediately until the scheduler caught up
+    CatchUp,
+    /// Skip missed ticks and continue with the next one on time
+    Skip,
+}
+
+impl FromStr for TickPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "catch_up" => Ok(Self::CatchUp),
+            "skip" => Ok(Self::Skip),
+            _ => Err(format!(
+                "unknown tick policy \"{}\". Expected one of catch_up or skip",
+                s
+            )),
+        }
+    }
+}
+
+/// What happens to an update that failed anti-cheat validation.
+/// Each response also includes all responses before it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CheatResponse {
+    /// Flag the account, but accept the update
+    Flag,
+    /// Ignore the update
+    Drop,
+    /// Reset the client to its last accepted state
+    RubberBand,
+    /// Disconnect the client
+    Kick,
+}
+
+impl FromStr for CheatResponse {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "flag" => Ok(Self::Flag),
+            "drop" => Ok(Self::Drop),
+            "rubber_band" => Ok(Self::RubberBand),
+            "kick" => Ok(Self::Kick),
+            _ => Err(format!(
+                "unknown anti-cheat response \"{}\". Expected one of flag, drop, rubber_band or kick",
+                s
+            )),
+        }
+    }
 }
@@ -0,0 +1,375 @@
+use crate::interest;
+
+use chrono::Utc;
+use parking_lot::Mutex;
+use sm64js_common::{CheatViolation, FlaggedAccount};
+use sm64js_proto::MarioMsg;
+use std::{collections::HashMap, time::Duration};
+
+pub use sm64js_env::CheatResponse;
+
+/// Duration of a single frame in Super Mario 64
+const FRAME: Duration = Duration::from_nanos(33_333_333);
+
+/// Maximum health, which equals 8 full health segments
+const MAX_HEALTH: u32 = 0x880;
+
+const ACT_GROUP_MASK: u32 = 0x1C0;
+const ACT_GROUP_AIRBORNE: u32 = 0x080;
+/// Warps, spawns and deaths
+const ACT_GROUP_CUTSCENE: u32 = 0x100;
+
+/// Maximum distance per frame on the ground or in water
+const MAX_SPEED: f32 = 150.;
+/// Maximum distance per frame while airborne, e.g. after being shot from a cannon
+const MAX_AIRBORNE_SPEED: f32 = 250.;
+/// Maximum distance between two updates, regardless of how much time has passed
+const MAX_TELEPORT_DISTANCE: f32 = 8000.;
+
+/// How many violations are kept as evidence per account
+pub const MAX_EVIDENCE: usize = 20;
+
+/// After this many consecutive rejected updates, which is about a second of updates,
+/// the next update is accepted and only flagged
+pub const MAX_REJECTED_UPDATES: u32 = 30;
+
+/// Checks a single update of a Mario.
+pub trait Validator: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Returns the evidence, if the update is invalid.
+    fn validate(&self, update: &MarioUpdate) -> Option<String>;
+}
+
+pub struct MarioUpdate<'a> {
+    pub socket_id: u32,
+    /// Last accepted update. Not set, if this is the first update in a level
+    pub prev: Option<&'a MarioMsg>,
+    pub next: &'a MarioMsg,
+    /// Time since the last accepted update
+    pub elapsed: Duration,
+    /// Consecutive updates that have been rejected since the last accepted update
+    pub rejected_updates: u32,
+}
+
+impl<'a> MarioUpdate<'a> {
+    fn get_positions(&self) -> Option<(&'a [f32], &'a [f32])> {
+        let prev = self.prev?;
+        if prev.pos.len() < 3 || self.next.pos.len() < 3 {
+            return None;
+        }
+        if is_cutscene(prev.action) || is_cutscene(self.next.action) {
+            return None;
+        }
+        Some((&prev.pos, &self.next.pos))
+    }
+}
+
+fn is_cutscene(action: u32) -> bool {
+    action & ACT_GROUP_MASK == ACT_GROUP_CUTSCENE
+}
+
+pub struct SocketIdValidator;
+
+impl Validator for SocketIdValidator {
+    fn name(&self) -> &'static str {
+        "socket_id"
+    }
+
+    fn validate(&self, update: &MarioUpdate) -> Option<String> {
+        if update.next.socket_id != 0 && update.next.socket_id != update.socket_id {
+            Some(format!(
+                "sent socket id {} instead of {}",
+                update.next.socket_id, update.socket_id
+            ))
+        } else {
+            None
+        }
+    }
+}
+
+pub struct HealthValidator;
+
+impl Validator for HealthValidator {
+    fn name(&self) -> &'static str {
+        "health"
+    }
+
+    fn validate(&self, update: &MarioUpdate) -> Option<String> {
+        if update.next.health > MAX_HEALTH {
+            Some(format!(
+                "health {:#x} exceeds {:#x}",
+                update.next.health, MAX_HEALTH
+            ))
+        } else {
+            None
+        }
+    }
+}
+
+pub struct SpeedValidator;
+
+impl Validator for SpeedValidator {
+    fn name(&self) -> &'static str {
+        "speed"
+    }
+
+    fn validate(&self, update: &MarioUpdate) -> Option<String> {
+        let (prev, next) = update.get_positions()?;
+        // updates might arrive in bursts, so at least one frame must have passed
+        let frames = update.elapsed.max(FRAME).as_secs_f32() / FRAME.as_secs_f32();
+        let speed = interest::distance(prev, next) / frames;
+        let max_speed = if update.next.action & ACT_GROUP_MASK == ACT_GROUP_AIRBORNE {
+            MAX_AIRBORNE_SPEED
+        } else {
+            MAX_SPEED
+        };
+        if speed > max_speed {
+            Some(format!(
+                "moved {:.0} units per frame with action {:#x}",
+                speed, update.next.action
+            ))
+        } else {
+            None
+        }
+    }
+}
+
+pub struct TeleportValidator;
+
+impl Validator for TeleportValidator {
+    fn name(&self) -> &'static str {
+        "teleport"
+    }
+
+    fn validate(&self, update: &MarioUpdate) -> Option<String> {
+        let (prev, next) = update.get_positions()?;
+        let distance = interest::distance(prev, next);
+        if distance > MAX_TELEPORT_DISTANCE {
+            Some(format!(
+                "teleported {:.0} units from {:?} to {:?}",
+                distance, prev, next
+            ))
+        } else {
+            None
+        }
+    }
+}
+
+pub struct AntiCheat {
+    validators: Vec<Box<dyn Validator>>,
+    response: CheatResponse,
+    flagged_accounts: Mutex<HashMap<i32, FlaggedAccount>>,
+    /// Violations that have not been saved to the database yet
+    unsaved_accounts: Mutex<HashMap<i32, FlaggedAccount>>,
+}
+
+impl AntiCheat {
+    pub fn new(response: CheatResponse, flagged_accounts: Vec<FlaggedAccount>) -> Self {
+        AntiCheat {
+            validators: vec![
+                Box::new(SocketIdValidator),
+                Box::new(HealthValidator),
+                Box::new(SpeedValidator),
+                Box::new(TeleportValidator),
+            ],
+            response,
+            flagged_accounts: Mutex::new(
+                flagged_accounts
+                    .into_iter()
+                    .map(|flagged_account| (flagged_account.account_id, flagged_account))
+                    .collect(),
+            ),
+            unsaved_accounts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn add_validator(&mut self, validator: Box<dyn Validator>) {
+        self.validators.push(validator);
+    }
+
+    /// Runs all validators and flags the account on a violation.
+    ///
+    /// Returns the response to a failed validation.
+    /// Updates are validated against the last accepted update, so a Mario that got rejected
+    /// after a legitimate long move, e.g. a warp, would be rejected forever.
+    /// Therefore an update is only flagged, if too many updates have been rejected in a row.
+    pub fn validate(&self, account_id: i32, update: &MarioUpdate) -> Option<CheatResponse> {
+        let violations: Vec<_> = self
+            .validators
+            .iter()
+            .filter_map(|validator| {
+                validator.validate(update).map(|evidence| CheatViolation {
+                    validator: validator.name().to_string(),
+                    evidence,
+                    date_time: Utc::now().naive_utc(),
+                })
+            })
+            .collect();
+        if violations.is_empty() {
+            return None;
+        }
+
+        add_violations(
+            &mut self.unsaved_accounts.lock(),
+            account_id,
+            violations.clone(),
+        );
+        add_violations(&mut self.flagged_accounts.lock(), account_id, violations);
+
+        match self.response {
+            CheatResponse::Drop | CheatResponse::RubberBand
+                if update.rejected_updates + 1 >= MAX_REJECTED_UPDATES =>
+            {
+                Some(CheatResponse::Flag)
+            }
+            response => Some(response),
+        }
+    }
+
+    pub fn get_flagged_accounts(&self) -> Vec<FlaggedAccount> {
+        let mut flagged_accounts: Vec<_> = self.flagged_accounts.lock().values().cloned().collect();
+        flagged_accounts.sort_by(|a, b| b.total_violations.cmp(&a.total_violations));
+        flagged_accounts
+    }
+
+    /// Takes all violations, that have not been saved to the database yet.
+    pub fn take_unsaved_accounts(&self) -> Vec<FlaggedAccount> {
+        self.unsaved_accounts
+            .lock()
+            .drain()
+            .map(|(_, flagged_account)| flagged_account)
+            .collect()
+    }
+}
+
+fn add_violations(
+    flagged_accounts: &mut HashMap<i32, FlaggedAccount>,
+    account_id: i32,
+    violations: Vec<CheatViolation>,
+) {
+    let flagged_account = flagged_accounts
+        .entry(account_id)
+        .or_insert_with(|| FlaggedAccount {
+            account_id,
+            total_violations: 0,
+            violations: vec![],
+        });
+    flagged_account.total_violations += violations.len() as u32;
+    for violation in violations {
+        if flagged_account.violations.len() >= MAX_EVIDENCE {
+            flagged_account.violations.remove(0);
+        }
+        flagged_account.violations.push(violation);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Last accepted update of a player, as tracked by the room actor
+    #[derive(Default)]
+    struct Player {
+        prev: Option<MarioMsg>,
+        elapsed: Duration,
+        rejected_updates: u32,
+    }
+
+    impl Player {
+        fn send(&mut self, anticheat: &AntiCheat, pos: [f32; 3]) -> Option<CheatResponse> {
+            let next = MarioMsg {
+                socket_id: 1,
+                health: MAX_HEALTH,
+                pos: pos.to_vec(),
+                ..Default::default()
+            };
+            self.elapsed += FRAME;
+            let response = anticheat.validate(
+                1,
+                &MarioUpdate {
+                    socket_id: 1,
+                    prev: self.prev.as_ref(),
+                    next: &next,
+                    elapsed: self.elapsed,
+                    rejected_updates: self.rejected_updates,
+                },
+            );
+            match response {
+                None | Some(CheatResponse::Flag) => {
+                    self.prev = Some(next);
+                    self.elapsed = Duration::default();
+                    self.rejected_updates = 0;
+                }
+                Some(CheatResponse::Drop) | Some(CheatResponse::RubberBand) => {
+                    self.rejected_updates += 1;
+                }
+                Some(CheatResponse::Kick) => {}
+            }
+            response
+        }
+    }
+
+    #[test]
+    fn accepts_valid_movement() {
+        let anticheat = AntiCheat::new(CheatResponse::Drop, vec![]);
+        let mut player = Player::default();
+        for i in 0..10 {
+            assert_eq!(player.send(&anticheat, [i as f32 * 50., 0., 0.]), None);
+        }
+        assert!(anticheat.get_flagged_accounts().is_empty());
+    }
+
+    #[test]
+    fn rejects_teleports() {
+        let anticheat = AntiCheat::new(CheatResponse::RubberBand, vec![]);
+        let mut player = Player::default();
+        assert_eq!(player.send(&anticheat, [0., 0., 0.]), None);
+        assert_eq!(
+            player.send(&anticheat, [10000., 0., 0.]),
+            Some(CheatResponse::RubberBand)
+        );
+        assert_eq!(player.send(&anticheat, [50., 0., 0.]), None);
+        assert_eq!(anticheat.get_flagged_accounts()[0].account_id, 1);
+    }
+
+    #[test]
+    fn accepts_position_after_consecutive_rejections() {
+        let anticheat = AntiCheat::new(CheatResponse::Drop, vec![]);
+        let mut player = Player::default();
+        assert_eq!(player.send(&anticheat, [0., 0., 0.]), None);
+
+        // e.g. a warp that has not been detected as such
+        for _ in 1..MAX_REJECTED_UPDATES {
+            assert_eq!(
+                player.send(&anticheat, [10000., 0., 0.]),
+                Some(CheatResponse::Drop)
+            );
+        }
+        assert_eq!(
+            player.send(&anticheat, [10000., 0., 0.]),
+            Some(CheatResponse::Flag)
+        );
+        assert_eq!(player.send(&anticheat, [10050., 0., 0.]), None);
+        assert_eq!(
+            player.send(&anticheat, [0., 0., 0.]),
+            Some(CheatResponse::Drop)
+        );
+    }
+
+    #[test]
+    fn keeps_kicking_after_consecutive_rejections() {
+        let anticheat = AntiCheat::new(CheatResponse::Kick, vec![]);
+        let update = MarioUpdate {
+            socket_id: 1,
+            prev: None,
+            next: &MarioMsg {
+                health: MAX_HEALTH + 1,
+                ..Default::default()
+            },
+            elapsed: FRAME,
+            rejected_updates: MAX_REJECTED_UPDATES,
+        };
+        assert_eq!(anticheat.validate(1, &update), Some(CheatResponse::Kick));
+    }
+}
@@ -0,0 +1,94 @@
+use actix::prelude::*;
+use actix_web::web;
+use chrono::{Duration, Utc};
+use sm64js_common::ChatHistoryData;
+use sm64js_db::{models::NewChatMessage, DbPool};
+use sm64js_env::{CHAT_FLUSH_INTERVAL_MS, CHAT_RETENTION_DAYS};
+use std::time;
+
+/// How often chat messages that exceeded the retention period are deleted
+const RETENTION_INTERVAL: time::Duration = time::Duration::from_secs(3600);
+
+/// Writes chat messages to the database in batches.
+///
+/// Chat messages are only queued in the [`ChatHistory`](sm64js_common::ChatHistory),
+/// so that sending a message never waits for the database.
+/// The writer should be started in its own arbiter, because all queries are blocking.
+pub struct ChatWriter {
+    pool: web::Data<DbPool>,
+    chat_history: ChatHistoryData,
+}
+
+impl Actor for ChatWriter {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        let flush_interval = time::Duration::from_millis(*CHAT_FLUSH_INTERVAL_MS.get().unwrap());
+        ctx.run_interval(flush_interval, |act, _| act.flush());
+
+        if *CHAT_RETENTION_DAYS.get().unwrap() > 0 {
+            self.delete_expired();
+            ctx.run_interval(RETENTION_INTERVAL, |act, _| act.delete_expired());
+        }
+    }
+
+    fn stopped(&mut self, _: &mut Self::Context) {
+        self.flush();
+    }
+}
+
+impl ChatWriter {
+    pub fn new(pool: web::Data<DbPool>, chat_history: ChatHistoryData) -> Self {
+        ChatWriter { pool, chat_history }
+    }
+
+    fn flush(&self) {
+        let messages = self.chat_history.write().take_pending();
+        if messages.is_empty() {
+            return;
+        }
+        let conn = match self.pool.get() {
+            Ok(conn) => conn,
+            Err(err) => {
+                eprintln!("{:?}", err);
+                self.chat_history.write().requeue(messages);
+                return;
+            }
+        };
+        let new_messages = messages.iter().cloned().map(NewChatMessage::from).collect();
+        if let Err(err) = sm64js_db::insert_chat_messages(&conn, new_messages) {
+            eprintln!("{:?}", err);
+            self.chat_history.write().requeue(messages);
+        }
+    }
+
+    /// Deletes all messages that are older than `CHAT_RETENTION_DAYS`
+    fn delete_expired(&self) {
+        let before = Utc::now().naive_utc() - Duration::days(*CHAT_RETENTION_DAYS.get().unwrap());
+        let conn = match self.pool.get() {
+            Ok(conn) => conn,
+            Err(err) => {
+                eprintln!("{:?}", err);
+                return;
+            }
+        };
+        match sm64js_db::delete_chat_messages_before(&conn, before) {
+            Ok(0) => {}
+            Ok(deleted) => eprintln!("Deleted {} expired chat messages", deleted),
+            Err(err) => eprintln!("{:?}", err),
+        }
+    }
+}
+
+/// Writes all queued chat messages to the database
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct FlushChat;
+
+impl Handler<FlushChat> for ChatWriter {
+    type Result = ();
+
+    fn handle(&mut self, _: FlushChat, _: &mut Context<Self>) {
+        self.flush();
+    }
+}
@@ -5,12 +5,13 @@ use anyhow::Result;
 use dashmap::DashMap;
 use parking_lot::RwLock;
 use sm64js_auth::AuthInfo;
-use sm64js_common::{ChatHistoryData, ChatResult};
+use sm64js_common::{ChatHistoryData, ChatResult, ChatRoom};
 use sm64js_db::DbPool;
 use sm64js_proto::{MarioMsg, SkinData};
 use std::{
     collections::HashMap,
     sync::{Arc, Weak},
+    time::{Duration, Instant},
 };
 
 pub type Clients = DashMap<u32, Client>;
@@ -23,8 +24,13 @@ pub struct Client {
     auth_info: AuthInfo,
     ip: String,
     data: Option<MarioMsg>,
+    data_updated_at: Option<Instant>,
+    /// Consecutive updates that have been rejected by the anti-cheat since the last accepted one
+    rejected_updates: u32,
     socket_id: u32,
     level: Option<u32>,
+    supports_delta: bool,
+    last_attack: Option<Instant>,
 }
 
 impl Client {
@@ -34,14 +40,33 @@ impl Client {
             auth_info,
             ip,
             data: None,
+            data_updated_at: None,
+            rejected_updates: 0,
             socket_id,
             level: None,
+            supports_delta: false,
+            last_attack: None,
         }
     }
 
     pub fn set_data(&mut self, mut data: MarioMsg) {
         data.socket_id = self.socket_id;
         self.data = Some(data);
+        self.data_updated_at = Some(Instant::now());
+        self.rejected_updates = 0;
+    }
+
+    pub fn reject_update(&mut self) {
+        self.rejected_updates += 1;
+    }
+
+    pub fn get_rejected_updates(&self) -> u32 {
+        self.rejected_updates
+    }
+
+    /// Returns the last update in the current level and how long ago it was received.
+    pub fn get_last_update(&self) -> Option<(&MarioMsg, Duration)> {
+        Some((self.data.as_ref()?, self.data_updated_at?.elapsed()))
     }
 
     pub fn get_pos(&self) -> Option<&Vec<f32>> {
@@ -70,12 +95,40 @@ impl Client {
 
     pub fn set_level(&mut self, level: u32) {
         self.level = Some(level);
+        self.data_updated_at = None;
+        self.rejected_updates = 0;
+    }
+
+    pub fn leave_level(&mut self) {
+        self.level = None;
+        self.data_updated_at = None;
+        self.rejected_updates = 0;
     }
 
     pub fn get_level(&self) -> Option<u32> {
         self.level
     }
 
+    pub fn set_supports_delta(&mut self, supports_delta: bool) {
+        self.supports_delta = supports_delta;
+    }
+
+    pub fn supports_delta(&self) -> bool {
+        self.supports_delta
+    }
+
+    /// Whether the cooldown since the last accepted attack has passed
+    pub fn is_attack_ready(&self, cooldown: Duration) -> bool {
+        self.last_attack
+            .map(|last_attack| last_attack.elapsed() >= cooldown)
+            .unwrap_or(true)
+    }
+
+    /// Restarts the attack cooldown
+    pub fn set_attacked(&mut self) {
+        self.last_attack = Some(Instant::now());
+    }
+
     pub fn send(&self, msg: Message) -> Result<()> {
         self.addr.do_send(msg)?;
         Ok(())
@@ -157,6 +210,13 @@ impl Player {
             .unwrap_or_default()
     }
 
+    pub fn supports_delta(&self) -> bool {
+        self.clients
+            .get(&self.socket_id)
+            .map(|client| client.supports_delta())
+            .unwrap_or_default()
+    }
+
     pub fn send_message(&self, msg: Vec<u8>) -> Result<()> {
         if let Some(client) = self.clients.get(&self.socket_id) {
             client.send(Message::SendData(msg))?;
@@ -178,14 +238,16 @@ impl Player {
             let account_info =
                 sm64js_db::get_account_info(&conn, auth_info.get_account_id(), true).unwrap();
 
+            let room = rooms.get(&self.level).map(|room| ChatRoom {
+                id: self.level,
+                level: room.get_level(),
+                name: room.name.clone(),
+            });
             chat_history.write().add_message(
                 message,
                 account_info,
                 self.name.clone(),
-                rooms
-                    .get(&self.level)
-                    .map(|room| room.name.clone())
-                    .unwrap_or_else(|| "Lobby".to_string()),
+                room,
                 client.ip.to_string(),
             )
         } else {
@@ -0,0 +1,493 @@
+use chrono::Duration;
+use once_cell::sync::Lazy;
+use sm64js_auth::{AuthInfo, Permission};
+use std::time;
+use thiserror::Error;
+
+/// All chat commands. A chat message is interpreted as a command, if it starts with `/`
+pub static COMMANDS: Lazy<Vec<CommandInfo>> = Lazy::new(|| {
+    vec![
+        CommandInfo {
+            name: "announcement",
+            args: "<message>",
+            description: "Shows an announcement to all players in your room",
+            permission: Some(Permission::SendAnnouncement),
+            parse: |args| {
+                Ok(ChatCommand::Announcement {
+                    message: args.text("message")?,
+                })
+            },
+        },
+        CommandInfo {
+            name: "ban",
+            args: "<player> <duration|perm> [reason]",
+            description: "Bans a player in your room, e.g. /ban Mario 3days spamming",
+            permission: Some(Permission::TempBanAccount(Duration::zero())),
+            parse: |args| {
+                Ok(ChatCommand::Ban {
+                    target: args.word("player")?,
+                    expires_in: args.expiry("duration")?,
+                    reason: args.optional_text(),
+                })
+            },
+        },
+        CommandInfo {
+            name: "help",
+            args: "[command]",
+            description: "Lists all commands you can use or describes a single command",
+            permission: None,
+            parse: |args| {
+                Ok(ChatCommand::Help {
+                    command: args.optional_word(),
+                })
+            },
+        },
+        CommandInfo {
+            name: "kick",
+            args: "<player> [reason]",
+            description: "Kicks a player in your room",
+            permission: Some(Permission::KickPlayer),
+            parse: |args| {
+                Ok(ChatCommand::Kick {
+                    target: args.word("player")?,
+                    reason: args.optional_text(),
+                })
+            },
+        },
+        CommandInfo {
+            name: "mute",
+            args: "<player> <duration|perm> [reason]",
+            description: "Mutes a player in your room, e.g. /mute Mario 15min screaming",
+            permission: Some(Permission::TempMuteAccount(Duration::zero())),
+            parse: |args| {
+                Ok(ChatCommand::Mute {
+                    target: args.word("player")?,
+                    expires_in: args.expiry("duration")?,
+                    reason: args.optional_text(),
+                })
+            },
+        },
+        CommandInfo {
+            name: "unmute",
+            args: "<player> [reason]",
+            description: "Lifts the mute of a player in your room",
+            permission: Some(Permission::TempMuteAccount(Duration::zero())),
+            parse: |args| {
+                Ok(ChatCommand::Unmute {
+                    target: args.word("player")?,
+                    reason: args.optional_text(),
+                })
+            },
+        },
+        CommandInfo {
+            name: "warn",
+            args: "<player> [reason]",
+            description: "Warns a player in your room. Too many warnings lead to a mute or ban",
+            permission: Some(Permission::WarnAccount),
+            parse: |args| {
+                Ok(ChatCommand::Warn {
+                    target: args.word("player")?,
+                    reason: args.optional_text(),
+                })
+            },
+        },
+    ]
+});
+
+/// Players are targeted by their name or socket id
+#[derive(Debug)]
+pub enum ChatCommand {
+    Announcement {
+        message: String,
+    },
+    Ban {
+        target: String,
+        /// Permanent, if `None`
+        expires_in: Option<time::Duration>,
+        reason: Option<String>,
+    },
+    Help {
+        command: Option<String>,
+    },
+    Kick {
+        target: String,
+        reason: Option<String>,
+    },
+    Mute {
+        target: String,
+        /// Permanent, if `None`
+        expires_in: Option<time::Duration>,
+        reason: Option<String>,
+    },
+    Unmute {
+        target: String,
+        reason: Option<String>,
+    },
+    Warn {
+        target: String,
+        reason: Option<String>,
+    },
+}
+
+pub struct CommandInfo {
+    pub name: &'static str,
+    /// Arguments as shown in the usage. Required arguments are written in angle brackets,
+    /// optional arguments in square brackets
+    pub args: &'static str,
+    pub description: &'static str,
+    /// Everyone can use the command, if `None`
+    pub permission: Option<Permission>,
+    parse: fn(&mut CommandArgs) -> Result<ChatCommand, CommandError>,
+}
+
+impl CommandInfo {
+    pub fn get_usage(&self) -> String {
+        if self.args.is_empty() {
+            format!("/{}", self.name)
+        } else {
+            format!("/{} {}", self.name, self.args)
+        }
+    }
+
+    pub fn can_be_used_by(&self, auth_info: &AuthInfo) -> bool {
+        self.permission
+            .as_ref()
+            .map(|permission| auth_info.has_permission(permission))
+            .unwrap_or(true)
+    }
+}
+
+/// Finds a command by its case insensitive name
+pub fn find_command(name: &str) -> Option<&'static CommandInfo> {
+    COMMANDS
+        .iter()
+        .find(|command| command.name.eq_ignore_ascii_case(name))
+}
+
+/// Describes a single command or lists all commands that can be used
+pub fn get_help(name: Option<&str>, auth_info: &AuthInfo) -> String {
+    if let Some(name) = name {
+        match find_command(name) {
+            Some(command) if command.can_be_used_by(auth_info) => {
+                format!("{} - {}", command.get_usage(), command.description)
+            }
+            _ => CommandError::Unknown(name.to_string()).to_string(),
+        }
+    } else {
+        let usages: Vec<_> = COMMANDS
+            .iter()
+            .filter(|command| command.can_be_used_by(auth_info))
+            .map(|command| command.get_usage())
+            .collect();
+        format!("Available commands: {}", usages.join(", "))
+    }
+}
+
+/// Parses a chat message that starts with `/`.
+///
+/// Fails, if the command does not exist, the sender is missing the required permission
+/// or the arguments are invalid.
+pub fn parse_command(message: &str, auth_info: &AuthInfo) -> Result<ChatCommand, CommandError> {
+    let message = message.strip_prefix('/').unwrap_or(message).trim();
+    let (name, args) = message
+        .split_once(char::is_whitespace)
+        .unwrap_or((message, ""));
+    let command = find_command(name).ok_or_else(|| CommandError::Unknown(name.to_string()))?;
+    if !command.can_be_used_by(auth_info) {
+        return Err(CommandError::Unauthorized(command.name));
+    }
+
+    let mut args = CommandArgs {
+        remaining: args.trim_start(),
+    };
+    let parsed = (command.parse)(&mut args).map_err(|err| match err {
+        CommandError::MissingArgument(arg) => {
+            CommandError::Usage(format!("Missing {}", arg), command.get_usage())
+        }
+        CommandError::InvalidArgument(arg, reason) => {
+            CommandError::Usage(format!("Invalid {}: {}", arg, reason), command.get_usage())
+        }
+        err => err,
+    })?;
+    if !args.remaining.is_empty() {
+        return Err(CommandError::Usage(
+            "Too many arguments".to_string(),
+            command.get_usage(),
+        ));
+    }
+    Ok(parsed)
+}
+
+/// Unparsed arguments of a command
+pub struct CommandArgs<'a> {
+    remaining: &'a str,
+}
+
+impl<'a> CommandArgs<'a> {
+    pub fn optional_word(&mut self) -> Option<String> {
+        if self.remaining.is_empty() {
+            return None;
+        }
+        let (word, remaining) = self
+            .remaining
+            .split_once(char::is_whitespace)
+            .unwrap_or((self.remaining, ""));
+        self.remaining = remaining.trim_start();
+        Some(word.to_string())
+    }
+
+    pub fn word(&mut self, name: &'static str) -> Result<String, CommandError> {
+        self.optional_word()
+            .ok_or(CommandError::MissingArgument(name))
+    }
+
+    /// All remaining arguments
+    pub fn optional_text(&mut self) -> Option<String> {
+        let text = std::mem::take(&mut self.remaining).trim_end();
+        if text.is_empty() {
+            None
+        } else {
+            Some(text.to_string())
+        }
+    }
+
+    /// All remaining arguments
+    pub fn text(&mut self, name: &'static str) -> Result<String, CommandError> {
+        self.optional_text()
+            .ok_or(CommandError::MissingArgument(name))
+    }
+
+    /// Duration of a punishment, e.g. "15days". See https://docs.rs/humantime/2.1.0/humantime/index.html
+    ///
+    /// Returns `None` for "perm"
+    pub fn expiry(&mut self, name: &'static str) -> Result<Option<time::Duration>, CommandError> {
+        let word = self.word(name)?;
+        if word.eq_ignore_ascii_case("perm") {
+            return Ok(None);
+        }
+        humantime::parse_duration(&word)
+            .map(Some)
+            .map_err(|err| CommandError::InvalidArgument(name, err.to_string()))
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum CommandError {
+    #[error("Unknown command /{0}. Type /help to see all commands you can use")]
+    Unknown(String),
+    #[error("You are not allowed to use /{0}")]
+    Unauthorized(&'static str),
+    #[error("Missing {0}")]
+    MissingArgument(&'static str),
+    #[error("Invalid {0}: {1}")]
+    InvalidArgument(&'static str, String),
+    #[error("{0}. Usage: {1}")]
+    Usage(String, String),
+    #[error("There is no player {0} in your room")]
+    PlayerNotFound(String),
+    #[error("There are multiple players named {0} in your room. Please use their socket id")]
+    AmbiguousPlayer(String),
+    #[error("You are not allowed to use /{0} with this duration")]
+    DurationNotAllowed(&'static str),
+    #[error("You are not allowed to lift the current {0} of this player")]
+    CannotRevoke(&'static str),
+    #[error("You are not allowed to overwrite the current {0} of this player")]
+    CannotOverwrite(&'static str),
+    #[error("/{0} failed due to an internal error")]
+    Internal(&'static str),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use sm64js_db::models::{Account, DiscordAccount, DiscordAuthInfo, DiscordSession};
+
+    const TRIAL_MOD: &str = "801876964892868659";
+
+    fn auth_info(roles: &[&str]) -> AuthInfo {
+        AuthInfo(sm64js_db::AuthInfo {
+            account: Account {
+                id: 1,
+                username: Some("Mario".to_string()),
+                last_ip: "127.0.0.1".to_string(),
+            },
+            discord: Some(DiscordAuthInfo {
+                account: DiscordAccount {
+                    id: "1".to_string(),
+                    username: "Mario".to_string(),
+                    discriminator: "0001".to_string(),
+                    avatar: None,
+                    mfa_enabled: None,
+                    locale: None,
+                    flags: None,
+                    premium_type: None,
+                    public_flags: None,
+                    nick: None,
+                    roles: roles.iter().map(|role| role.to_string()).collect(),
+                    joined_at: String::new(),
+                    premium_since: None,
+                    deaf: false,
+                    mute: false,
+                    account_id: 1,
+                },
+                session: DiscordSession {
+                    id: 1,
+                    access_token: String::new(),
+                    token_type: String::new(),
+                    expires_at: Utc::now().naive_utc(),
+                    discord_account_id: "1".to_string(),
+                },
+            }),
+            google: None,
+        })
+    }
+
+    fn usage_error(err: CommandError) -> String {
+        match err {
+            CommandError::Usage(message, _) => message,
+            err => panic!("expected usage error, got {:?}", err),
+        }
+    }
+
+    #[test]
+    fn commands_are_case_insensitive() {
+        let command = parse_command("/HeLp", &auth_info(&[])).unwrap();
+        assert!(matches!(command, ChatCommand::Help { command: None }));
+
+        let command = parse_command("/KICK Luigi", &auth_info(&[TRIAL_MOD])).unwrap();
+        assert!(matches!(command, ChatCommand::Kick { target, reason: None } if target == "Luigi"));
+    }
+
+    #[test]
+    fn rejects_unknown_commands() {
+        let err = parse_command("/jump", &auth_info(&[])).unwrap_err();
+        assert!(matches!(err, CommandError::Unknown(name) if name == "jump"));
+    }
+
+    #[test]
+    fn rejects_commands_without_permission() {
+        let err = parse_command("/kick Luigi", &auth_info(&[])).unwrap_err();
+        assert!(matches!(err, CommandError::Unauthorized("kick")));
+
+        let err = parse_command("/announcement Hello", &auth_info(&[TRIAL_MOD])).unwrap_err();
+        assert!(matches!(err, CommandError::Unauthorized("announcement")));
+    }
+
+    #[test]
+    fn parses_durations() {
+        let auth_info = auth_info(&[TRIAL_MOD]);
+
+        let command = parse_command("/ban Luigi perm", &auth_info).unwrap();
+        assert!(matches!(
+            command,
+            ChatCommand::Ban {
+                expires_in: None,
+                ..
+            }
+        ));
+
+        let command = parse_command("/ban Luigi PERM", &auth_info).unwrap();
+        assert!(matches!(
+            command,
+            ChatCommand::Ban {
+                expires_in: None,
+                ..
+            }
+        ));
+
+        let command = parse_command("/ban Luigi 3days", &auth_info).unwrap();
+        assert!(matches!(
+            command,
+            ChatCommand::Ban { expires_in: Some(expires_in), .. }
+                if expires_in == time::Duration::from_secs(3 * 24 * 60 * 60)
+        ));
+
+        let command = parse_command("/mute Luigi 1h15min", &auth_info).unwrap();
+        assert!(matches!(
+            command,
+            ChatCommand::Mute { expires_in: Some(expires_in), .. }
+                if expires_in == time::Duration::from_secs(75 * 60)
+        ));
+
+        let err = parse_command("/mute Luigi forever", &auth_info).unwrap_err();
+        assert!(usage_error(err).starts_with("Invalid duration: "));
+    }
+
+    #[test]
+    fn parses_optional_text() {
+        let auth_info = auth_info(&[TRIAL_MOD]);
+
+        let command =
+            parse_command("/mute  Luigi  15min  screaming  in chat ", &auth_info).unwrap();
+        assert!(matches!(
+            command,
+            ChatCommand::Mute { target, reason: Some(reason), .. }
+                if target == "Luigi" && reason == "screaming  in chat"
+        ));
+
+        let command = parse_command("/warn Luigi", &auth_info).unwrap();
+        assert!(matches!(command, ChatCommand::Warn { reason: None, .. }));
+    }
+
+    #[test]
+    fn rejects_missing_arguments() {
+        let auth_info = auth_info(&[TRIAL_MOD]);
+
+        let err = parse_command("/ban", &auth_info).unwrap_err();
+        assert_eq!(usage_error(err), "Missing player");
+
+        let err = parse_command("/ban Luigi", &auth_info).unwrap_err();
+        assert!(matches!(
+            err,
+            CommandError::Usage(message, usage)
+                if message == "Missing duration"
+                    && usage == "/ban <player> <duration|perm> [reason]"
+        ));
+    }
+
+    #[test]
+    fn rejects_too_many_arguments() {
+        let err = parse_command("/help ban kick", &auth_info(&[])).unwrap_err();
+        assert_eq!(usage_error(err), "Too many arguments");
+    }
+
+    #[test]
+    fn trial_mods_cannot_lift_permanent_mutes() {
+        let auth_info = auth_info(&[TRIAL_MOD]);
+
+        let command = parse_command("/unmute Luigi appealed", &auth_info).unwrap();
+        assert!(matches!(
+            command,
+            ChatCommand::Unmute { target, reason: Some(reason) }
+                if target == "Luigi" && reason == "appealed"
+        ));
+
+        let expires_at = Utc::now().naive_utc() + Duration::days(1);
+        assert!(auth_info.has_permission(&Permission::revoke_mute(Some(expires_at))));
+        assert!(!auth_info.has_permission(&Permission::revoke_mute(None)));
+        assert_eq!(
+            CommandError::CannotRevoke("mute").to_string(),
+            "You are not allowed to lift the current mute of this player"
+        );
+    }
+
+    #[test]
+    fn help_only_lists_permitted_commands() {
+        assert_eq!(
+            get_help(None, &auth_info(&[])),
+            "Available commands: /help [command]"
+        );
+
+        let help = get_help(None, &auth_info(&[TRIAL_MOD]));
+        assert!(help.contains("/ban <player>"));
+        assert!(help.contains("/warn <player>"));
+        assert!(!help.contains("/announcement"));
+
+        assert!(get_help(Some("BAN"), &auth_info(&[TRIAL_MOD])).starts_with("/ban "));
+        assert_eq!(
+            get_help(Some("ban"), &auth_info(&[])),
+            CommandError::Unknown("ban".to_string()).to_string()
+        );
+    }
+}
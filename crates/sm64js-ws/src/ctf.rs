@@ -0,0 +1,153 @@
+use serde::Deserialize;
+use sm64js_proto::{CtfMsg, CtfTeamMsg};
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct CtfConfig {
+    /// Team names. Each team owns the flag with the same index
+    /// and the flag's spawn position is the team's base
+    pub teams: Vec<String>,
+    /// A carried flag is captured within this distance to the carrier's base
+    pub capture_radius: f32,
+    /// Round duration in seconds
+    pub round_duration: u64,
+    /// A round ends early, if a team reaches this score
+    pub score_limit: u32,
+}
+
+#[derive(Debug)]
+pub struct CtfState {
+    config: CtfConfig,
+    scores: Vec<u32>,
+    /// Team index of each player
+    teams: HashMap<u32, usize>,
+    round: u32,
+    round_started_at: Instant,
+}
+
+impl CtfState {
+    pub fn new(config: CtfConfig) -> Self {
+        CtfState {
+            scores: vec![0; config.teams.len()],
+            config,
+            teams: HashMap::new(),
+            round: 1,
+            round_started_at: Instant::now(),
+        }
+    }
+
+    pub fn get_config(&self) -> &CtfConfig {
+        &self.config
+    }
+
+    /// Applies changes of a reloaded room config.
+    ///
+    /// Teams and scores are only reset, if the teams changed.
+    /// Players have to be assigned to a team again afterwards.
+    pub fn apply_config(&mut self, config: CtfConfig) {
+        if self.config.teams != config.teams {
+            *self = Self::new(config);
+        } else {
+            self.config = config;
+        }
+    }
+
+    /// Assigns a player to the team with the fewest players.
+    pub fn assign_team<F>(&mut self, socket_id: u32, is_in_room: F) -> usize
+    where
+        F: Fn(&u32) -> bool,
+    {
+        self.teams.retain(|socket_id, _| is_in_room(socket_id));
+        let mut team_sizes = vec![0usize; self.config.teams.len()];
+        for team in self.teams.values() {
+            team_sizes[*team] += 1;
+        }
+        let team = team_sizes
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, size)| **size)
+            .map(|(team, _)| team)
+            .unwrap_or_default();
+        self.teams.insert(socket_id, team);
+        team
+    }
+
+    pub fn get_team(&self, socket_id: u32) -> Option<usize> {
+        self.teams.get(&socket_id).copied()
+    }
+
+    pub fn get_team_name(&self, team: usize) -> &str {
+        &self.config.teams[team]
+    }
+
+    pub fn capture(&mut self, team: usize) {
+        self.scores[team] += 1;
+    }
+
+    pub fn is_round_over(&self) -> bool {
+        self.round_started_at.elapsed() >= Duration::from_secs(self.config.round_duration)
+            || self
+                .scores
+                .iter()
+                .any(|score| *score >= self.config.score_limit)
+    }
+
+    /// Returns the winning team or `None` on a draw.
+    pub fn get_winner(&self) -> Option<usize> {
+        let max = self.scores.iter().max()?;
+        let mut leaders = self
+            .scores
+            .iter()
+            .enumerate()
+            .filter(|(_, score)| *score == max);
+        match (leaders.next(), leaders.next()) {
+            (Some((team, _)), None) => Some(team),
+            _ => None,
+        }
+    }
+
+    /// Resets all scores and starts the next round.
+    pub fn start_next_round(&mut self) {
+        self.scores.iter_mut().for_each(|score| *score = 0);
+        self.round += 1;
+        self.round_started_at = Instant::now();
+    }
+
+    pub fn get_msg<F>(&self, bases: &[[f32; 3]], is_in_room: F) -> CtfMsg
+    where
+        F: Fn(&u32) -> bool,
+    {
+        let remaining_time = Duration::from_secs(self.config.round_duration)
+            .saturating_sub(self.round_started_at.elapsed());
+        CtfMsg {
+            team: self
+                .config
+                .teams
+                .iter()
+                .enumerate()
+                .map(|(team, name)| CtfTeamMsg {
+                    name: name.clone(),
+                    score: self.scores[team],
+                    socket_ids: self
+                        .teams
+                        .iter()
+                        .filter(|(socket_id, player_team)| {
+                            **player_team == team && is_in_room(socket_id)
+                        })
+                        .map(|(socket_id, _)| *socket_id)
+                        .collect(),
+                    base: bases
+                        .get(team)
+                        .map(|base| base.to_vec())
+                        .unwrap_or_default(),
+                })
+                .collect(),
+            round: self.round,
+            remaining_time: remaining_time.as_secs() as u32,
+            ..Default::default()
+        }
+    }
+}
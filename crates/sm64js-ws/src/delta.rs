@@ -0,0 +1,233 @@
+use sm64js_proto::{MarioDeltaMsg, MarioMsg};
+use std::collections::{HashMap, HashSet};
+
+/// How many deltas are sent before a player's full state is sent again
+const KEYFRAME_INTERVAL: u32 = 30;
+
+macro_rules! diff_fields {
+    ( $base:expr, $mario:expr, $delta:expr, $changed:expr, $($field:ident = $num:expr),+ ) => {
+        $(
+            if $base.$field != $mario.$field {
+                $delta.$field = $mario.$field.clone();
+                $changed |= 1 << $num;
+            }
+        )+
+    };
+}
+
+/// Marios that have already been sent to a single recipient.
+#[derive(Debug, Default)]
+pub struct DeltaState {
+    marios: HashMap<u32, (MarioMsg, u32)>,
+}
+
+impl DeltaState {
+    /// Encodes a Mario as delta to the Mario that has last been sent to this recipient.
+    ///
+    /// A keyframe is sent instead, if this Mario is unknown to the recipient
+    /// or if the keyframe interval has been reached.
+    pub fn encode(&mut self, mario: &MarioMsg) -> MarioDeltaMsg {
+        match self.marios.get_mut(&mario.socket_id) {
+            Some((base, deltas_sent)) if *deltas_sent < KEYFRAME_INTERVAL => {
+                let delta = encode_delta(base, mario);
+                *base = mario.clone();
+                *deltas_sent += 1;
+                delta
+            }
+            _ => {
+                self.marios.insert(mario.socket_id, (mario.clone(), 0));
+                MarioDeltaMsg {
+                    socket_id: mario.socket_id,
+                    keyframe: true,
+                    changed_fields: 0,
+                    mario: Some(mario.clone()),
+                }
+            }
+        }
+    }
+
+    /// Forgets all Marios that are no longer part of the room,
+    /// so that they will receive a keyframe, if they rejoin.
+    pub fn retain(&mut self, socket_ids: &HashSet<u32>) {
+        self.marios
+            .retain(|socket_id, _| socket_ids.contains(socket_id));
+    }
+}
+
+/// Only fields that changed are set.
+///
+/// Because unset fields are not distinguishable from fields that changed to their default value,
+/// each changed field is marked in a bitmask with `1 << field number` of `MarioMsg`.
+pub fn encode_delta(base: &MarioMsg, mario: &MarioMsg) -> MarioDeltaMsg {
+    let mut delta = MarioMsg {
+        socket_id: mario.socket_id,
+        ..Default::default()
+    };
+    let mut changed_fields = 0u32;
+    diff_fields!(
+        base,
+        mario,
+        delta,
+        changed_fields,
+        controller = 1,
+        health = 2,
+        action = 3,
+        prev_action = 4,
+        action_state = 5,
+        action_timer = 6,
+        action_arg = 7,
+        invinc_timer = 8,
+        frames_since_a = 9,
+        frames_since_b = 10,
+        wall_kick_timer = 11,
+        double_jump_timer = 12,
+        face_angle = 13,
+        angle_vel = 14,
+        pos = 15,
+        vel = 16,
+        forward_vel = 17,
+        raw_data = 18,
+        used_obj_id = 19,
+        pvp = 21
+    );
+
+    MarioDeltaMsg {
+        socket_id: mario.socket_id,
+        keyframe: false,
+        changed_fields,
+        mario: Some(delta),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    macro_rules! apply_fields {
+        ( $base:expr, $delta:expr, $changed:expr, $($field:ident = $num:expr),+ ) => {
+            $(
+                if $changed & (1 << $num) != 0 {
+                    $base.$field = $delta.$field.clone();
+                }
+            )+
+        };
+    }
+
+    /// Applies a delta the same way as the client
+    fn decode(base: &MarioMsg, delta: &MarioDeltaMsg) -> MarioMsg {
+        let mario = delta.mario.as_ref().unwrap();
+        if delta.keyframe {
+            return mario.clone();
+        }
+        let mut decoded = base.clone();
+        apply_fields!(
+            decoded,
+            mario,
+            delta.changed_fields,
+            controller = 1,
+            health = 2,
+            action = 3,
+            prev_action = 4,
+            action_state = 5,
+            action_timer = 6,
+            action_arg = 7,
+            invinc_timer = 8,
+            frames_since_a = 9,
+            frames_since_b = 10,
+            wall_kick_timer = 11,
+            double_jump_timer = 12,
+            face_angle = 13,
+            angle_vel = 14,
+            pos = 15,
+            vel = 16,
+            forward_vel = 17,
+            raw_data = 18,
+            used_obj_id = 19,
+            pvp = 21
+        );
+        decoded
+    }
+
+    fn mario() -> MarioMsg {
+        MarioMsg {
+            socket_id: 7,
+            health: 2176,
+            action: 0x0C40_0201,
+            pos: vec![100., 200., 300.],
+            vel: vec![0., 0., 0.],
+            face_angle: vec![0, 16384, 0],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn encode_delta_marks_changed_fields() {
+        let base = mario();
+        let mut next = base.clone();
+        next.pos = vec![110., 200., 300.];
+        next.forward_vel = 10.;
+
+        let delta = encode_delta(&base, &next);
+        assert!(!delta.keyframe);
+        assert_eq!(delta.socket_id, 7);
+        assert_eq!(delta.changed_fields, 1 << 15 | 1 << 17);
+        let mario = delta.mario.unwrap();
+        assert_eq!(mario.pos, next.pos);
+        assert_eq!(mario.forward_vel, 10.);
+        assert_eq!(mario.health, 0);
+    }
+
+    #[test]
+    fn encode_delta_marks_fields_changed_to_default() {
+        let base = mario();
+        let mut next = base.clone();
+        next.health = 0;
+        next.vel = vec![];
+
+        let delta = encode_delta(&base, &next);
+        assert_eq!(delta.changed_fields, 1 << 2 | 1 << 16);
+        assert_eq!(decode(&base, &delta), next);
+    }
+
+    #[test]
+    fn encode_delta_without_changes_is_empty() {
+        let delta = encode_delta(&mario(), &mario());
+        assert_eq!(delta.changed_fields, 0);
+        assert_eq!(decode(&mario(), &delta), mario());
+    }
+
+    #[test]
+    fn decoded_deltas_follow_the_sent_marios() {
+        let mut state = DeltaState::default();
+        let mut decoded = MarioMsg::default();
+        let mut mario = mario();
+        for i in 0..(KEYFRAME_INTERVAL * 2) {
+            mario.pos[0] += 1.;
+            mario.action_timer = i % 3;
+            let delta = state.encode(&mario);
+            decoded = decode(&decoded, &delta);
+            assert_eq!(decoded, mario);
+        }
+    }
+
+    #[test]
+    fn keyframes_are_sent_to_new_recipients_and_in_intervals() {
+        let mut state = DeltaState::default();
+        assert!(state.encode(&mario()).keyframe);
+        for _ in 0..KEYFRAME_INTERVAL {
+            assert!(!state.encode(&mario()).keyframe);
+        }
+        assert!(state.encode(&mario()).keyframe);
+    }
+
+    #[test]
+    fn retain_forgets_marios_that_left() {
+        let mut state = DeltaState::default();
+        state.encode(&mario());
+        state.retain(&HashSet::new());
+        assert!(state.encode(&mario()).keyframe);
+
+        state.retain(&[7].iter().copied().collect());
+        assert!(!state.encode(&mario()).keyframe);
+    }
+}
@@ -1,72 +1,113 @@
 use crate::{
+    room_actor::{BroadcastData, BroadcastSkins, ProcessGameModes, RoomActor, RoomActors},
+    scheduler::{Scheduler, SchedulerHandle},
     server::{BroadcastLobbyData, SendPlayerList},
     Rooms, Sm64JsServer,
 };
 
-use actix::Addr;
-use anyhow::Result;
+use actix::{Addr, Handler, Message};
+use futures::executor::block_on;
 use rayon::prelude::*;
 use sm64js_common::create_uncompressed_msg;
-use sm64js_env::ENABLE_PLAYER_LIST;
+use sm64js_env::{ENABLE_PLAYER_LIST, GAME_MODES_RATE, TICK_POLICY, TICK_RATE};
 use sm64js_proto::{sm64_js_msg, PlayerListsMsg};
-use std::{thread, time::Duration};
+use std::time::Duration;
+
+/// How often Marios are broadcast. Zero means every tick
+const DATA_BROADCAST_INTERVAL: Duration = Duration::from_millis(0);
+
+/// How often updated skins and the CTF state are broadcast
+const SKINS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How often valid players are sent to the lobby and abandoned rooms are removed
+const LOBBY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How often the Discord player list gets updated
+const PLAYER_LIST_INTERVAL: Duration = Duration::from_secs(10);
 
 pub struct Game;
 
 impl Game {
-    pub fn run(server: Addr<Sm64JsServer>, rooms: Rooms) {
-        thread::spawn(move || {
-            let mut i = 0u16;
-            let mut j = 0u16;
-            loop {
-                Self::process_flags(rooms.clone());
-                Self::broadcast_data(rooms.clone());
-                i += 1;
-                if i == 30 {
-                    Self::broadcast_skins(rooms.clone());
-                    Self::broadcast_valid_update(server.clone(), rooms.clone());
-                    i = 0;
-                }
-                if *ENABLE_PLAYER_LIST.get().unwrap() {
-                    j += 1;
-                    if j == 300 {
-                        Self::send_player_list(server.clone());
-                        j = 0;
-                    }
-                }
-                thread::sleep(Duration::from_millis(33));
-            }
-        });
-    }
-
-    fn process_flags(rooms: Rooms) {
-        rooms.par_iter().for_each(|room| room.process_flags());
-    }
+    /// Starts the game loop.
+    ///
+    /// Rooms are processed by their own `RoomActor`, which receive their tasks from here.
+    /// The game loop stops, once the returned handle is stopped or dropped.
+    pub fn run(
+        server: Addr<Sm64JsServer>,
+        rooms: Rooms,
+        room_actors: RoomActors,
+    ) -> SchedulerHandle {
+        let tick_rate = *TICK_RATE.get().unwrap();
+        // flag physics expect 30 updates per second regardless of the tick rate
+        let game_modes_rate = *GAME_MODES_RATE.get().unwrap();
+        if game_modes_rate > tick_rate {
+            eprintln!(
+                "GAME_MODES_RATE {} exceeds TICK_RATE {}, so game modes only run every tick",
+                game_modes_rate, tick_rate
+            );
+        }
+        let game_modes_interval = Duration::from_secs(1) / game_modes_rate.max(1);
 
-    pub fn broadcast_data(rooms: Rooms) {
-        if let Err(err) = rooms
-            .par_iter()
-            .map(|room| room.broadcast_data())
-            .collect::<Result<Vec<_>>>()
+        let mut scheduler = Scheduler::new(tick_rate, *TICK_POLICY.get().unwrap());
         {
-            eprintln!("{:?}", err);
+            let room_actors = room_actors.clone();
+            scheduler.add_task("game modes", game_modes_interval, move |_| {
+                Self::send_to_rooms(&room_actors, ProcessGameModes)
+            });
         }
+        {
+            let room_actors = room_actors.clone();
+            scheduler.add_task("data broadcast", DATA_BROADCAST_INTERVAL, move |_| {
+                Self::send_to_rooms(&room_actors, BroadcastData)
+            });
+        }
+        scheduler.add_task("skins", SKINS_INTERVAL, move |_| {
+            room_actors.retain(|_, room_actor| room_actor.connected());
+            Self::send_to_rooms(&room_actors, BroadcastSkins)
+        });
+        {
+            let server = server.clone();
+            scheduler.add_task("lobby", LOBBY_INTERVAL, move |_| {
+                Self::broadcast_valid_update(server.clone(), rooms.clone());
+                Self::remove_abandoned_rooms(rooms.clone());
+            });
+        }
+        if *ENABLE_PLAYER_LIST.get().unwrap() {
+            scheduler.add_task("player list", PLAYER_LIST_INTERVAL, move |_| {
+                Self::send_player_list(server.clone())
+            });
+        }
+        scheduler.start()
     }
 
-    fn broadcast_skins(rooms: Rooms) {
-        if let Err(err) = rooms
-            .par_iter()
-            .map(|room| room.broadcast_skins())
-            .collect::<Result<Vec<_>>>()
-        {
-            eprintln!("{:?}", err);
+    /// Sends a task to all room actors and waits until they processed it,
+    /// so that the duration of a tick includes the work of all rooms.
+    fn send_to_rooms<M>(room_actors: &RoomActors, msg: M)
+    where
+        M: Message<Result = ()> + Copy + Send + 'static,
+        RoomActor: Handler<M>,
+    {
+        let requests: Vec<_> = room_actors
+            .iter()
+            .map(|room_actor| room_actor.send(msg))
+            .collect();
+        for request in requests {
+            // room actors stop, once their room has been removed
+            let _ = block_on(request);
         }
     }
 
     fn broadcast_valid_update(server: Addr<Sm64JsServer>, rooms: Rooms) {
         let game = rooms
             .par_iter()
-            .map(|room| room.get_and_send_valid_players())
+            .filter_map(|room| {
+                let valid_players = room.get_and_send_valid_players();
+                if room.is_custom_game() {
+                    None
+                } else {
+                    Some(valid_players)
+                }
+            })
             .collect::<Vec<_>>();
         let message = sm64_js_msg::Message::PlayerListsMsg(PlayerListsMsg { game });
         let root_msg = create_uncompressed_msg(message);
@@ -74,6 +115,10 @@ impl Game {
         server.do_send(BroadcastLobbyData { data: root_msg });
     }
 
+    fn remove_abandoned_rooms(rooms: Rooms) {
+        rooms.retain(|_, room| !room.is_abandoned());
+    }
+
     fn send_player_list(server: Addr<Sm64JsServer>) {
         server.do_send(SendPlayerList);
     }
@@ -0,0 +1,81 @@
+use serde::Deserialize;
+use sm64js_proto::MarioMsg;
+use std::collections::{HashMap, HashSet};
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+pub struct InterestConfig {
+    /// Players within this distance receive each other's updates every tick
+    pub near_distance: f32,
+    /// Players farther away only receive each other's updates every n-th tick
+    pub far_update_interval: u32,
+}
+
+/// Spatial grid of all Marios in a room.
+///
+/// The cell size equals the near distance,
+/// so all near Marios can be found in the surrounding 27 cells.
+pub struct SpatialGrid<'a> {
+    cell_size: f32,
+    cells: HashMap<[i32; 3], Vec<&'a MarioMsg>>,
+}
+
+impl<'a> SpatialGrid<'a> {
+    pub fn new(cell_size: f32, marios: &'a [MarioMsg]) -> Self {
+        let mut cells: HashMap<[i32; 3], Vec<&'a MarioMsg>> = HashMap::new();
+        for mario in marios {
+            if let Some(cell) = Self::get_cell(cell_size, &mario.pos) {
+                cells.entry(cell).or_default().push(mario);
+            }
+        }
+        Self { cell_size, cells }
+    }
+
+    /// Returns the socket ids of all Marios within the cell size of the given position.
+    pub fn get_near(&self, pos: &[f32]) -> HashSet<u32> {
+        let mut near = HashSet::new();
+        let cell = match Self::get_cell(self.cell_size, pos) {
+            Some(cell) => cell,
+            None => return near,
+        };
+        for x in -1..=1 {
+            for y in -1..=1 {
+                for z in -1..=1 {
+                    let neighbour = [
+                        cell[0].saturating_add(x),
+                        cell[1].saturating_add(y),
+                        cell[2].saturating_add(z),
+                    ];
+                    if let Some(marios) = self.cells.get(&neighbour) {
+                        near.extend(
+                            marios
+                                .iter()
+                                .filter(|mario| distance(pos, &mario.pos) <= self.cell_size)
+                                .map(|mario| mario.socket_id),
+                        );
+                    }
+                }
+            }
+        }
+        near
+    }
+
+    /// Positions that are not finite don't belong to any cell.
+    /// Positions out of range are clamped to the outermost cells
+    fn get_cell(cell_size: f32, pos: &[f32]) -> Option<[i32; 3]> {
+        if pos.len() < 3 || pos[..3].iter().any(|coord| !coord.is_finite()) {
+            return None;
+        }
+        Some([
+            (pos[0] / cell_size).floor() as i32,
+            (pos[1] / cell_size).floor() as i32,
+            (pos[2] / cell_size).floor() as i32,
+        ])
+    }
+}
+
+pub fn distance(pos1: &[f32], pos2: &[f32]) -> f32 {
+    let x_diff = pos1[0] - pos2[0];
+    let y_diff = pos1[1] - pos2[1];
+    let z_diff = pos1[2] - pos2[2];
+    (x_diff * x_diff + y_diff * y_diff + z_diff * z_diff).sqrt()
+}
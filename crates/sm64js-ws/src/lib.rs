@@ -1,13 +1,39 @@
 #![feature(try_blocks)]
 
+mod anticheat;
+mod chat_writer;
 mod client;
+mod command;
+mod ctf;
+mod delta;
 mod game;
+mod interest;
+mod metrics;
+mod race;
+mod rate_limit;
 mod room;
+mod room_actor;
+mod room_config;
+mod scheduler;
 mod server;
 mod session;
 
+pub use anticheat::{AntiCheat, CheatResponse, MarioUpdate, Validator};
+pub use chat_writer::{ChatWriter, FlushChat};
 pub use client::{Client, Clients, Player, Players, WeakPlayers};
+pub use command::{ChatCommand, CommandArgs, CommandError, CommandInfo, COMMANDS};
+pub use ctf::CtfConfig;
 pub use game::Game;
+pub use interest::InterestConfig;
+pub use metrics::{Metrics, METRICS};
+pub use race::{Checkpoint, RaceConfig};
 pub use room::{Flag, Room, Rooms};
-pub use server::{GetPlayers, KickClientByAccountId, KickClientByIpAddr, Message, Sm64JsServer};
+pub use room_actor::{BroadcastData, ProcessGameModes, RoomActor, RoomActors};
+pub use room_config::{GameMode, RoomConfig, RoomConfigError, RoomsConfig};
+pub use scheduler::{Scheduler, SchedulerHandle, TickPolicy};
+pub use server::{
+    Disconnect, GetFlaggedAccounts, GetPlayers, GetServerStatus, KickClientByAccountId,
+    KickClientByIpAddr, Message, RecordRaceTime, ReloadChatFilters, ReloadRooms, RoomsReloaded,
+    SetMaintenanceMode, Shutdown, Sm64JsServer, WarnAccount,
+};
 pub use session::Sm64JsWsSession;
@@ -0,0 +1,99 @@
+use crate::rate_limit::RateLimitedMsg;
+
+use once_cell::sync::Lazy;
+use sm64js_common::ServerMetrics;
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+pub static METRICS: Lazy<Metrics> = Lazy::new(Metrics::default);
+
+/// Server wide counters since the server started
+#[derive(Debug, Default)]
+pub struct Metrics {
+    malformed_messages: AtomicU64,
+    rate_limited_mario_msgs: AtomicU64,
+    rate_limited_skin_msgs: AtomicU64,
+    rate_limited_grab_flag_msgs: AtomicU64,
+    rate_limited_attack_msgs: AtomicU64,
+    rate_limit_kicks: AtomicU64,
+    ticks: AtomicU64,
+    skipped_ticks: AtomicU64,
+    tick_duration_total_us: AtomicU64,
+    tick_duration_max_us: AtomicU64,
+    room_tasks: AtomicU64,
+    room_task_duration_total_us: AtomicU64,
+    room_task_duration_max_us: AtomicU64,
+    broadcast_bytes: AtomicU64,
+}
+
+impl Metrics {
+    pub fn inc_malformed_messages(&self) {
+        self.malformed_messages.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn inc_rate_limited(&self, msg: RateLimitedMsg) {
+        let counter = match msg {
+            RateLimitedMsg::Mario => &self.rate_limited_mario_msgs,
+            RateLimitedMsg::Skin => &self.rate_limited_skin_msgs,
+            RateLimitedMsg::GrabFlag => &self.rate_limited_grab_flag_msgs,
+            RateLimitedMsg::Attack => &self.rate_limited_attack_msgs,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn inc_rate_limit_kicks(&self) {
+        self.rate_limit_kicks.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn add_skipped_ticks(&self, skipped_ticks: u64) {
+        self.skipped_ticks
+            .fetch_add(skipped_ticks, Ordering::Relaxed);
+    }
+
+    pub fn record_tick(&self, duration: Duration) {
+        let duration = duration.as_micros() as u64;
+        self.ticks.fetch_add(1, Ordering::Relaxed);
+        self.tick_duration_total_us
+            .fetch_add(duration, Ordering::Relaxed);
+        self.tick_duration_max_us
+            .fetch_max(duration, Ordering::Relaxed);
+    }
+
+    pub fn record_room_task(&self, duration: Duration) {
+        let duration = duration.as_micros() as u64;
+        self.room_tasks.fetch_add(1, Ordering::Relaxed);
+        self.room_task_duration_total_us
+            .fetch_add(duration, Ordering::Relaxed);
+        self.room_task_duration_max_us
+            .fetch_max(duration, Ordering::Relaxed);
+    }
+
+    pub fn add_broadcast_bytes(&self, bytes: u64) {
+        self.broadcast_bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    pub fn get_snapshot(&self) -> ServerMetrics {
+        let ticks = self.ticks.load(Ordering::Relaxed);
+        let room_tasks = self.room_tasks.load(Ordering::Relaxed);
+        ServerMetrics {
+            malformed_messages: self.malformed_messages.load(Ordering::Relaxed),
+            rate_limited_mario_msgs: self.rate_limited_mario_msgs.load(Ordering::Relaxed),
+            rate_limited_skin_msgs: self.rate_limited_skin_msgs.load(Ordering::Relaxed),
+            rate_limited_grab_flag_msgs: self.rate_limited_grab_flag_msgs.load(Ordering::Relaxed),
+            rate_limited_attack_msgs: self.rate_limited_attack_msgs.load(Ordering::Relaxed),
+            rate_limit_kicks: self.rate_limit_kicks.load(Ordering::Relaxed),
+            ticks,
+            skipped_ticks: self.skipped_ticks.load(Ordering::Relaxed),
+            avg_tick_duration_us: self.tick_duration_total_us.load(Ordering::Relaxed)
+                / ticks.max(1),
+            max_tick_duration_us: self.tick_duration_max_us.load(Ordering::Relaxed),
+            room_tasks,
+            avg_room_task_duration_us: self.room_task_duration_total_us.load(Ordering::Relaxed)
+                / room_tasks.max(1),
+            max_room_task_duration_us: self.room_task_duration_max_us.load(Ordering::Relaxed),
+            broadcast_bytes: self.broadcast_bytes.load(Ordering::Relaxed),
+        }
+    }
+}
@@ -0,0 +1,175 @@
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct RaceConfig {
+    /// Checkpoints that have to be passed in order.
+    /// The first checkpoint is the start and the last one is the finish of a lap
+    pub checkpoints: Vec<Checkpoint>,
+    /// Number of laps. A new lap starts right after the finish of the previous one
+    #[serde(default = "default_laps")]
+    pub laps: u32,
+}
+
+fn default_laps() -> u32 {
+    1
+}
+
+/// Axis-aligned box between two corners
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct Checkpoint {
+    pub min: [f32; 3],
+    pub max: [f32; 3],
+}
+
+impl Checkpoint {
+    pub fn contains(&self, pos: &[f32]) -> bool {
+        pos.len() >= 3 && (0..3).all(|i| self.min[i] <= pos[i] && pos[i] <= self.max[i])
+    }
+
+    pub fn is_valid(&self) -> bool {
+        (0..3).all(|i| {
+            self.min[i].is_finite() && self.max[i].is_finite() && self.min[i] <= self.max[i]
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct RaceFinish {
+    pub socket_id: u32,
+    pub time: Duration,
+    pub best_lap: Duration,
+}
+
+#[derive(Debug)]
+pub enum RaceEvent {
+    Lap {
+        socket_id: u32,
+        lap: u32,
+        time: Duration,
+    },
+    Finish(RaceFinish),
+}
+
+#[derive(Debug)]
+struct Racer {
+    next_checkpoint: usize,
+    lap: u32,
+    started_at: Instant,
+    lap_started_at: Instant,
+    best_lap: Option<Duration>,
+}
+
+#[derive(Debug)]
+pub struct RaceState {
+    config: RaceConfig,
+    racers: HashMap<u32, Racer>,
+}
+
+impl RaceState {
+    pub fn new(config: RaceConfig) -> Self {
+        RaceState {
+            config,
+            racers: HashMap::new(),
+        }
+    }
+
+    pub fn get_config(&self) -> &RaceConfig {
+        &self.config
+    }
+
+    /// Applies changes of a reloaded room config.
+    ///
+    /// Running races are cancelled, if the track changed.
+    pub fn apply_config(&mut self, config: RaceConfig) {
+        if self.config != config {
+            *self = Self::new(config);
+        }
+    }
+
+    /// Advances a player to the next checkpoint, if the given position is inside of it.
+    ///
+    /// The timer restarts while a player stays inside the start,
+    /// so it effectively starts when a player leaves it.
+    pub fn process_position(&mut self, socket_id: u32, pos: &[f32]) -> Option<RaceEvent> {
+        let checkpoints = &self.config.checkpoints;
+        let now = Instant::now();
+
+        let racer = match self.racers.get_mut(&socket_id) {
+            Some(racer) => racer,
+            None => {
+                if checkpoints[0].contains(pos) {
+                    self.racers.insert(
+                        socket_id,
+                        Racer {
+                            next_checkpoint: 1,
+                            lap: 1,
+                            started_at: now,
+                            lap_started_at: now,
+                            best_lap: None,
+                        },
+                    );
+                }
+                return None;
+            }
+        };
+
+        if racer.lap == 1 && racer.next_checkpoint == 1 && checkpoints[0].contains(pos) {
+            racer.started_at = now;
+            racer.lap_started_at = now;
+            return None;
+        }
+        if !checkpoints[racer.next_checkpoint].contains(pos) {
+            return None;
+        }
+
+        racer.next_checkpoint += 1;
+        if racer.next_checkpoint < checkpoints.len() {
+            return None;
+        }
+
+        let lap_time = now - racer.lap_started_at;
+        let best_lap = racer.best_lap.map_or(lap_time, |best| best.min(lap_time));
+        if racer.lap >= self.config.laps {
+            let time = now - racer.started_at;
+            self.racers.remove(&socket_id);
+            return Some(RaceEvent::Finish(RaceFinish {
+                socket_id,
+                time,
+                best_lap,
+            }));
+        }
+
+        let lap = racer.lap;
+        racer.lap += 1;
+        racer.next_checkpoint = 1;
+        racer.lap_started_at = now;
+        racer.best_lap = Some(best_lap);
+        Some(RaceEvent::Lap {
+            socket_id,
+            lap,
+            time: lap_time,
+        })
+    }
+
+    pub fn remove_racers<F>(&mut self, is_in_room: F)
+    where
+        F: Fn(&u32) -> bool,
+    {
+        self.racers.retain(|socket_id, _| is_in_room(socket_id));
+    }
+}
+
+/// Formats a race time like `1:23.456`.
+pub fn format_race_time(time: Duration) -> String {
+    let millis = time.as_millis();
+    format!(
+        "{}:{:02}.{:03}",
+        millis / 60_000,
+        millis / 1000 % 60,
+        millis % 1000
+    )
+}
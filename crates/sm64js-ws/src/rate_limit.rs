@@ -0,0 +1,214 @@
+use std::time::{Duration, Instant};
+
+/// Violations are counted per window, so that occasional bursts add up to a kick
+const VIOLATIONS_WINDOW: Duration = Duration::from_secs(60);
+
+/// Token bucket that allows bursts of up to one second worth of messages.
+#[derive(Debug)]
+pub struct TokenBucket {
+    /// Tokens per second, which is also the capacity of the bucket
+    rate: f32,
+    tokens: f32,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    pub fn new(rate: u32) -> Self {
+        TokenBucket {
+            rate: rate as f32,
+            tokens: rate as f32,
+            last_refill: Instant::now(),
+        }
+    }
+
+    /// Takes a token, if one is available.
+    pub fn try_take(&mut self) -> bool {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_refill).as_secs_f32();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.rate);
+        self.last_refill = now;
+
+        if self.tokens >= 1. {
+            self.tokens -= 1.;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Kinds of messages that are rate limited per session
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RateLimitedMsg {
+    Mario,
+    Skin,
+    GrabFlag,
+    Attack,
+}
+
+impl RateLimitedMsg {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Mario => "MarioMsg",
+            Self::Skin => "SkinMsg",
+            Self::GrabFlag => "GrabFlagMsg",
+            Self::Attack => "AttackMsg",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct RateLimiter {
+    mario: TokenBucket,
+    skin: TokenBucket,
+    grab_flag: TokenBucket,
+    attack: TokenBucket,
+    /// Number of dropped messages in the current window
+    violations: u32,
+    max_violations: u32,
+    /// Whether `RateLimit::Exceeded` has been returned in the current window
+    exceeded: bool,
+    window_started: Instant,
+}
+
+impl RateLimiter {
+    pub fn new() -> Self {
+        Self::with_limits(
+            [
+                *sm64js_env::RATE_LIMIT_MARIO.get().unwrap(),
+                *sm64js_env::RATE_LIMIT_SKIN.get().unwrap(),
+                *sm64js_env::RATE_LIMIT_GRAB_FLAG.get().unwrap(),
+                *sm64js_env::RATE_LIMIT_ATTACK.get().unwrap(),
+            ],
+            *sm64js_env::MAX_RATE_LIMIT_VIOLATIONS.get().unwrap(),
+        )
+    }
+
+    /// `rates` are the tokens per second of MarioMsg, SkinMsg, GrabFlagMsg and AttackMsg
+    fn with_limits(rates: [u32; 4], max_violations: u32) -> Self {
+        let [mario, skin, grab_flag, attack] = rates;
+        RateLimiter {
+            mario: TokenBucket::new(mario),
+            skin: TokenBucket::new(skin),
+            grab_flag: TokenBucket::new(grab_flag),
+            attack: TokenBucket::new(attack),
+            violations: 0,
+            max_violations,
+            exceeded: false,
+            window_started: Instant::now(),
+        }
+    }
+
+    pub fn check(&mut self, msg: RateLimitedMsg) -> RateLimit {
+        let bucket = match msg {
+            RateLimitedMsg::Mario => &mut self.mario,
+            RateLimitedMsg::Skin => &mut self.skin,
+            RateLimitedMsg::GrabFlag => &mut self.grab_flag,
+            RateLimitedMsg::Attack => &mut self.attack,
+        };
+        if bucket.try_take() {
+            return RateLimit::Allowed;
+        }
+
+        if self.window_started.elapsed() >= VIOLATIONS_WINDOW {
+            self.violations = 0;
+            self.exceeded = false;
+            self.window_started = Instant::now();
+        }
+        self.violations += 1;
+        if self.violations >= self.max_violations && !self.exceeded {
+            self.exceeded = true;
+            RateLimit::Exceeded
+        } else {
+            RateLimit::Limited {
+                first: self.violations == 1,
+            }
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum RateLimit {
+    Allowed,
+    /// The message has to be dropped
+    Limited {
+        first: bool,
+    },
+    /// The message has to be dropped and the client exceeded its message budget.
+    /// This is only returned once per violations window
+    Exceeded,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn token_bucket_allows_bursts_and_refills() {
+        let mut bucket = TokenBucket::new(2);
+        assert!(bucket.try_take());
+        assert!(bucket.try_take());
+        assert!(!bucket.try_take());
+
+        bucket.last_refill -= Duration::from_millis(600);
+        assert!(bucket.try_take());
+        assert!(!bucket.try_take());
+
+        // the bucket never holds more than one second worth of tokens
+        bucket.last_refill -= Duration::from_secs(10);
+        assert!(bucket.try_take());
+        assert!(bucket.try_take());
+        assert!(!bucket.try_take());
+    }
+
+    #[test]
+    fn rate_limiter_counts_violations() {
+        let mut rate_limiter = RateLimiter::with_limits([1, 1, 1, 1], 3);
+        assert_eq!(
+            rate_limiter.check(RateLimitedMsg::Mario),
+            RateLimit::Allowed
+        );
+        assert_eq!(
+            rate_limiter.check(RateLimitedMsg::Mario),
+            RateLimit::Limited { first: true }
+        );
+        assert_eq!(
+            rate_limiter.check(RateLimitedMsg::Mario),
+            RateLimit::Limited { first: false }
+        );
+        // message kinds have separate budgets
+        assert_eq!(rate_limiter.check(RateLimitedMsg::Skin), RateLimit::Allowed);
+        assert_eq!(
+            rate_limiter.check(RateLimitedMsg::Skin),
+            RateLimit::Exceeded
+        );
+        // exceeding is only reported once per window
+        assert_eq!(
+            rate_limiter.check(RateLimitedMsg::Mario),
+            RateLimit::Limited { first: false }
+        );
+
+        rate_limiter.window_started -= VIOLATIONS_WINDOW;
+        assert_eq!(
+            rate_limiter.check(RateLimitedMsg::Mario),
+            RateLimit::Limited { first: true }
+        );
+    }
+
+    #[test]
+    fn rate_limiter_without_violations_budget_kicks_immediately() {
+        let mut rate_limiter = RateLimiter::with_limits([1, 1, 1, 1], 0);
+        assert_eq!(
+            rate_limiter.check(RateLimitedMsg::Attack),
+            RateLimit::Allowed
+        );
+        assert_eq!(
+            rate_limiter.check(RateLimitedMsg::Attack),
+            RateLimit::Exceeded
+        );
+        assert_eq!(
+            rate_limiter.check(RateLimitedMsg::Attack),
+            RateLimit::Limited { first: false }
+        );
+    }
+}
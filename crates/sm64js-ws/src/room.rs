@@ -1,4 +1,12 @@
-use crate::{Player, WeakPlayers};
+use crate::{
+    ctf::{CtfConfig, CtfState},
+    delta::DeltaState,
+    interest::{self, InterestConfig, SpatialGrid},
+    race::{self, RaceConfig, RaceEvent, RaceState},
+    room_config::{GameMode, RoomConfig, RoomsConfig},
+    server::RecordRaceTime,
+    Player, WeakPlayers, METRICS,
+};
 
 use anyhow::Result;
 use dashmap::DashMap;
@@ -6,117 +14,422 @@ use flate2::{write::ZlibEncoder, Compression};
 use prost::Message as ProstMessage;
 use rand::{self, Rng};
 use rayon::prelude::*;
-use sm64js_common::{create_uncompressed_msg, DiscordRichEmbedField};
+use sha2::{Digest, Sha256};
+use sm64js_common::{create_server_chat_msg, create_uncompressed_msg, DiscordRichEmbedField};
 use sm64js_env::REDIRECT_URI;
 use sm64js_proto::{
-    root_msg, sm64_js_msg, FlagMsg, MarioListMsg, PlayerListsMsg, RootMsg, SkinMsg, Sm64JsMsg,
-    ValidPlayersMsg,
+    root_msg, sm64_js_msg, AnnouncementMsg, FlagMsg, MarioDeltaListMsg, MarioDeltaMsg,
+    MarioListMsg, MarioMsg, PlayerListsMsg, RootMsg, SkinMsg, Sm64JsMsg, ValidPlayersMsg,
 };
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     io::prelude::*,
-    sync::{Arc, RwLock, Weak},
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc, RwLock, Weak,
+    },
+    time::{Duration, Instant},
 };
+use thiserror::Error;
 
 pub type Rooms = Arc<DashMap<u32, Room>>;
 
-macro_rules! add_room {
-    ( $rooms:expr, $id:expr, $name:expr, $($flag:expr),+ ) => {
-        $rooms.insert(
-            $id,
-            Room {
-                id: $id,
-                name: $name.to_string(),
-                flags: vec![$(RwLock::new(Flag::new($flag)),)*],
-                players: HashMap::new(),
-            },
-        );
-    };
-    ( $rooms:expr, $id:expr, $name:expr ) => {
-        $rooms.insert(
-            $id,
-            Room {
-                id: $id,
-                name: $name.to_string(),
-                flags: vec![],
-                players: HashMap::new(),
-            },
-        );
-    };
-}
+/// Rooms that are created on demand get an id starting from here,
+/// so that they never collide with a level id
+pub const DYNAMIC_ROOM_ID_START: u32 = 1 << 24;
+
+/// How long an empty custom game or shard is kept alive after creation
+const DYNAMIC_ROOM_GRACE_PERIOD: Duration = Duration::from_secs(30);
+
+/// Maximum distance between attacker and target, including some tolerance for latency
+const MAX_ATTACK_DISTANCE: f32 = 500.;
 
 #[derive(Debug)]
 pub struct Room {
-    id: u32,
+    level: u32,
     pub name: String,
+    shard: u32,
+    custom_game: Option<CustomGame>,
+    created_at: Instant,
     flags: Vec<RwLock<Flag>>,
     players: WeakPlayers,
+    max_players: Option<usize>,
+    game_modes: Vec<GameMode>,
+    interest: Option<InterestConfig>,
+    tick: AtomicU32,
+    /// Marios that have already been sent to each player that supports delta encoding
+    delta_states: DashMap<u32, DeltaState>,
+    ctf: Option<RwLock<CtfState>>,
+    race: Option<RwLock<RaceState>>,
 }
 
 impl Room {
-    pub fn init_rooms() -> Rooms {
+    pub fn init_rooms(config: &RoomsConfig) -> Rooms {
         let rooms = DashMap::new();
-        add_room!(rooms, 4, "Big Boo's Haunt", [671., 2867., 1908.]);
-        add_room!(rooms, 5, "Cool, Cool Mountain", [2556., 2662., -1041.]);
-        add_room!(rooms, 6, "Castle Inside First Level");
-        add_room!(rooms, 7, "Hazy Maze Cave", [6099., -4689., 2327.]);
-        add_room!(rooms, 8, "Shifting Sand Land", [-2048., 1103., -463.]);
-        add_room!(rooms, 9, "Bob-omb Battlefield", [-2384., 260., 6203.]);
-        add_room!(rooms, 10, "Snowman's Land", [214., 4864., -39.]);
-        add_room!(
-            rooms,
-            16,
-            "Castle Grounds",
-            [6300., 910., -5900.],
-            [-4200., -1300., -5300.]
-        );
-        add_room!(rooms, 24, "Whomp's Fortress", [242., 3584., 178.]);
-        add_room!(rooms, 26, "Castle Courtyard");
-        add_room!(rooms, 27, "Princess's Secret Slide");
-        add_room!(rooms, 29, "Tower of the Wing Cap");
-        add_room!(rooms, 36, "Tall, Tall Mountain", [1165., 2309., 261.]);
-        add_room!(rooms, 56, "Cool, Cool Mountain Slide");
-        add_room!(rooms, 602, "Castle Inside Second Level");
-        add_room!(rooms, 999, "Clouded Ruins", [-6., 1116., -2027.]);
-        add_room!(
-            rooms,
-            1000,
-            "Mushroom Battlefield",
-            [9380., 7657., -8980.],
-            [-5126., 3678., 10106.],
-            [-14920., 3800., -8675.],
-            [12043., 3000., 10086.]
-        );
-        add_room!(
-            rooms,
-            1001,
-            "CTF/Race Map",
-            [-76., 467., -7768.],
-            [-76., 467., 7945.]
-        );
-        add_room!(rooms, 1002, "Starman Fortress", [1919., 4319., -1024.]);
-        add_room!(rooms, 1003, "Glider Jungle", [8363., 8798., -1613.]);
-        add_room!(rooms, 1004, "Mushroom Raceway");
-        add_room!(
-            rooms,
-            1006,
-            "Dolphin Town",
-            [4124., 7528., 576.],
-            [4224., 7528., -1267.]
-        );
+        for room in config.rooms.iter() {
+            rooms.insert(room.id, Room::new(room));
+        }
 
         Arc::new(rooms)
     }
 
+    pub fn new(config: &RoomConfig) -> Self {
+        Room {
+            level: config.id,
+            name: config.name.clone(),
+            shard: 1,
+            custom_game: None,
+            created_at: Instant::now(),
+            flags: config
+                .flags
+                .iter()
+                .map(|pos| RwLock::new(Flag::new(*pos)))
+                .collect(),
+            players: HashMap::new(),
+            max_players: config.max_players,
+            game_modes: config.game_modes.clone(),
+            interest: config.interest,
+            tick: AtomicU32::new(0),
+            delta_states: DashMap::new(),
+            ctf: Self::create_ctf(&config.game_modes, config.ctf.as_ref()),
+            race: Self::create_race(&config.game_modes, config.race.as_ref()),
+        }
+    }
+
+    fn create_race(
+        game_modes: &[GameMode],
+        config: Option<&RaceConfig>,
+    ) -> Option<RwLock<RaceState>> {
+        if game_modes.contains(&GameMode::Race) {
+            config.map(|config| RwLock::new(RaceState::new(config.clone())))
+        } else {
+            None
+        }
+    }
+
+    fn create_ctf(game_modes: &[GameMode], config: Option<&CtfConfig>) -> Option<RwLock<CtfState>> {
+        if game_modes.contains(&GameMode::Ctf) {
+            config.map(|config| RwLock::new(CtfState::new(config.clone())))
+        } else {
+            None
+        }
+    }
+
+    /// Creates a private room based on the level of this room.
+    #[must_use]
+    pub fn create_custom_game(&self, custom_game: CustomGame) -> Self {
+        let name = format!("{} [{}]", self.name, custom_game.game_id);
+        self.create_dynamic_room(name, 1, Some(custom_game))
+    }
+
+    /// Creates an additional shard of this room, if it is full.
+    #[must_use]
+    pub fn create_shard(&self, shard: u32) -> Self {
+        let name = format!("{} #{}", self.name, shard);
+        self.create_dynamic_room(name, shard, None)
+    }
+
+    fn create_dynamic_room(
+        &self,
+        name: String,
+        shard: u32,
+        custom_game: Option<CustomGame>,
+    ) -> Self {
+        Room {
+            level: self.level,
+            name,
+            shard,
+            custom_game,
+            created_at: Instant::now(),
+            flags: self
+                .flags
+                .iter()
+                .map(|flag| RwLock::new(Flag::new(*flag.read().unwrap().start_pos)))
+                .collect(),
+            players: HashMap::new(),
+            max_players: self.max_players,
+            game_modes: self.game_modes.clone(),
+            interest: self.interest,
+            tick: AtomicU32::new(0),
+            delta_states: DashMap::new(),
+            ctf: Self::create_ctf(
+                &self.game_modes,
+                self.ctf
+                    .as_ref()
+                    .map(|ctf| ctf.read().unwrap().get_config().clone())
+                    .as_ref(),
+            ),
+            race: Self::create_race(
+                &self.game_modes,
+                self.race
+                    .as_ref()
+                    .map(|race| race.read().unwrap().get_config().clone())
+                    .as_ref(),
+            ),
+        }
+    }
+
+    pub fn get_level(&self) -> u32 {
+        self.level
+    }
+
+    pub fn get_shard(&self) -> u32 {
+        self.shard
+    }
+
+    pub fn get_custom_game(&self) -> Option<&CustomGame> {
+        self.custom_game.as_ref()
+    }
+
+    pub fn is_custom_game(&self) -> bool {
+        self.custom_game.is_some()
+    }
+
+    /// Whether this custom game or shard is empty and can be removed.
+    pub fn is_abandoned(&self) -> bool {
+        (self.is_custom_game() || self.shard > 1)
+            && self.created_at.elapsed() > DYNAMIC_ROOM_GRACE_PERIOD
+            && !self
+                .players
+                .values()
+                .any(|player| player.strong_count() > 0)
+    }
+
+    /// Applies changes of a reloaded room config.
+    ///
+    /// Flags are only reset, if their spawn positions changed.
+    pub fn apply_config(&mut self, config: &RoomConfig) {
+        self.name = if self.shard > 1 {
+            format!("{} #{}", config.name, self.shard)
+        } else {
+            config.name.clone()
+        };
+        self.max_players = config.max_players;
+        self.game_modes = config.game_modes.clone();
+        self.interest = config.interest;
+        match (&mut self.ctf, &config.ctf) {
+            (Some(ctf), Some(ctf_config)) if self.game_modes.contains(&GameMode::Ctf) => {
+                ctf.get_mut().unwrap().apply_config(ctf_config.clone())
+            }
+            _ => self.ctf = Self::create_ctf(&self.game_modes, config.ctf.as_ref()),
+        }
+        match (&mut self.race, &config.race) {
+            (Some(race), Some(race_config)) if self.game_modes.contains(&GameMode::Race) => {
+                race.get_mut().unwrap().apply_config(race_config.clone())
+            }
+            _ => self.race = Self::create_race(&self.game_modes, config.race.as_ref()),
+        }
+        if let Some(ctf) = &mut self.ctf {
+            let ctf = ctf.get_mut().unwrap();
+            let players = &self.players;
+            for (socket_id, player) in players.iter() {
+                if player.strong_count() > 0 && ctf.get_team(*socket_id).is_none() {
+                    ctf.assign_team(*socket_id, |socket_id| is_live_player(players, socket_id));
+                }
+            }
+        }
+
+        if self.get_flag_spawns() != config.flags {
+            self.flags = config
+                .flags
+                .iter()
+                .map(|pos| RwLock::new(Flag::new(*pos)))
+                .collect();
+        }
+    }
+
+    /// Processes flags, CTF and races.
+    ///
+    /// Returns all finished races, so that their times can be recorded.
+    pub fn process_game_modes(&self) -> Vec<RecordRaceTime> {
+        self.process_flags();
+        self.process_race()
+    }
+
     pub fn process_flags(&self) {
         self.flags.par_iter().for_each(|flag| {
             let mut flag = flag.write().unwrap();
             flag.process_falling();
             flag.process_idle();
         });
+        self.process_ctf();
+    }
+
+    /// Scores a point for each enemy flag that has been carried to its carrier's base
+    /// and ends the round, if the time is up or the score limit has been reached.
+    ///
+    /// A flag can only be captured while the own flag is at its base.
+    fn process_ctf(&self) {
+        let ctf = match &self.ctf {
+            Some(ctf) => ctf,
+            None => return,
+        };
+        let mut ctf = ctf.write().unwrap();
+        let bases = self.get_flag_spawns();
+        let flags_at_base: Vec<_> = self
+            .flags
+            .iter()
+            .map(|flag| flag.read().unwrap().at_start_position)
+            .collect();
+        let mut captures = vec![];
+
+        for flag in self.flags.iter() {
+            let mut flag = flag.write().unwrap();
+            let carrier = match flag.linked_to_player {
+                Some(carrier) => carrier,
+                None => continue,
+            };
+            let team = match ctf.get_team(carrier) {
+                Some(team) => team,
+                None => continue,
+            };
+            if !flags_at_base[team] {
+                continue;
+            }
+            let carrier_pos = self
+                .players
+                .get(&carrier)
+                .and_then(|player| player.upgrade())
+                .and_then(|player| player.read().get_data())
+                .map(|data| data.pos)
+                .filter(|pos| pos.len() >= 3);
+            if let Some(carrier_pos) = carrier_pos {
+                if interest::distance(&carrier_pos, &bases[team]) <= ctf.get_config().capture_radius
+                {
+                    flag.reset();
+                    ctf.capture(team);
+                    captures.push(team);
+                }
+            }
+        }
+
+        for &team in &captures {
+            let msg = create_server_chat_msg(format!(
+                "Team {} captured a flag!",
+                ctf.get_team_name(team)
+            ));
+            self.broadcast_message(&msg);
+        }
+
+        if ctf.is_round_over() {
+            let mut msg = ctf.get_msg(&bases, |socket_id| self.has_live_player(socket_id));
+            msg.round_ended = true;
+            let announcement = match ctf.get_winner() {
+                Some(team) => {
+                    msg.winning_team = team as u32;
+                    format!("Team {} wins round {}!", ctf.get_team_name(team), msg.round)
+                }
+                None => {
+                    msg.draw = true;
+                    format!("Round {} ended in a draw!", msg.round)
+                }
+            };
+            self.broadcast_message(&create_uncompressed_msg(sm64_js_msg::Message::CtfMsg(msg)));
+            self.broadcast_message(&create_server_chat_msg(announcement));
+
+            ctf.start_next_round();
+            self.flags
+                .iter()
+                .for_each(|flag| flag.write().unwrap().reset());
+        } else if !captures.is_empty() {
+            let msg = ctf.get_msg(&bases, |socket_id| self.has_live_player(socket_id));
+            self.broadcast_message(&create_uncompressed_msg(sm64_js_msg::Message::CtfMsg(msg)));
+        }
+    }
+
+    /// Checks the checkpoints of all racing players.
+    ///
+    /// Lap times are only announced to the racer, whereas finishes are announced to the whole room.
+    /// Returns all finished races, so that their times can be recorded.
+    /// Only races in the first shard of an official room count for the leaderboard.
+    pub fn process_race(&self) -> Vec<RecordRaceTime> {
+        let race = match &self.race {
+            Some(race) => race,
+            None => return vec![],
+        };
+        let mut race = race.write().unwrap();
+        race.remove_racers(|socket_id| self.has_live_player(socket_id));
+
+        let mut finished = vec![];
+        for player in self.players.values() {
+            let player = match player.upgrade() {
+                Some(player) => player,
+                None => continue,
+            };
+            let player = player.read();
+            let pos = match player.get_data() {
+                Some(data) => data.pos,
+                None => continue,
+            };
+            match race.process_position(player.get_socket_id(), &pos) {
+                Some(RaceEvent::Lap { lap, time, .. }) => {
+                    let msg = Self::create_announcement_msg(format!(
+                        "Lap {}: {}",
+                        lap,
+                        race::format_race_time(time)
+                    ));
+                    if let Err(err) = player.send_message(msg) {
+                        eprintln!("{:?}", err);
+                    }
+                }
+                Some(RaceEvent::Finish(finish)) => {
+                    self.broadcast_message(&Self::create_announcement_msg(format!(
+                        "{} finished in {} (best lap {})",
+                        player.get_name(),
+                        race::format_race_time(finish.time),
+                        race::format_race_time(finish.best_lap)
+                    )));
+                    let account_id = player
+                        .get_account_id()
+                        .filter(|_| !self.is_custom_game() && self.shard == 1);
+                    if let Some(account_id) = account_id {
+                        finished.push(RecordRaceTime {
+                            socket_id: finish.socket_id,
+                            level: self.level,
+                            account_id,
+                            player_name: player.get_name().clone(),
+                            time: finish.time,
+                        });
+                    }
+                }
+                None => {}
+            }
+        }
+        finished
+    }
+
+    fn create_announcement_msg(message: String) -> Vec<u8> {
+        create_uncompressed_msg(sm64_js_msg::Message::AnnouncementMsg(AnnouncementMsg {
+            message,
+            timer: 150,
+        }))
+    }
+
+    pub fn broadcast_ctf(&self) {
+        if let Some(ctf) = &self.ctf {
+            let msg = ctf
+                .read()
+                .unwrap()
+                .get_msg(&self.get_flag_spawns(), |socket_id| {
+                    self.has_live_player(socket_id)
+                });
+            self.broadcast_message(&create_uncompressed_msg(sm64_js_msg::Message::CtfMsg(msg)));
+        }
+    }
+
+    fn get_flag_spawns(&self) -> Vec<[f32; 3]> {
+        self.flags
+            .iter()
+            .map(|flag| *flag.read().unwrap().start_pos)
+            .collect()
+    }
+
+    fn has_live_player(&self, socket_id: &u32) -> bool {
+        is_live_player(&self.players, socket_id)
     }
 
     pub fn broadcast_data(&self) -> Result<()> {
+        let tick = self.tick.fetch_add(1, Ordering::Relaxed);
         let mario_list: Vec<_> = self
             .players
             .values()
@@ -134,11 +447,130 @@ impl Room {
             .iter()
             .map(|flag| flag.read().unwrap().get_msg())
             .collect();
+
+        if self.interest.is_some() || self.has_delta_players() {
+            return self.broadcast_data_per_player(tick, flag_list, mario_list);
+        }
+
+        let msg = Self::create_mario_list_msg(flag_list, mario_list)?;
+        self.broadcast_message(&msg);
+        let recipients = self
+            .players
+            .values()
+            .filter(|player| player.strong_count() > 0)
+            .count();
+        METRICS.add_broadcast_bytes((msg.len() * recipients) as u64);
+        Ok(())
+    }
+
+    /// Sends each player a separate list of Marios.
+    ///
+    /// If interest management is enabled, near Marios are sent every tick,
+    /// whereas far away Marios are only sent every n-th tick.
+    /// Players that support delta encoding only receive fields that changed since the last tick.
+    fn broadcast_data_per_player(
+        &self,
+        tick: u32,
+        flag_list: Vec<FlagMsg>,
+        mario_list: Vec<MarioMsg>,
+    ) -> Result<()> {
+        let grid = self
+            .interest
+            .map(|interest| SpatialGrid::new(interest.near_distance, &mario_list));
+        let far_update_interval = self
+            .interest
+            .map(|interest| interest.far_update_interval.max(1))
+            .unwrap_or(1);
+        let positions: HashMap<u32, &Vec<f32>> = mario_list
+            .iter()
+            .map(|mario| (mario.socket_id, &mario.pos))
+            .collect();
+        let socket_ids: HashSet<u32> = positions.keys().copied().collect();
+        // without interest management all legacy players receive the same list
+        let full_msg = if self.interest.is_none() {
+            Some(Self::create_mario_list_msg(
+                flag_list.clone(),
+                mario_list.clone(),
+            )?)
+        } else {
+            None
+        };
+
+        self.players
+            .values()
+            .par_bridge()
+            .map(|player| -> Result<()> {
+                if let Some(player) = player.upgrade() {
+                    let player = player.read();
+                    let socket_id = player.get_socket_id();
+                    let near = grid
+                        .as_ref()
+                        .and_then(|grid| positions.get(&socket_id).map(|pos| grid.get_near(pos)));
+                    let marios = mario_list.iter().filter(|mario| match &near {
+                        Some(near) => {
+                            near.contains(&mario.socket_id)
+                                || tick.wrapping_add(mario.socket_id) % far_update_interval == 0
+                        }
+                        None => true,
+                    });
+
+                    let msg = if player.supports_delta() {
+                        let mut delta_state = self.delta_states.entry(socket_id).or_default();
+                        delta_state.retain(&socket_ids);
+                        let deltas = marios.map(|mario| delta_state.encode(mario)).collect();
+                        drop(delta_state);
+                        Self::create_mario_delta_list_msg(flag_list.clone(), deltas)?
+                    } else if let Some(full_msg) = &full_msg {
+                        full_msg.clone()
+                    } else {
+                        Self::create_mario_list_msg(flag_list.clone(), marios.cloned().collect())?
+                    };
+                    METRICS.add_broadcast_bytes(msg.len() as u64);
+                    player.send_message(msg)?;
+                }
+                Ok(())
+            })
+            .filter_map(Result::ok)
+            .collect::<Vec<_>>();
+
+        self.delta_states
+            .retain(|socket_id, _| self.players.contains_key(socket_id));
+
+        Ok(())
+    }
+
+    fn has_delta_players(&self) -> bool {
+        self.players.values().any(|player| {
+            player
+                .upgrade()
+                .map(|player| player.read().supports_delta())
+                .unwrap_or_default()
+        })
+    }
+
+    fn create_mario_list_msg(
+        flag_list: Vec<FlagMsg>,
+        mario_list: Vec<MarioMsg>,
+    ) -> Result<Vec<u8>> {
+        Self::create_compressed_msg(sm64_js_msg::Message::ListMsg(MarioListMsg {
+            flag: flag_list,
+            mario: mario_list,
+        }))
+    }
+
+    fn create_mario_delta_list_msg(
+        flag_list: Vec<FlagMsg>,
+        mario_list: Vec<MarioDeltaMsg>,
+    ) -> Result<Vec<u8>> {
+        Self::create_compressed_msg(sm64_js_msg::Message::DeltaListMsg(MarioDeltaListMsg {
+            flag: flag_list,
+            mario: mario_list,
+        }))
+    }
+
+    fn create_compressed_msg(message: sm64_js_msg::Message) -> Result<Vec<u8>> {
         let sm64js_msg = Sm64JsMsg {
-            message: Some(sm64_js_msg::Message::ListMsg(MarioListMsg {
-                flag: flag_list,
-                mario: mario_list,
-            })),
+            message: Some(message),
         };
         let mut msg = vec![];
         sm64js_msg.encode(&mut msg)?;
@@ -153,8 +585,7 @@ impl Room {
         let mut msg = vec![];
         root_msg.encode(&mut msg)?;
 
-        self.broadcast_message(&msg);
-        Ok(())
+        Ok(msg)
     }
 
     pub fn broadcast_skins(&self) -> Result<()> {
@@ -215,7 +646,8 @@ impl Room {
 
     pub fn get_and_send_valid_players(&self) -> ValidPlayersMsg {
         let valid_players = ValidPlayersMsg {
-            level_id: self.id,
+            level_id: self.level,
+            shard: self.shard,
             valid_players: self
                 .players
                 .iter()
@@ -249,24 +681,92 @@ impl Room {
         }
     }
 
+    pub fn get_player(&self, socket_id: u32) -> Option<Arc<parking_lot::RwLock<Player>>> {
+        self.players
+            .get(&socket_id)
+            .and_then(|player| player.upgrade())
+    }
+
+    pub fn is_full(&self) -> bool {
+        if let Some(max_players) = self.max_players {
+            self.players
+                .values()
+                .filter(|player| player.strong_count() > 0)
+                .count()
+                >= max_players
+        } else {
+            false
+        }
+    }
+
+    pub fn has_game_mode(&self, game_mode: GameMode) -> bool {
+        self.game_modes.contains(&game_mode)
+    }
+
     pub fn add_player(&mut self, socket_id: u32, player: Weak<parking_lot::RwLock<Player>>) {
         self.players.insert(socket_id, player);
+        self.delta_states.remove(&socket_id);
+        if let Some(ctf) = &mut self.ctf {
+            let players = &self.players;
+            ctf.get_mut()
+                .unwrap()
+                .assign_team(socket_id, |socket_id| is_live_player(players, socket_id));
+        }
     }
 
-    pub fn process_attack(&self, flag_id: usize, attacker_pos: Vec<f32>, target_id: u32) {
-        if let Some(flag) = self.flags.get(flag_id) {
-            let flag = &mut *flag.write().unwrap();
-            if let Some(link_id) = flag.linked_to_player {
-                // TODO use target_id to determine valid attack
-                if link_id != target_id {
-                    return;
-                }
-                flag.drop(&attacker_pos);
+    /// Knocks the flag off the target, if the attack is valid.
+    ///
+    /// The attack is validated against the last known positions of both players.
+    pub fn process_attack(
+        &self,
+        flag_id: usize,
+        attacker_id: u32,
+        attacker_pos: Vec<f32>,
+        target_id: u32,
+    ) -> Result<(), AttackError> {
+        let target_pos = self
+            .players
+            .get(&target_id)
+            .and_then(|player| player.upgrade())
+            .and_then(|player| player.read().get_data())
+            .map(|data| data.pos)
+            .filter(|pos| pos.len() >= 3)
+            .ok_or(AttackError::TargetNotFound(target_id))?;
+        if attacker_pos.len() < 3 {
+            return Err(AttackError::AttackerNotFound(attacker_id));
+        }
+        let distance = interest::distance(&attacker_pos, &target_pos);
+        if distance > MAX_ATTACK_DISTANCE {
+            return Err(AttackError::TooFar(distance));
+        }
+        // teams must be looked up before locking a flag, because `process_ctf` locks in this order
+        if let Some(ctf) = &self.ctf {
+            let ctf = ctf.read().unwrap();
+            if ctf.get_team(attacker_id) == ctf.get_team(target_id) {
+                return Err(AttackError::Teammate);
             }
         }
+
+        let flag = self
+            .flags
+            .get(flag_id)
+            .ok_or(AttackError::InvalidFlag(flag_id))?;
+        let flag = &mut *flag.write().unwrap();
+        if flag.linked_to_player != Some(target_id) {
+            return Err(AttackError::NotHoldingFlag);
+        }
+        flag.drop(&attacker_pos);
+        Ok(())
     }
 
     pub fn process_grab_flag(&self, flag_id: usize, pos: Vec<f32>, socket_id: u32) {
+        let team = match &self.ctf {
+            Some(ctf) => match ctf.read().unwrap().get_team(socket_id) {
+                Some(team) => Some(team),
+                None => return,
+            },
+            None => None,
+        };
         if let Some(flag) = self.flags.get(flag_id) {
             let mut flag = flag.write().unwrap();
             if flag.linked_to_player.is_some() {
@@ -276,6 +776,13 @@ impl Room {
             let z_diff = pos[2] - flag.pos[2];
             let dist = (x_diff * x_diff + z_diff * z_diff).sqrt();
             if dist < 50. {
+                // players cannot carry their own flag, but they can return it to their base
+                if team == Some(flag_id) {
+                    if !flag.at_start_position {
+                        flag.reset();
+                    }
+                    return;
+                }
                 flag.linked_to_player = Some(socket_id);
                 flag.fall_mode = false;
                 flag.at_start_position = false;
@@ -368,6 +875,85 @@ impl Room {
     }
 }
 
+#[derive(Debug, Error)]
+pub enum AttackError {
+    #[error("attacker {0} has no known position")]
+    AttackerNotFound(u32),
+    #[error("target {0} is not in this room")]
+    TargetNotFound(u32),
+    #[error("target is {0} units away")]
+    TooFar(f32),
+    #[error("attacker is on cooldown")]
+    Cooldown,
+    #[error("flag {0} does not exist")]
+    InvalidFlag(usize),
+    #[error("target is not holding the flag")]
+    NotHoldingFlag,
+    #[error("target is a teammate")]
+    Teammate,
+}
+
+impl AttackError {
+    /// Whether this attack could not have been sent by an unmodified client.
+    ///
+    /// A flag that has already been knocked off by someone else, an attack on a teammate
+    /// or an attack that has been sent slightly too fast is no indication of cheating.
+    pub fn is_suspicious(&self) -> bool {
+        !matches!(self, Self::NotHoldingFlag | Self::Teammate | Self::Cooldown)
+    }
+}
+
+/// Rejected attacks of an account
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RejectedAttacks {
+    /// Attacks that could not have been sent by an unmodified client
+    pub suspicious: u32,
+    /// Attacks that have been sent before the cooldown passed
+    pub cooldown: u32,
+}
+
+fn is_live_player(players: &WeakPlayers, socket_id: &u32) -> bool {
+    players
+        .get(socket_id)
+        .map(|player| player.strong_count() > 0)
+        .unwrap_or_default()
+}
+
+#[derive(Debug)]
+pub struct CustomGame {
+    pub game_id: String,
+    pub password: Option<PasswordHash>,
+    pub host_account_id: i32,
+}
+
+/// Salted SHA-256 hash of a custom game password
+#[derive(Debug)]
+pub struct PasswordHash {
+    salt: [u8; 16],
+    hash: Vec<u8>,
+}
+
+impl PasswordHash {
+    pub fn new(password: &str) -> Self {
+        let salt: [u8; 16] = rand::thread_rng().gen();
+        PasswordHash {
+            hash: Self::hash(&salt, password),
+            salt,
+        }
+    }
+
+    pub fn verify(&self, password: &str) -> bool {
+        Self::hash(&self.salt, password) == self.hash
+    }
+
+    fn hash(salt: &[u8], password: &str) -> Vec<u8> {
+        let mut hasher = Sha256::new();
+        hasher.update(salt);
+        hasher.update(password.as_bytes());
+        hasher.finalize().to_vec()
+    }
+}
+
 #[derive(Debug)]
 pub struct Flag {
     pos: Box<[f32; 3]>,
@@ -410,6 +996,14 @@ impl Flag {
         }
     }
 
+    fn reset(&mut self) {
+        self.pos = self.start_pos.clone();
+        self.linked_to_player = None;
+        self.fall_mode = false;
+        self.at_start_position = true;
+        self.idle_timer = 0;
+    }
+
     pub fn get_msg(&self) -> FlagMsg {
         FlagMsg {
             pos: self.pos.to_vec(),
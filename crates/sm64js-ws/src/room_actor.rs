@@ -0,0 +1,308 @@
+use crate::{
+    anticheat::{AntiCheat, CheatResponse, MarioUpdate},
+    metrics::METRICS,
+    room::{AttackError, RejectedAttacks},
+    server::{Disconnect, Message, RecordRaceTime},
+    Clients, Room, Rooms, Sm64JsServer,
+};
+
+use actix::prelude::*;
+use dashmap::DashMap;
+use sm64js_proto::{sm64_js_msg, AttackMsg, GrabFlagMsg, MarioMsg, SkinMsg};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+/// Minimum time between two attacks of the same player
+const ATTACK_COOLDOWN: Duration = Duration::from_millis(500);
+
+pub type RoomActors = Arc<DashMap<u32, Addr<RoomActor>>>;
+
+/// Handles the per-frame traffic of a single room.
+///
+/// Every room that has been joined gets its own actor, which runs on one of the
+/// room arbiters of the [`Sm64JsServer`]. Rooms are therefore processed independently
+/// and the server actor only handles connects, joins and moderation.
+/// Periodic tasks are sent by the `Game` scheduler.
+/// The actor stops itself, once its room has been removed.
+pub struct RoomActor {
+    room_id: u32,
+    rooms: Rooms,
+    clients: Arc<Clients>,
+    /// Receives finished races, usually the [`Sm64JsServer`]
+    race_times: Recipient<RecordRaceTime>,
+    /// Receives kicked clients, usually the [`Sm64JsServer`]
+    disconnects: Recipient<Disconnect>,
+    anticheat: Arc<AntiCheat>,
+    rejected_attacks: Arc<DashMap<i32, RejectedAttacks>>,
+}
+
+impl Actor for RoomActor {
+    type Context = Context<Self>;
+}
+
+impl RoomActor {
+    pub fn new(
+        room_id: u32,
+        rooms: Rooms,
+        clients: Arc<Clients>,
+        race_times: Recipient<RecordRaceTime>,
+        disconnects: Recipient<Disconnect>,
+        anticheat: Arc<AntiCheat>,
+        rejected_attacks: Arc<DashMap<i32, RejectedAttacks>>,
+    ) -> Self {
+        RoomActor {
+            room_id,
+            rooms,
+            clients,
+            race_times,
+            disconnects,
+            anticheat,
+            rejected_attacks,
+        }
+    }
+
+    /// Runs a periodic task on the room and records its duration.
+    ///
+    /// Stops the actor, if the room has been removed.
+    fn run_task<F, R>(&self, ctx: &mut Context<Self>, task: F) -> Option<R>
+    where
+        F: FnOnce(&Room) -> R,
+    {
+        let room = match self.rooms.get(&self.room_id) {
+            Some(room) => room,
+            None => {
+                ctx.stop();
+                return None;
+            }
+        };
+        let started_at = Instant::now();
+        let res = task(&room);
+        METRICS.record_room_task(started_at.elapsed());
+        Some(res)
+    }
+
+    fn is_in_room(&self, socket_id: u32) -> bool {
+        self.clients
+            .get(&socket_id)
+            .and_then(|client| client.get_level())
+            == Some(self.room_id)
+    }
+}
+
+/// Processes flags, CTF and races
+#[derive(Clone, Copy, Message)]
+#[rtype(result = "()")]
+pub struct ProcessGameModes;
+
+impl Handler<ProcessGameModes> for RoomActor {
+    type Result = ();
+
+    fn handle(&mut self, _: ProcessGameModes, ctx: &mut Context<Self>) {
+        let race_times = self.run_task(ctx, |room| room.process_game_modes());
+        for race_time in race_times.into_iter().flatten() {
+            if let Err(err) = self.race_times.do_send(race_time) {
+                eprintln!("{:?}", err);
+            }
+        }
+    }
+}
+
+/// Broadcasts all Marios
+#[derive(Clone, Copy, Message)]
+#[rtype(result = "()")]
+pub struct BroadcastData;
+
+impl Handler<BroadcastData> for RoomActor {
+    type Result = ();
+
+    fn handle(&mut self, _: BroadcastData, ctx: &mut Context<Self>) {
+        if let Some(Err(err)) = self.run_task(ctx, |room| room.broadcast_data()) {
+            eprintln!("{:?}", err);
+        }
+    }
+}
+
+/// Broadcasts updated skins and the CTF state
+#[derive(Clone, Copy, Message)]
+#[rtype(result = "()")]
+pub struct BroadcastSkins;
+
+impl Handler<BroadcastSkins> for RoomActor {
+    type Result = ();
+
+    fn handle(&mut self, _: BroadcastSkins, ctx: &mut Context<Self>) {
+        if let Some(Err(err)) = self.run_task(ctx, |room| {
+            room.broadcast_ctf();
+            room.broadcast_skins()
+        }) {
+            eprintln!("{:?}", err);
+        }
+    }
+}
+
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct SetData {
+    pub socket_id: u32,
+    pub data: MarioMsg,
+}
+
+impl Handler<SetData> for RoomActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: SetData, _: &mut Context<Self>) {
+        let mut client = match self.clients.get_mut(&msg.socket_id) {
+            Some(client) => client,
+            None => return,
+        };
+        if client.get_level() != Some(self.room_id) {
+            return;
+        }
+        let last_update = client.get_last_update();
+        let update = MarioUpdate {
+            socket_id: msg.socket_id,
+            prev: last_update.map(|(data, _)| data),
+            next: &msg.data,
+            elapsed: last_update.map(|(_, elapsed)| elapsed).unwrap_or_default(),
+            rejected_updates: client.get_rejected_updates(),
+        };
+        match self.anticheat.validate(client.get_account_id(), &update) {
+            None | Some(CheatResponse::Flag) => client.set_data(msg.data),
+            Some(CheatResponse::Drop) => client.reject_update(),
+            Some(CheatResponse::RubberBand) => {
+                client.reject_update();
+                if let Some((data, _)) = client.get_last_update() {
+                    let msg = Sm64JsServer::create_uncompressed_msg(
+                        sm64_js_msg::Message::RubberBandMsg(data.clone()),
+                    );
+                    if let Err(err) = client.send(Message::SendData(msg)) {
+                        eprintln!("{:?}", err);
+                    }
+                }
+            }
+            Some(CheatResponse::Kick) => {
+                if let Err(err) = client.send(Message::Kick) {
+                    eprintln!("{:?}", err);
+                }
+                drop(client);
+                if let Err(err) = self.disconnects.do_send(Disconnect {
+                    socket_id: msg.socket_id,
+                }) {
+                    eprintln!("{:?}", err);
+                }
+            }
+        }
+    }
+}
+
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct SendAttack {
+    pub socket_id: u32,
+    pub attack_msg: AttackMsg,
+}
+
+impl Handler<SendAttack> for RoomActor {
+    type Result = ();
+
+    fn handle(&mut self, send_attack: SendAttack, _: &mut Context<Self>) {
+        let socket_id = send_attack.socket_id;
+        let attack_msg = send_attack.attack_msg;
+        let (attacker_pos, account_id, is_ready) = {
+            let client = match self.clients.get(&socket_id) {
+                Some(client) => client,
+                None => return,
+            };
+            if client.get_level() != Some(self.room_id) {
+                return;
+            }
+            (
+                client.get_pos().cloned().unwrap_or_default(),
+                client.get_account_id(),
+                client.is_attack_ready(ATTACK_COOLDOWN),
+            )
+        };
+        let result = if is_ready {
+            match self.rooms.get(&self.room_id) {
+                Some(room) => room.process_attack(
+                    attack_msg.flag_id as usize,
+                    socket_id,
+                    attacker_pos,
+                    attack_msg.target_socket_id,
+                ),
+                None => return,
+            }
+        } else {
+            Err(AttackError::Cooldown)
+        };
+        match result {
+            Ok(()) => {
+                if let Some(mut client) = self.clients.get_mut(&socket_id) {
+                    client.set_attacked();
+                }
+            }
+            Err(AttackError::Cooldown) => {
+                self.rejected_attacks
+                    .entry(account_id)
+                    .or_default()
+                    .cooldown += 1;
+            }
+            Err(err) if err.is_suspicious() => {
+                self.rejected_attacks
+                    .entry(account_id)
+                    .or_default()
+                    .suspicious += 1;
+            }
+            Err(_) => {}
+        }
+    }
+}
+
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct SendGrabFlag {
+    pub socket_id: u32,
+    pub grab_flag_msg: GrabFlagMsg,
+}
+
+impl Handler<SendGrabFlag> for RoomActor {
+    type Result = ();
+
+    fn handle(&mut self, send_grab: SendGrabFlag, _: &mut Context<Self>) {
+        let socket_id = send_grab.socket_id;
+        let grab_flag_msg = send_grab.grab_flag_msg;
+        if !self.is_in_room(socket_id) {
+            return;
+        }
+        if let Some(room) = self.rooms.get(&self.room_id) {
+            let flag_id = grab_flag_msg.flag_id as usize;
+            let pos = grab_flag_msg.pos;
+            room.process_grab_flag(flag_id, pos, socket_id);
+        }
+    }
+}
+
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct SendSkin {
+    pub socket_id: u32,
+    pub skin_msg: SkinMsg,
+}
+
+impl Handler<SendSkin> for RoomActor {
+    type Result = ();
+
+    fn handle(&mut self, send_skin: SendSkin, _: &mut Context<Self>) {
+        let socket_id = send_skin.socket_id;
+        let skin_msg = send_skin.skin_msg;
+        if let Some(player) = self
+            .rooms
+            .get(&self.room_id)
+            .and_then(|room| room.get_player(socket_id))
+        {
+            player.write().set_skin_data(skin_msg.skin_data);
+        }
+    }
+}
@@ -0,0 +1,303 @@
+use crate::{
+    ctf::CtfConfig, interest::InterestConfig, race::RaceConfig, room::DYNAMIC_ROOM_ID_START,
+};
+
+use serde::Deserialize;
+use std::{collections::HashSet, fs::read_to_string, io, path::Path};
+use thiserror::Error;
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct RoomsConfig {
+    pub rooms: Vec<RoomConfig>,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct RoomConfig {
+    /// Level id that is also internally used by Super Mario 64.
+    /// Custom levels should use a level id >= 1000
+    pub id: u32,
+    pub name: String,
+    #[serde(default)]
+    pub flags: Vec<[f32; 3]>,
+    /// Maximum number of players. Unlimited, if not set
+    #[serde(default)]
+    pub max_players: Option<usize>,
+    #[serde(default)]
+    pub game_modes: Vec<GameMode>,
+    /// Only send nearby players every tick. Everyone is sent every tick, if not set
+    #[serde(default)]
+    pub interest: Option<InterestConfig>,
+    /// Required by game mode `Ctf`
+    #[serde(default)]
+    pub ctf: Option<CtfConfig>,
+    /// Required by game mode `Race`
+    #[serde(default)]
+    pub race: Option<RaceConfig>,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq)]
+pub enum GameMode {
+    /// Grab a flag and keep it as long as possible
+    Flag,
+    /// Capture the flag of another team and bring it to your base
+    Ctf,
+    /// Pass all checkpoints in order as fast as possible
+    Race,
+}
+
+impl RoomsConfig {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, RoomConfigError> {
+        let path = path.as_ref();
+        let config = read_to_string(path).map_err(|err| RoomConfigError::Io {
+            path: path.display().to_string(),
+            source: err,
+        })?;
+        let config: Self = ron::from_str(&config)?;
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<(), RoomConfigError> {
+        let mut ids = HashSet::new();
+        for room in self.rooms.iter() {
+            if room.id == 0 {
+                return Err(RoomConfigError::ReservedId(room.name.clone()));
+            }
+            if room.id >= DYNAMIC_ROOM_ID_START {
+                return Err(RoomConfigError::IdOutOfRange(room.id));
+            }
+            if !ids.insert(room.id) {
+                return Err(RoomConfigError::DuplicateId(room.id));
+            }
+            if room.name.trim().is_empty() {
+                return Err(RoomConfigError::EmptyName(room.id));
+            }
+            if room.max_players == Some(0) {
+                return Err(RoomConfigError::InvalidMaxPlayers(room.id));
+            }
+            if room
+                .flags
+                .iter()
+                .any(|flag| flag.iter().any(|coord| !coord.is_finite()))
+            {
+                return Err(RoomConfigError::InvalidFlagPosition(room.id));
+            }
+            if let Some(interest) = room.interest {
+                if !interest.near_distance.is_finite()
+                    || interest.near_distance <= 0.
+                    || interest.far_update_interval == 0
+                {
+                    return Err(RoomConfigError::InvalidInterest(room.id));
+                }
+            }
+            let has_flag_mode = room.game_modes.contains(&GameMode::Flag);
+            let has_ctf_mode = room.game_modes.contains(&GameMode::Ctf);
+            if has_flag_mode && has_ctf_mode {
+                return Err(RoomConfigError::ConflictingGameModes(room.id));
+            }
+            if !room.flags.is_empty() && !has_flag_mode && !has_ctf_mode {
+                return Err(RoomConfigError::FlagsWithoutGameMode(room.id));
+            }
+            if room.flags.is_empty() && has_flag_mode {
+                return Err(RoomConfigError::GameModeWithoutFlags(room.id));
+            }
+            match (&room.ctf, has_ctf_mode) {
+                (Some(ctf), true) => {
+                    if ctf.teams.len() < 2 || ctf.teams.len() != room.flags.len() {
+                        return Err(RoomConfigError::InvalidCtfTeams(room.id));
+                    }
+                    if !ctf.capture_radius.is_finite()
+                        || ctf.capture_radius <= 0.
+                        || ctf.round_duration == 0
+                        || ctf.score_limit == 0
+                    {
+                        return Err(RoomConfigError::InvalidCtf(room.id));
+                    }
+                }
+                (None, false) => {}
+                _ => return Err(RoomConfigError::CtfConfigMismatch(room.id)),
+            }
+            match (&room.race, room.game_modes.contains(&GameMode::Race)) {
+                (Some(race), true) => {
+                    if race.checkpoints.len() < 2
+                        || race.laps == 0
+                        || !race
+                            .checkpoints
+                            .iter()
+                            .all(|checkpoint| checkpoint.is_valid())
+                    {
+                        return Err(RoomConfigError::InvalidRace(room.id));
+                    }
+                }
+                (None, false) => {}
+                _ => return Err(RoomConfigError::RaceConfigMismatch(room.id)),
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum RoomConfigError {
+    #[error("[Io]: could not read room config {path}: {source}")]
+    Io { path: String, source: io::Error },
+    #[error("[Ron]: {0}")]
+    Ron(#[from] ron::Error),
+    #[error("room \"{0}\" uses reserved id 0")]
+    ReservedId(String),
+    #[error("room id {0} is out of range. Ids must be lower than 16777216")]
+    IdOutOfRange(u32),
+    #[error("room id {0} is defined more than once")]
+    DuplicateId(u32),
+    #[error("room {0} has an empty name")]
+    EmptyName(u32),
+    #[error("room {0} must allow at least one player")]
+    InvalidMaxPlayers(u32),
+    #[error("room {0} has a flag spawn position that is not a finite number")]
+    InvalidFlagPosition(u32),
+    #[error("room {0} must have a positive near distance and far update interval")]
+    InvalidInterest(u32),
+    #[error(
+        "room {0} has flag spawn positions, but neither game mode `Flag` nor `Ctf` is enabled"
+    )]
+    FlagsWithoutGameMode(u32),
+    #[error("room {0} enables game mode `Flag`, but has no flag spawn positions")]
+    GameModeWithoutFlags(u32),
+    #[error("room {0} cannot enable game modes `Flag` and `Ctf` at the same time")]
+    ConflictingGameModes(u32),
+    #[error("room {0} must enable game mode `Ctf` together with a ctf config")]
+    CtfConfigMismatch(u32),
+    #[error("room {0} needs at least two CTF teams and exactly one flag per team")]
+    InvalidCtfTeams(u32),
+    #[error("room {0} must have a positive capture radius, round duration and score limit")]
+    InvalidCtf(u32),
+    #[error("room {0} must enable game mode `Race` together with a race config")]
+    RaceConfigMismatch(u32),
+    #[error("room {0} needs at least two valid checkpoints and one lap")]
+    InvalidRace(u32),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn validate(rooms: &str) -> Result<(), RoomConfigError> {
+        let config: RoomsConfig = ron::from_str(&format!("(rooms: [{}])", rooms))?;
+        config.validate()
+    }
+
+    #[test]
+    fn default_config_is_valid() {
+        let config: RoomsConfig = ron::from_str(include_str!("../../../rooms.ron")).unwrap();
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn minimal_room_is_valid() {
+        assert!(validate(r#"(id: 6, name: "Castle Inside First Level")"#).is_ok());
+    }
+
+    #[test]
+    fn rejects_invalid_ids() {
+        assert!(matches!(
+            validate(r#"(id: 0, name: "Lobby")"#),
+            Err(RoomConfigError::ReservedId(_))
+        ));
+        assert!(matches!(
+            validate(r#"(id: 16777216, name: "Custom")"#),
+            Err(RoomConfigError::IdOutOfRange(16777216))
+        ));
+        assert!(matches!(
+            validate(r#"(id: 4, name: "A"), (id: 4, name: "B")"#),
+            Err(RoomConfigError::DuplicateId(4))
+        ));
+    }
+
+    #[test]
+    fn rejects_empty_name_and_capacity() {
+        assert!(matches!(
+            validate(r#"(id: 4, name: "  ")"#),
+            Err(RoomConfigError::EmptyName(4))
+        ));
+        assert!(matches!(
+            validate(r#"(id: 4, name: "A", max_players: Some(0))"#),
+            Err(RoomConfigError::InvalidMaxPlayers(4))
+        ));
+    }
+
+    #[test]
+    fn flags_require_flag_game_mode() {
+        assert!(matches!(
+            validate(r#"(id: 4, name: "A", flags: [(0., 0., 0.)])"#),
+            Err(RoomConfigError::FlagsWithoutGameMode(4))
+        ));
+        assert!(matches!(
+            validate(r#"(id: 4, name: "A", game_modes: [Flag])"#),
+            Err(RoomConfigError::GameModeWithoutFlags(4))
+        ));
+        assert!(matches!(
+            validate(r#"(id: 4, name: "A", flags: [(0., 0., 0.)], game_modes: [Flag, Ctf])"#),
+            Err(RoomConfigError::ConflictingGameModes(4))
+        ));
+    }
+
+    #[test]
+    fn ctf_requires_one_flag_per_team() {
+        let room = |flags: &str| {
+            format!(
+                r#"(
+                    id: 4,
+                    name: "A",
+                    flags: [{}],
+                    game_modes: [Ctf],
+                    ctf: Some((
+                        teams: ["Red", "Blue"],
+                        capture_radius: 200.,
+                        round_duration: 600,
+                        score_limit: 3,
+                    )),
+                )"#,
+                flags
+            )
+        };
+        assert!(validate(&room("(0., 0., 0.), (100., 0., 0.)")).is_ok());
+        assert!(matches!(
+            validate(&room("(0., 0., 0.)")),
+            Err(RoomConfigError::InvalidCtfTeams(4))
+        ));
+        assert!(matches!(
+            validate(r#"(id: 4, name: "A", flags: [(0., 0., 0.)], game_modes: [Ctf])"#),
+            Err(RoomConfigError::CtfConfigMismatch(4))
+        ));
+    }
+
+    #[test]
+    fn race_requires_valid_checkpoints() {
+        let room = |max: &str| {
+            format!(
+                r#"(
+                    id: 4,
+                    name: "A",
+                    game_modes: [Race],
+                    race: Some((
+                        checkpoints: [
+                            (min: (0., 0., 0.), max: (100., 100., 100.)),
+                            (min: (200., 0., 0.), max: {}),
+                        ],
+                    )),
+                )"#,
+                max
+            )
+        };
+        assert!(validate(&room("(300., 100., 100.)")).is_ok());
+        assert!(matches!(
+            validate(&room("(100., 100., 100.)")),
+            Err(RoomConfigError::InvalidRace(4))
+        ));
+        assert!(matches!(
+            validate(r#"(id: 4, name: "A", game_modes: [Race])"#),
+            Err(RoomConfigError::RaceConfigMismatch(4))
+        ));
+    }
+}
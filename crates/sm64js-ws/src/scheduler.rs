@@ -0,0 +1,143 @@
+use crate::metrics::METRICS;
+
+pub use sm64js_env::TickPolicy;
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+/// Maximum number of missed ticks that are run back-to-back with `TickPolicy::CatchUp`.
+/// All ticks that are missed beyond that are skipped
+const MAX_CATCH_UP_TICKS: u32 = 5;
+
+struct Task {
+    name: &'static str,
+    /// Interval in ticks
+    interval: u64,
+    next_run: u64,
+    run: Box<dyn FnMut(u64) + Send>,
+}
+
+/// Fixed-timestep scheduler.
+///
+/// Ticks are scheduled relative to the start of the scheduler instead of the end of the
+/// previous tick, so that the tick rate does not drift with load.
+pub struct Scheduler {
+    tick_duration: Duration,
+    policy: TickPolicy,
+    tasks: Vec<Task>,
+}
+
+impl Scheduler {
+    pub fn new(tick_rate: u32, policy: TickPolicy) -> Self {
+        Scheduler {
+            tick_duration: Duration::from_secs(1) / tick_rate.max(1),
+            policy,
+            tasks: vec![],
+        }
+    }
+
+    /// Adds a task that runs every `interval`, rounded to whole ticks.
+    /// Tasks run in the order they have been added.
+    pub fn add_task<F>(&mut self, name: &'static str, interval: Duration, task: F) -> &mut Self
+    where
+        F: FnMut(u64) + Send + 'static,
+    {
+        let interval = (interval.as_secs_f64() / self.tick_duration.as_secs_f64()).round() as u64;
+        self.tasks.push(Task {
+            name,
+            interval: interval.max(1),
+            next_run: 0,
+            run: Box::new(task),
+        });
+        self
+    }
+
+    pub fn start(self) -> SchedulerHandle {
+        let running = Arc::new(AtomicBool::new(true));
+        let thread = {
+            let running = running.clone();
+            thread::spawn(move || self.run(&running))
+        };
+        SchedulerHandle {
+            running,
+            thread: Some(thread),
+        }
+    }
+
+    fn run(mut self, running: &AtomicBool) {
+        let mut tick = 0u64;
+        let mut next_tick = Instant::now();
+        while running.load(Ordering::Relaxed) {
+            let now = Instant::now();
+            if now < next_tick {
+                thread::sleep(next_tick - now);
+            } else {
+                let missed = ((now - next_tick).as_nanos() / self.tick_duration.as_nanos()) as u32;
+                let skipped = match self.policy {
+                    TickPolicy::CatchUp => missed.saturating_sub(MAX_CATCH_UP_TICKS),
+                    TickPolicy::Skip => missed,
+                };
+                if skipped > 0 {
+                    tick += skipped as u64;
+                    next_tick += self.tick_duration * skipped;
+                    METRICS.add_skipped_ticks(skipped as u64);
+                }
+            }
+
+            let started_at = Instant::now();
+            for task in self.tasks.iter_mut() {
+                if tick >= task.next_run {
+                    let task_started_at = Instant::now();
+                    (task.run)(tick);
+                    let task_duration = task_started_at.elapsed();
+                    if task_duration > self.tick_duration {
+                        eprintln!(
+                            "task {} took {:?} in tick {}",
+                            task.name, task_duration, tick
+                        );
+                    }
+                    task.next_run = tick - tick % task.interval + task.interval;
+                }
+            }
+            let duration = started_at.elapsed();
+            METRICS.record_tick(duration);
+
+            tick += 1;
+            next_tick += self.tick_duration;
+        }
+    }
+}
+
+/// Stops the scheduler, when dropped.
+#[must_use]
+pub struct SchedulerHandle {
+    running: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl SchedulerHandle {
+    /// Stops the scheduler after the current tick and waits for it.
+    pub fn stop(mut self) {
+        self.stop_and_join();
+    }
+
+    fn stop_and_join(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            if thread.join().is_err() {
+                eprintln!("scheduler thread panicked");
+            }
+        }
+    }
+}
+
+impl Drop for SchedulerHandle {
+    fn drop(&mut self) {
+        self.stop_and_join();
+    }
+}
@@ -1,52 +1,143 @@
-use crate::{Client, Clients, Player, Players, Rooms};
+use crate::{
+    anticheat::{self, AntiCheat},
+    command::{self, ChatCommand, CommandError},
+    room::{CustomGame, PasswordHash, RejectedAttacks, DYNAMIC_ROOM_ID_START},
+    room_actor::{RoomActor, RoomActors},
+    Client, Clients, Player, Players, Room, RoomConfigError, Rooms, RoomsConfig,
+};
 use actix::{prelude::*, Recipient};
 use actix_web::web;
 use anyhow::Result;
-use chrono::{Duration, Utc};
+use chrono::{Duration, NaiveDateTime, Utc};
 use dashmap::{mapref::one::Ref, DashMap};
+use diesel::{Connection, PgConnection};
 use humantime::format_duration;
-use once_cell::sync::Lazy;
-use parking_lot::{Mutex, RwLock};
+use parking_lot::RwLock;
 use prost::Message as ProstMessage;
 use rand::{self, Rng};
 use rustrict::CensorStr;
 use sm64js_auth::{AuthInfo, Permission};
 use sm64js_common::{
-    sanitize_chat, send_discord_message, ChatError, ChatHistoryData, ChatResult, GetChat,
-    PlayerInfo,
+    create_server_chat_msg, sanitize_chat, send_discord_message, ChatAction, ChatFilterConfigError,
+    ChatFiltersConfig, ChatHistoryData, ChatResult, EscalationPunishment, EscalationStep,
+    FlaggedAccount, ModerationActionKind, ModerationSource, PlayerInfo, ServerStatus,
+};
+use sm64js_db::{
+    models::{NewGeolocation, NewModerationAction},
+    DbPool,
+};
+use sm64js_env::{
+    ANTICHEAT_RESPONSE, CHAT_FILTERS_CONFIG, CHAT_FILTERS_CONFIG_WATCH, MAINTENANCE_MODE,
+    ROOMS_CONFIG, ROOMS_CONFIG_WATCH,
 };
-use sm64js_db::DbPool;
 use sm64js_proto::{
-    root_msg, sm64_js_msg, AnnouncementMsg, AttackMsg, ChatMsg, GrabFlagMsg, JoinGameMsg, MarioMsg,
-    RootMsg, SkinMsg, Sm64JsMsg,
+    root_msg, sm64_js_msg, AnnouncementMsg, ChatMsg, JoinGameMsg, RootMsg, Sm64JsMsg,
+};
+use std::{
+    collections::HashMap,
+    fs,
+    sync::Arc,
+    time::{self, Instant, SystemTime},
 };
-use std::{collections::HashMap, sync::Arc, time};
-
-pub static PRIVILEGED_COMMANDS: Lazy<Mutex<HashMap<&str, Permission>>> = Lazy::new(|| {
-    let mut m = HashMap::new();
-    m.insert("ANNOUNCEMENT", Permission::SendAnnouncement);
-    Mutex::new(m)
-});
 
 #[derive(Message)]
 #[rtype(result = "()")]
 pub enum Message {
     SendData(Vec<u8>),
     Kick,
+    /// Closes the connection with the given reason
+    Close(String),
 }
 
+/// How often the room config file is checked for changes
+const ROOMS_CONFIG_WATCH_INTERVAL: time::Duration = time::Duration::from_secs(5);
+
+/// How often the chat filter config file is checked for changes
+const CHAT_FILTERS_CONFIG_WATCH_INTERVAL: time::Duration = time::Duration::from_secs(5);
+
+/// How often new anti-cheat violations are saved to the database
+const FLAGGED_ACCOUNTS_SAVE_INTERVAL: time::Duration = time::Duration::from_secs(30);
+
+/// How often the remaining time is checked during a shutdown countdown
+const SHUTDOWN_ANNOUNCEMENT_INTERVAL: time::Duration = time::Duration::from_secs(1);
+
 pub struct Sm64JsServer {
     pool: web::Data<DbPool>,
     clients: Arc<Clients>,
     players: Players,
     rooms: Rooms,
     chat_history: ChatHistoryData,
+    /// Config that has been applied to the rooms
+    rooms_config: RoomsConfig,
+    rooms_config_modified: Option<SystemTime>,
+    chat_filters_config_modified: Option<SystemTime>,
+    rejected_attacks: Arc<DashMap<i32, RejectedAttacks>>,
+    anticheat: Arc<AntiCheat>,
+    room_actors: RoomActors,
+    /// Threads on which room actors are running
+    room_arbiters: Vec<Arbiter>,
+    next_room_arbiter: usize,
+    /// Only accounts with `Permission::JoinDuringMaintenance` can join
+    maintenance: bool,
+    shutting_down: bool,
 }
 
 impl Actor for Sm64JsServer {
     type Context = Context<Self>;
 
-    fn started(&mut self, _: &mut Self::Context) {}
+    fn started(&mut self, ctx: &mut Self::Context) {
+        self.room_arbiters = (0..num_cpus::get()).map(|_| Arbiter::new()).collect();
+
+        ctx.run_interval(FLAGGED_ACCOUNTS_SAVE_INTERVAL, |act, _| {
+            let flagged_accounts = act.anticheat.take_unsaved_accounts();
+            if flagged_accounts.is_empty() {
+                return;
+            }
+            let pool = act.pool.clone();
+            actix::spawn(async move {
+                if let Err(err) = web::block(move || {
+                    let conn = pool.get().unwrap();
+                    sm64js_db::save_flagged_accounts(
+                        &conn,
+                        flagged_accounts,
+                        anticheat::MAX_EVIDENCE as i64,
+                    )
+                })
+                .await
+                {
+                    eprintln!("{:?}", err);
+                }
+            });
+        });
+
+        if *ROOMS_CONFIG_WATCH.get().unwrap() {
+            self.rooms_config_modified = Self::get_rooms_config_modified();
+            ctx.run_interval(ROOMS_CONFIG_WATCH_INTERVAL, |act, _| {
+                let modified = Self::get_rooms_config_modified();
+                if modified.is_some() && modified != act.rooms_config_modified {
+                    act.rooms_config_modified = modified;
+                    match act.reload_rooms() {
+                        Ok(reloaded) => eprintln!("Room config reloaded: {:?}", reloaded),
+                        Err(err) => eprintln!("{}", err),
+                    }
+                }
+            });
+        }
+
+        if *CHAT_FILTERS_CONFIG_WATCH.get().unwrap() {
+            self.chat_filters_config_modified = Self::get_chat_filters_config_modified();
+            ctx.run_interval(CHAT_FILTERS_CONFIG_WATCH_INTERVAL, |act, _| {
+                let modified = Self::get_chat_filters_config_modified();
+                if modified.is_some() && modified != act.chat_filters_config_modified {
+                    act.chat_filters_config_modified = modified;
+                    match act.reload_chat_filters() {
+                        Ok(()) => eprintln!("Chat filter config reloaded"),
+                        Err(err) => eprintln!("{}", err),
+                    }
+                }
+            });
+        }
+    }
 }
 
 #[derive(Message)]
@@ -103,182 +194,201 @@ impl Handler<Disconnect> for Sm64JsServer {
 }
 
 #[derive(Message)]
-#[rtype(result = "()")]
-pub struct SetData {
+#[rtype(result = "Option<Vec<u8>>")]
+pub struct SendChat {
     pub socket_id: u32,
-    pub data: MarioMsg,
+    pub chat_msg: ChatMsg,
+    pub auth_info: AuthInfo,
 }
 
-impl Handler<SetData> for Sm64JsServer {
-    type Result = ();
+impl Handler<SendChat> for Sm64JsServer {
+    type Result = Option<Vec<u8>>;
 
-    fn handle(&mut self, msg: SetData, _: &mut Context<Self>) {
-        if let Some(mut client) = self.clients.get_mut(&msg.socket_id) {
-            client.set_data(msg.data);
+    fn handle(&mut self, send_chat: SendChat, ctx: &mut Context<Self>) -> Self::Result {
+        let socket_id = send_chat.socket_id;
+        let chat_msg = send_chat.chat_msg;
+        let auth_info = send_chat.auth_info;
+
+        let chat_reply = if chat_msg.message.starts_with('/') {
+            self.handle_command(socket_id, &chat_msg.message, &auth_info, ctx)
+        } else if let Some(player) = self.players.get(&socket_id) {
+            self.handle_chat(player, socket_id, chat_msg, auth_info, ctx)
+        } else {
+            ChatReply::default()
+        };
+
+        if let Some(msg) = chat_reply.broadcast {
+            let level = self.clients.get(&socket_id)?.get_level()?;
+            let room = self.rooms.get(&level)?;
+            room.broadcast_message(&msg);
         }
+        chat_reply.reply
     }
 }
 
-#[derive(Message)]
-#[rtype(result = "()")]
-pub struct SendAttack {
-    pub socket_id: u32,
-    pub attack_msg: AttackMsg,
+/// Player that has been targeted by a chat command
+struct Target {
+    account_id: i32,
+    name: String,
 }
 
-impl Handler<SendAttack> for Sm64JsServer {
-    type Result = ();
-
-    fn handle(&mut self, send_attack: SendAttack, _: &mut Context<Self>) {
-        let socket_id = send_attack.socket_id;
-        let attack_msg = send_attack.attack_msg;
-        if let Some((level_id, attacker_pos)) = self
-            .clients
-            .get(&socket_id)
-            .and_then(|client| try { (client.get_level()?, client.get_pos()?.clone()) })
-        {
-            if let Some(room) = self.rooms.get(&level_id) {
-                let flag_id = attack_msg.flag_id as usize;
-                let target_id = attack_msg.target_socket_id;
-                room.process_attack(flag_id, attacker_pos, target_id);
-            }
-        }
-    }
+/// Messages that result from a chat message
+#[derive(Default)]
+struct ChatReply {
+    /// Message for all players in the room of the sender
+    broadcast: Option<Vec<u8>>,
+    /// Message for the sender only
+    reply: Option<Vec<u8>>,
 }
 
 #[derive(Message)]
-#[rtype(result = "()")]
-pub struct SendGrabFlag {
+#[rtype(result = "Option<JoinGameAccepted>")]
+pub struct SendJoinGame {
     pub socket_id: u32,
-    pub grab_flag_msg: GrabFlagMsg,
+    pub join_game_msg: JoinGameMsg,
+    pub auth_info: AuthInfo,
 }
 
-impl Handler<SendGrabFlag> for Sm64JsServer {
-    type Result = ();
+impl Handler<SendJoinGame> for Sm64JsServer {
+    type Result = Option<JoinGameAccepted>;
 
-    fn handle(&mut self, send_grab: SendGrabFlag, _: &mut Context<Self>) {
-        let socket_id = send_grab.socket_id;
-        let grab_flag_msg = send_grab.grab_flag_msg;
-        if let Some(level_id) = self
-            .clients
-            .get(&socket_id)
-            .and_then(|client| client.get_level())
+    fn handle(&mut self, send_join_game: SendJoinGame, ctx: &mut Context<Self>) -> Self::Result {
+        let join_game_msg = send_join_game.join_game_msg;
+        let socket_id = send_join_game.socket_id;
+        let auth_info = send_join_game.auth_info;
+        if self.shutting_down
+            || self.maintenance && !auth_info.has_permission(&Permission::JoinDuringMaintenance)
         {
-            if let Some(room) = self.rooms.get(&level_id) {
-                let flag_id = grab_flag_msg.flag_id as usize;
-                let pos = grab_flag_msg.pos;
-                room.process_grab_flag(flag_id, pos, socket_id);
+            return None;
+        }
+        let name = if join_game_msg.use_discord_name {
+            auth_info.get_discord_username()?
+        } else {
+            if !Self::is_name_valid(&join_game_msg.name) {
+                return None;
             }
+            join_game_msg.name.clone()
+        };
+        let room_id = if join_game_msg.game_id.is_empty() {
+            self.get_or_create_shard(join_game_msg.level, join_game_msg.shard)?
+        } else {
+            self.get_or_create_custom_game(&join_game_msg, auth_info.get_account_id())?
+        };
+        match self.rooms.get(&room_id) {
+            Some(room) if !room.has_player(socket_id) && !room.is_full() => {}
+            _ => return None,
+        }
+        // only start the room actor, once the join has been accepted
+        let room_actor = self.get_room_actor(room_id, ctx);
+        let mut room = self.rooms.get_mut(&room_id)?;
+        let player = Arc::new(RwLock::new(Player::new(
+            self.clients.clone(),
+            socket_id,
+            room_id,
+            name.clone(),
+        )));
+        // TODO check duplicate custom name
+        room.add_player(socket_id, Arc::downgrade(&player));
+        if let Some(mut client) = self.clients.get_mut(&socket_id) {
+            client.set_level(room_id);
+            client.set_supports_delta(join_game_msg.supports_delta);
         }
+        self.players.insert(socket_id, player);
+        Some(JoinGameAccepted {
+            level: room.get_level(),
+            name,
+            game_id: join_game_msg.game_id,
+            shard: room.get_shard(),
+            room_actor,
+        })
     }
 }
 
 #[derive(Message)]
-#[rtype(result = "Option<Vec<u8>>")]
-pub struct SendChat {
-    pub socket_id: u32,
-    pub chat_msg: ChatMsg,
-    pub auth_info: AuthInfo,
-}
+#[rtype(result = "Result<RoomsReloaded, RoomConfigError>")]
+pub struct ReloadRooms;
 
-impl Handler<SendChat> for Sm64JsServer {
-    type Result = Option<Vec<u8>>;
+impl Handler<ReloadRooms> for Sm64JsServer {
+    type Result = Result<RoomsReloaded, RoomConfigError>;
 
-    fn handle(&mut self, send_chat: SendChat, _: &mut Context<Self>) -> Self::Result {
-        let socket_id = send_chat.socket_id;
-        let chat_msg = send_chat.chat_msg;
-        let auth_info = send_chat.auth_info;
+    fn handle(&mut self, _: ReloadRooms, _: &mut Context<Self>) -> Self::Result {
+        self.reload_rooms()
+    }
+}
 
-        let msg = if chat_msg.message.starts_with('/') {
-            Ok(Self::handle_command(chat_msg, auth_info))
-        } else if let Some(player) = self.players.get(&socket_id) {
-            self.handle_chat(player, socket_id, chat_msg, auth_info)
-        } else {
-            Ok(None)
-        };
+#[derive(Message)]
+#[rtype(result = "Result<(), ChatFilterConfigError>")]
+pub struct ReloadChatFilters;
 
-        match msg {
-            Ok(Some(msg)) => {
-                let level = self.clients.get(&socket_id)?.get_level()?;
-                let room = self.rooms.get(&level)?;
-                room.broadcast_message(&msg);
-                None
-            }
-            Ok(None) => None,
-            Err(msg) => Some(msg),
-        }
+impl Handler<ReloadChatFilters> for Sm64JsServer {
+    type Result = Result<(), ChatFilterConfigError>;
+
+    fn handle(&mut self, _: ReloadChatFilters, _: &mut Context<Self>) -> Self::Result {
+        self.reload_chat_filters()
     }
 }
 
-#[derive(Message)]
-#[rtype(result = "()")]
-pub struct SendSkin {
-    pub socket_id: u32,
-    pub skin_msg: SkinMsg,
+#[derive(Debug, Default)]
+pub struct RoomsReloaded {
+    pub added: Vec<u32>,
+    pub updated: Vec<u32>,
+    pub removed: Vec<u32>,
 }
 
-impl Handler<SendSkin> for Sm64JsServer {
-    type Result = ();
+#[derive(Message)]
+#[rtype(result = "Vec<FlaggedAccount>")]
+pub struct GetFlaggedAccounts;
 
-    fn handle(&mut self, send_skin: SendSkin, _: &mut Context<Self>) {
-        let socket_id = send_skin.socket_id;
-        let skin_msg = send_skin.skin_msg;
-        if let Some(player) = self.players.get_mut(&socket_id) {
-            player.write().set_skin_data(skin_msg.skin_data);
-        }
+impl Handler<GetFlaggedAccounts> for Sm64JsServer {
+    type Result = MessageResult<GetFlaggedAccounts>;
+
+    fn handle(&mut self, _: GetFlaggedAccounts, _: &mut Context<Self>) -> Self::Result {
+        MessageResult(self.anticheat.get_flagged_accounts())
     }
 }
 
 #[derive(Message)]
-#[rtype(result = "Option<JoinGameAccepted>")]
-pub struct SendJoinGame {
+#[rtype(result = "()")]
+pub struct RecordRaceTime {
     pub socket_id: u32,
-    pub join_game_msg: JoinGameMsg,
-    pub auth_info: AuthInfo,
+    pub level: u32,
+    pub account_id: i32,
+    pub player_name: String,
+    pub time: time::Duration,
 }
 
-impl Handler<SendJoinGame> for Sm64JsServer {
-    type Result = Option<JoinGameAccepted>;
+impl Handler<RecordRaceTime> for Sm64JsServer {
+    type Result = ();
 
-    fn handle(&mut self, send_join_game: SendJoinGame, _: &mut Context<Self>) -> Self::Result {
-        let join_game_msg = send_join_game.join_game_msg;
-        let socket_id = send_join_game.socket_id;
-        let auth_info = send_join_game.auth_info;
-        if let Some(mut room) = self.rooms.get_mut(&join_game_msg.level) {
-            if room.has_player(socket_id) {
-                None
-            } else {
-                let name = if join_game_msg.use_discord_name {
-                    auth_info.get_discord_username()?
-                } else {
-                    if !Self::is_name_valid(&join_game_msg.name) {
-                        return None;
-                    }
-                    join_game_msg.name
-                };
-                let level = join_game_msg.level;
-                if level == 0 {
-                    // TODO is custom game
-                    None
-                } else {
-                    let player = Arc::new(RwLock::new(Player::new(
-                        self.clients.clone(),
-                        socket_id,
-                        level,
-                        name.clone(),
-                    )));
-                    // TODO check duplicate custom name
-                    room.add_player(socket_id, Arc::downgrade(&player));
-                    if let Some(mut client) = self.clients.get_mut(&socket_id) {
-                        client.set_level(level);
+    fn handle(&mut self, msg: RecordRaceTime, _: &mut Context<Self>) {
+        let pool = self.pool.clone();
+        let clients = self.clients.clone();
+        actix::spawn(async move {
+            let socket_id = msg.socket_id;
+            let res = web::block(move || {
+                let conn = pool.get().unwrap();
+                sm64js_db::record_race_time(
+                    &conn,
+                    msg.level as i32,
+                    msg.account_id,
+                    msg.player_name,
+                    msg.time.as_millis() as i32,
+                )
+            })
+            .await;
+            match res {
+                Ok(true) => {
+                    if let Some(client) = clients.get(&socket_id) {
+                        let msg = create_server_chat_msg("New personal best!".to_string());
+                        if let Err(err) = client.send(Message::SendData(msg)) {
+                            eprintln!("{:?}", err);
+                        }
                     }
-                    self.players.insert(socket_id, player);
-                    Some(JoinGameAccepted { level, name })
                 }
+                Ok(false) => {}
+                Err(err) => eprintln!("{:?}", err),
             }
-        } else {
-            None
-        }
+        });
     }
 }
 
@@ -330,6 +440,32 @@ impl Handler<KickClientByAccountId> for Sm64JsServer {
     }
 }
 
+/// Warns an account via the API
+#[derive(Message)]
+#[rtype(result = "Result<()>")]
+pub struct WarnAccount {
+    pub account_id: i32,
+    pub reason: Option<String>,
+    /// Account id of the moderator
+    pub issued_by: i32,
+}
+
+impl Handler<WarnAccount> for Sm64JsServer {
+    type Result = Result<()>;
+
+    fn handle(&mut self, msg: WarnAccount, ctx: &mut Context<Self>) -> Self::Result {
+        let conn = self.pool.get().unwrap();
+        self.warn_account(
+            &conn,
+            msg.account_id,
+            msg.reason,
+            Some(msg.issued_by),
+            ModerationSource::Api,
+            ctx,
+        )
+    }
+}
+
 #[derive(Message)]
 #[rtype(result = "Result<()>")]
 pub struct KickClientByIpAddr {
@@ -358,16 +494,73 @@ impl Handler<KickClientByIpAddr> for Sm64JsServer {
 }
 
 #[derive(Message)]
-#[rtype(result = "Vec<PlayerInfo>")]
-pub struct GetPlayers {
-    pub include_chat: Option<u32>,
+#[rtype(result = "ServerStatus")]
+pub struct GetServerStatus;
+
+impl Handler<GetServerStatus> for Sm64JsServer {
+    type Result = MessageResult<GetServerStatus>;
+
+    fn handle(&mut self, _: GetServerStatus, _: &mut Context<Self>) -> Self::Result {
+        MessageResult(ServerStatus {
+            maintenance: self.maintenance,
+            shutting_down: self.shutting_down,
+        })
+    }
+}
+
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct SetMaintenanceMode {
+    pub enabled: bool,
+}
+
+impl Handler<SetMaintenanceMode> for Sm64JsServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: SetMaintenanceMode, _: &mut Context<Self>) {
+        self.maintenance = msg.enabled;
+    }
+}
+
+/// Announces a countdown to all clients and closes all connections afterwards.
+///
+/// No new games can be joined during the countdown.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Shutdown {
+    pub countdown: time::Duration,
+}
+
+impl Handler<Shutdown> for Sm64JsServer {
+    type Result = ResponseActFuture<Self, ()>;
+
+    fn handle(&mut self, msg: Shutdown, ctx: &mut Context<Self>) -> Self::Result {
+        self.shutting_down = true;
+        let shutdown_at = Instant::now() + msg.countdown;
+        self.announce_shutdown(shutdown_at);
+        let countdown = ctx.run_interval(SHUTDOWN_ANNOUNCEMENT_INTERVAL, move |act, _| {
+            act.announce_shutdown(shutdown_at)
+        });
+
+        Box::pin(
+            actix::clock::delay_for(msg.countdown)
+                .into_actor(self)
+                .map(move |_, act, ctx| {
+                    ctx.cancel_future(countdown);
+                    act.shutdown();
+                }),
+        )
+    }
 }
 
+#[derive(Message)]
+#[rtype(result = "Vec<PlayerInfo>")]
+pub struct GetPlayers;
+
 impl Handler<GetPlayers> for Sm64JsServer {
     type Result = MessageResult<GetPlayers>;
 
-    fn handle(&mut self, msg: GetPlayers, _: &mut Context<Self>) -> Self::Result {
-        let include_chat = msg.include_chat;
+    fn handle(&mut self, _: GetPlayers, _: &mut Context<Self>) -> Self::Result {
         MessageResult(
             self.players
                 .iter()
@@ -376,28 +569,24 @@ impl Handler<GetPlayers> for Sm64JsServer {
                     let client = self.clients.get(&player.get_socket_id())?;
                     let discord_id = client.get_discord_id();
                     let google_id = client.get_google_id();
-                    let chat = if let Some(include_chat) = include_chat {
-                        Some(self.chat_history.read().get_messages(
-                            GetChat {
-                                limit: Some(include_chat),
-                                discord_id: discord_id.clone(),
-                                google_id: google_id.clone(),
-                                ..Default::default()
-                            },
-                            false,
-                            false,
-                        ))
-                    } else {
-                        None
-                    };
+                    let room = self.rooms.get(&player.get_level());
+                    let rejected_attacks = self
+                        .rejected_attacks
+                        .get(&client.get_account_id())
+                        .map(|rejected_attacks| *rejected_attacks)
+                        .unwrap_or_default();
                     Some(PlayerInfo {
                         account_id: client.get_account_id(),
                         discord_id,
                         google_id,
                         ip: client.get_ip().to_string(),
-                        level: player.get_level(),
+                        level: room.as_ref().map(|room| room.get_level()),
+                        shard: room.as_ref().map(|room| room.get_shard()),
+                        room: room.as_ref().map(|room| room.name.clone()),
                         name: player.get_name().clone(),
-                        chat,
+                        rejected_attacks: rejected_attacks.suspicious,
+                        cooldown_attacks: rejected_attacks.cooldown,
+                        chat: None,
                     })
                 })
                 .collect(),
@@ -405,10 +594,12 @@ impl Handler<GetPlayers> for Sm64JsServer {
     }
 }
 
-#[derive(Debug)]
 pub struct JoinGameAccepted {
     pub level: u32,
     pub name: String,
+    pub game_id: String,
+    pub shard: u32,
+    pub room_actor: Addr<RoomActor>,
 }
 
 #[derive(Message)]
@@ -487,13 +678,82 @@ impl Handler<SendPlayerList> for Sm64JsServer {
 }
 
 impl Sm64JsServer {
-    pub fn new(pool: web::Data<DbPool>, chat_history: ChatHistoryData, rooms: Rooms) -> Self {
+    pub fn new(
+        pool: web::Data<DbPool>,
+        chat_history: ChatHistoryData,
+        rooms: Rooms,
+        rooms_config: RoomsConfig,
+        room_actors: RoomActors,
+    ) -> Self {
+        let flagged_accounts = match sm64js_db::get_flagged_accounts(&pool.get().unwrap()) {
+            Ok(flagged_accounts) => flagged_accounts,
+            Err(err) => {
+                eprintln!("{:?}", err);
+                vec![]
+            }
+        };
         Sm64JsServer {
             pool,
             clients: Arc::new(DashMap::new()),
             players: HashMap::new(),
             rooms,
             chat_history,
+            rooms_config,
+            rooms_config_modified: None,
+            chat_filters_config_modified: None,
+            rejected_attacks: Arc::new(DashMap::new()),
+            anticheat: Arc::new(AntiCheat::new(
+                *ANTICHEAT_RESPONSE.get().unwrap(),
+                flagged_accounts,
+            )),
+            room_actors,
+            room_arbiters: vec![],
+            next_room_arbiter: 0,
+            maintenance: *MAINTENANCE_MODE.get().unwrap(),
+            shutting_down: false,
+        }
+    }
+
+    /// Announces the remaining time every ten seconds and every second for the last five seconds
+    fn announce_shutdown(&self, shutdown_at: Instant) {
+        let remaining = shutdown_at
+            .saturating_duration_since(Instant::now())
+            .as_secs_f32()
+            .round() as u64;
+        if remaining == 0 || (remaining % 10 != 0 && remaining > 5) {
+            return;
+        }
+        let msg =
+            Self::create_uncompressed_msg(sm64_js_msg::Message::AnnouncementMsg(AnnouncementMsg {
+                message: format!("Server restarting in {} seconds", remaining),
+                timer: 150,
+            }));
+        for client in self.clients.iter() {
+            if let Err(err) = client.send(Message::SendData(msg.clone())) {
+                eprintln!("{:?}", err);
+            }
+        }
+    }
+
+    fn shutdown(&mut self) {
+        for client in self.clients.iter() {
+            if let Err(err) = client.send(Message::Close("Server restarting".to_string())) {
+                eprintln!("{:?}", err);
+            }
+        }
+        self.clients.clear();
+        self.players.clear();
+
+        let flagged_accounts = self.anticheat.take_unsaved_accounts();
+        if !flagged_accounts.is_empty() {
+            let conn = self.pool.get().unwrap();
+            if let Err(err) = sm64js_db::save_flagged_accounts(
+                &conn,
+                flagged_accounts,
+                anticheat::MAX_EVIDENCE as i64,
+            ) {
+                eprintln!("{:?}", err);
+            }
         }
     }
 
@@ -509,6 +769,234 @@ impl Sm64JsServer {
         msg
     }
 
+    /// Returns the actor of a room and starts it, if it is not running yet.
+    ///
+    /// Room actors are distributed evenly across all room arbiters.
+    fn get_room_actor(&mut self, room_id: u32, ctx: &mut Context<Self>) -> Addr<RoomActor> {
+        self.room_actors
+            .retain(|_, room_actor| room_actor.connected());
+        if let Some(room_actor) = self.room_actors.get(&room_id) {
+            return room_actor.value().clone();
+        }
+
+        let room_actor = RoomActor::new(
+            room_id,
+            self.rooms.clone(),
+            self.clients.clone(),
+            ctx.address().recipient(),
+            ctx.address().recipient(),
+            self.anticheat.clone(),
+            self.rejected_attacks.clone(),
+        );
+        let arbiter = &self.room_arbiters[self.next_room_arbiter % self.room_arbiters.len()];
+        self.next_room_arbiter = self.next_room_arbiter.wrapping_add(1);
+        let room_actor = RoomActor::start_in_arbiter(arbiter, |_| room_actor);
+        self.room_actors.insert(room_id, room_actor.clone());
+        room_actor
+    }
+
+    fn get_rooms_config_modified() -> Option<SystemTime> {
+        fs::metadata(ROOMS_CONFIG.get().unwrap())
+            .and_then(|metadata| metadata.modified())
+            .ok()
+    }
+
+    fn get_chat_filters_config_modified() -> Option<SystemTime> {
+        fs::metadata(CHAT_FILTERS_CONFIG.get().unwrap())
+            .and_then(|metadata| metadata.modified())
+            .ok()
+    }
+
+    /// Reloads the chat filter config.
+    ///
+    /// The current filters stay active, if the config is invalid.
+    fn reload_chat_filters(&mut self) -> Result<(), ChatFilterConfigError> {
+        let config = ChatFiltersConfig::load(CHAT_FILTERS_CONFIG.get().unwrap())?;
+        self.chat_history.write().set_filters(config);
+        Ok(())
+    }
+
+    /// Reloads the room config and applies it to the live rooms.
+    ///
+    /// Players of removed rooms will be moved back to the lobby.
+    fn reload_rooms(&mut self) -> Result<RoomsReloaded, RoomConfigError> {
+        let config = RoomsConfig::load(ROOMS_CONFIG.get().unwrap())?;
+        let mut reloaded = RoomsReloaded::default();
+
+        let removed: Vec<u32> = self
+            .rooms
+            .iter()
+            .filter(|room| {
+                !config
+                    .rooms
+                    .iter()
+                    .any(|room_config| room_config.id == room.get_level())
+            })
+            .map(|room| *room.key())
+            .collect();
+        for id in removed {
+            if let Some((_, room)) = self.rooms.remove(&id) {
+                self.move_players_to_lobby(
+                    id,
+                    format!(
+                        "{} has been closed. You have been moved back to the lobby",
+                        room.name
+                    ),
+                );
+                reloaded.removed.push(id);
+            }
+        }
+
+        let changed: Vec<_> = config
+            .rooms
+            .iter()
+            .filter(|room_config| {
+                self.rooms_config
+                    .rooms
+                    .iter()
+                    .any(|old| old.id == room_config.id && old != *room_config)
+            })
+            .collect();
+        for mut room in self.rooms.iter_mut().filter(|room| !room.is_custom_game()) {
+            if let Some(room_config) = changed
+                .iter()
+                .find(|room_config| room_config.id == room.get_level())
+            {
+                room.apply_config(room_config);
+                reloaded.updated.push(*room.key());
+            }
+        }
+
+        for room_config in config.rooms.iter() {
+            if !self.rooms.contains_key(&room_config.id) {
+                self.rooms.insert(room_config.id, Room::new(room_config));
+                reloaded.added.push(room_config.id);
+            }
+        }
+        self.rooms_config = config;
+
+        Ok(reloaded)
+    }
+
+    /// Returns the room id of the requested shard of a level.
+    ///
+    /// If the requested shard is full or does not exist, the first shard with free capacity
+    /// will be chosen instead. A new shard will be created, if all shards are full.
+    fn get_or_create_shard(&mut self, level: u32, shard: u32) -> Option<u32> {
+        let mut shards: Vec<_> = self
+            .rooms
+            .iter()
+            .filter(|room| !room.is_custom_game() && room.get_level() == level)
+            .map(|room| (*room.key(), room.get_shard(), room.is_full()))
+            .collect();
+        shards.sort_unstable_by_key(|(_, shard, _)| *shard);
+
+        if let Some((id, _, _)) = shards
+            .iter()
+            .find(|(_, s, is_full)| *s == shard && !is_full)
+        {
+            return Some(*id);
+        }
+        if let Some((id, _, _)) = shards.iter().find(|(_, _, is_full)| !is_full) {
+            return Some(*id);
+        }
+
+        let shard = (2..)
+            .find(|s| !shards.iter().any(|(_, shard, _)| shard == s))
+            .unwrap();
+        // the id has to be generated before a room is borrowed,
+        // because `generate_room_id` locks the rooms again
+        let id = self.generate_room_id();
+        let room = self.rooms.get(&level)?;
+        let new_shard = room.create_shard(shard);
+        drop(room);
+        self.rooms.insert(id, new_shard);
+
+        Some(id)
+    }
+
+    fn generate_room_id(&self) -> u32 {
+        loop {
+            let id = rand::thread_rng().gen_range(DYNAMIC_ROOM_ID_START..=u32::MAX);
+            if !self.rooms.contains_key(&id) {
+                break id;
+            }
+        }
+    }
+
+    /// Returns the room id of the custom game with the given `game_id`.
+    ///
+    /// If it does not exist yet, it will be created with the requesting account as host.
+    /// A level must then be given, which will be used as the custom game's map.
+    fn get_or_create_custom_game(
+        &mut self,
+        join_game_msg: &JoinGameMsg,
+        account_id: i32,
+    ) -> Option<u32> {
+        let game_id = &join_game_msg.game_id;
+        if !Self::is_game_id_valid(game_id) {
+            return None;
+        }
+        let password = &join_game_msg.password;
+
+        if let Some(room) = self.rooms.iter().find(|room| {
+            room.get_custom_game()
+                .map(|custom_game| &custom_game.game_id == game_id)
+                .unwrap_or_default()
+        }) {
+            let custom_game = room.get_custom_game()?;
+            if let Some(password_hash) = &custom_game.password {
+                if !password_hash.verify(password) {
+                    return None;
+                }
+            }
+            return Some(*room.key());
+        }
+
+        if join_game_msg.level == 0 {
+            return None;
+        }
+        // the id has to be generated before a room is borrowed,
+        // because `generate_room_id` locks the rooms again
+        let id = self.generate_room_id();
+        let room = self.rooms.get(&join_game_msg.level)?;
+        if room.is_custom_game() {
+            return None;
+        }
+        let custom_game = room.create_custom_game(CustomGame {
+            game_id: game_id.clone(),
+            password: if password.is_empty() {
+                None
+            } else {
+                Some(PasswordHash::new(password))
+            },
+            host_account_id: account_id,
+        });
+        drop(room);
+        self.rooms.insert(id, custom_game);
+
+        Some(id)
+    }
+
+    fn move_players_to_lobby(&mut self, level: u32, message: String) {
+        let socket_ids: Vec<u32> = self
+            .players
+            .iter()
+            .filter(|(_, player)| player.read().get_level() == level)
+            .map(|(socket_id, _)| *socket_id)
+            .collect();
+        let msg = create_server_chat_msg(message);
+        for socket_id in socket_ids {
+            self.players.remove(&socket_id);
+            if let Some(mut client) = self.clients.get_mut(&socket_id) {
+                client.leave_level();
+                if let Err(err) = client.send(Message::SendData(msg.clone())) {
+                    eprintln!("{:?}", err);
+                }
+            }
+        }
+    }
+
     fn get_client_by_account_id(&self, account_id: i32) -> Option<Ref<u32, Client>> {
         self.clients
             .iter()
@@ -529,41 +1017,567 @@ impl Sm64JsServer {
             })
     }
 
-    fn handle_command(chat_msg: ChatMsg, auth_info: AuthInfo) -> Option<Vec<u8>> {
-        let message = chat_msg
-            .message
-            .char_indices()
-            .nth(1)
-            .and_then(|(i, _)| chat_msg.message.get(i..))
-            .unwrap_or("");
-        if let Some(index) = message.find(' ') {
-            let (cmd, message) = message.split_at(index);
-            let cmd = cmd.to_ascii_uppercase();
-            if let Some(permission) = PRIVILEGED_COMMANDS.lock().get(cmd.as_str()) {
-                if !auth_info.has_permission(permission) {
-                    return None;
+    fn handle_command(
+        &mut self,
+        socket_id: u32,
+        message: &str,
+        auth_info: &AuthInfo,
+        ctx: &mut Context<Self>,
+    ) -> ChatReply {
+        match command::parse_command(message, auth_info)
+            .and_then(|command| self.execute_command(socket_id, command, auth_info, ctx))
+        {
+            Ok(chat_reply) => chat_reply,
+            Err(err) => ChatReply {
+                broadcast: None,
+                reply: Some(create_server_chat_msg(err.to_string())),
+            },
+        }
+    }
+
+    fn execute_command(
+        &mut self,
+        socket_id: u32,
+        command: ChatCommand,
+        auth_info: &AuthInfo,
+        ctx: &mut Context<Self>,
+    ) -> Result<ChatReply, CommandError> {
+        let moderator = auth_info.get_discord_username().unwrap_or_default();
+        let reply = match command {
+            ChatCommand::Announcement { message } => {
+                return Ok(ChatReply {
+                    broadcast: Some(Self::create_uncompressed_msg(
+                        sm64_js_msg::Message::AnnouncementMsg(AnnouncementMsg {
+                            message,
+                            timer: 300,
+                        }),
+                    )),
+                    reply: None,
+                })
+            }
+            ChatCommand::Help { command: name } => command::get_help(name.as_deref(), auth_info),
+            ChatCommand::Kick { target, reason } => {
+                let target = self.find_player_in_room(socket_id, &target)?;
+                ctx.notify(KickClientByAccountId {
+                    account_id: target.account_id,
+                });
+                let conn = self.pool.get().unwrap();
+                Self::log_moderation_action(
+                    &conn,
+                    NewModerationAction {
+                        target_account_id: Some(target.account_id),
+                        reason: reason.clone(),
+                        ..NewModerationAction::new(
+                            ModerationActionKind::Kick,
+                            ModerationSource::ChatCommand,
+                            Some(auth_info.get_account_id()),
+                        )
+                    },
+                );
+                Self::send_discord_moderation_message(
+                    &conn,
+                    format!("/kick player by {}", moderator),
+                    reason,
+                    None,
+                    target.account_id,
+                );
+                format!("{} has been kicked", target.name)
+            }
+            ChatCommand::Mute {
+                target,
+                expires_in,
+                reason,
+            } => {
+                let expires_at = Self::get_punishment_expiry(
+                    auth_info,
+                    "mute",
+                    expires_in,
+                    Permission::TempMuteAccount,
+                    Permission::PermMuteAccount,
+                )?;
+                let target = self.find_player_in_room(socket_id, &target)?;
+                let conn = self.pool.get().unwrap();
+                let mute =
+                    sm64js_db::is_account_muted(&conn, target.account_id).map_err(|err| {
+                        eprintln!("{:?}", err);
+                        CommandError::Internal("mute")
+                    })?;
+                // overwriting a mute lifts it, which requires the same permission as /unmute
+                if let Some(mute) = mute {
+                    if !auth_info.has_permission(&Permission::revoke_mute(mute.expires_at)) {
+                        return Err(CommandError::CannotOverwrite("mute"));
+                    }
+                }
+                conn.transaction::<_, sm64js_db::DbError, _>(|| {
+                    sm64js_db::mute_account(&conn, reason.clone(), expires_at, target.account_id)?;
+                    sm64js_db::log_moderation_action(
+                        &conn,
+                        NewModerationAction {
+                            target_account_id: Some(target.account_id),
+                            expires_at,
+                            reason: reason.clone(),
+                            ..NewModerationAction::new(
+                                ModerationActionKind::Mute,
+                                ModerationSource::ChatCommand,
+                                Some(auth_info.get_account_id()),
+                            )
+                        },
+                    )
+                })
+                .map_err(|err| {
+                    eprintln!("{:?}", err);
+                    CommandError::Internal("mute")
+                })?;
+                Self::send_discord_moderation_message(
+                    &conn,
+                    format!("/mute player by {}", moderator),
+                    reason,
+                    expires_at,
+                    target.account_id,
+                );
+                format!("{} has been muted", target.name)
+            }
+            ChatCommand::Unmute { target, reason } => {
+                let target = self.find_player_in_room(socket_id, &target)?;
+                let conn = self.pool.get().unwrap();
+                let mute =
+                    sm64js_db::is_account_muted(&conn, target.account_id).map_err(|err| {
+                        eprintln!("{:?}", err);
+                        CommandError::Internal("unmute")
+                    })?;
+                if let Some(mute) = mute {
+                    if !auth_info.has_permission(&Permission::revoke_mute(mute.expires_at)) {
+                        return Err(CommandError::CannotRevoke("mute"));
+                    }
+                    conn.transaction::<_, sm64js_db::DbError, _>(|| {
+                        sm64js_db::revoke_mute(
+                            &conn,
+                            target.account_id,
+                            Some(auth_info.get_account_id()),
+                            reason.clone(),
+                        )?;
+                        sm64js_db::log_moderation_action(
+                            &conn,
+                            NewModerationAction {
+                                target_account_id: Some(target.account_id),
+                                reason: reason.clone(),
+                                ..NewModerationAction::new(
+                                    ModerationActionKind::Unmute,
+                                    ModerationSource::ChatCommand,
+                                    Some(auth_info.get_account_id()),
+                                )
+                            },
+                        )
+                    })
+                    .map_err(|err| {
+                        eprintln!("{:?}", err);
+                        CommandError::Internal("unmute")
+                    })?;
+                    Self::send_discord_moderation_message(
+                        &conn,
+                        format!("/unmute player by {}", moderator),
+                        reason,
+                        None,
+                        target.account_id,
+                    );
+                    format!("{} has been unmuted", target.name)
+                } else {
+                    format!("{} is not muted", target.name)
                 }
             }
-            match cmd.as_ref() {
-                // TODO store in enum
-                "ANNOUNCEMENT" => {
-                    let root_msg = RootMsg {
-                        message: Some(root_msg::Message::UncompressedSm64jsMsg(Sm64JsMsg {
-                            message: Some(sm64_js_msg::Message::AnnouncementMsg(AnnouncementMsg {
-                                message: message.to_string(),
-                                timer: 300,
-                            })),
-                        })),
+            ChatCommand::Warn { target, reason } => {
+                let target = self.find_player_in_room(socket_id, &target)?;
+                let conn = self.pool.get().unwrap();
+                self.warn_account(
+                    &conn,
+                    target.account_id,
+                    reason.clone(),
+                    Some(auth_info.get_account_id()),
+                    ModerationSource::ChatCommand,
+                    ctx,
+                )
+                .map_err(|err| {
+                    eprintln!("{:?}", err);
+                    CommandError::Internal("warn")
+                })?;
+                Self::send_discord_moderation_message(
+                    &conn,
+                    format!("/warn player by {}", moderator),
+                    reason,
+                    None,
+                    target.account_id,
+                );
+                format!("{} has been warned", target.name)
+            }
+            ChatCommand::Ban {
+                target,
+                expires_in,
+                reason,
+            } => {
+                let expires_at = Self::get_punishment_expiry(
+                    auth_info,
+                    "ban",
+                    expires_in,
+                    Permission::TempBanAccount,
+                    Permission::PermBanAccount,
+                )?;
+                let target = self.find_player_in_room(socket_id, &target)?;
+                let conn = self.pool.get().unwrap();
+                let account = sm64js_db::get_account(&conn, target.account_id).map_err(|err| {
+                    eprintln!("{:?}", err);
+                    CommandError::Internal("ban")
+                })?;
+                let ban =
+                    sm64js_db::is_account_banned(&conn, target.account_id).map_err(|err| {
+                        eprintln!("{:?}", err);
+                        CommandError::Internal("ban")
+                    })?;
+                // overwriting a ban lifts it, which requires the same permission as DELETE Ban
+                if let Some(ban) = ban {
+                    if !auth_info.has_permission(&Permission::revoke_ban(ban.expires_at)) {
+                        return Err(CommandError::CannotOverwrite("ban"));
+                    }
+                }
+                drop(conn);
+                ctx.notify(KickClientByAccountId {
+                    account_id: target.account_id,
+                });
+
+                let moderator_id = auth_info.get_account_id();
+                let pool = self.pool.clone();
+                let clients = self.clients.clone();
+                let target_name = target.name.clone();
+                actix::spawn(async move {
+                    // the ban is stored in the background, so its result is sent afterwards
+                    let reply = |message: String| {
+                        if let Some(client) = clients.get(&socket_id) {
+                            let msg = create_server_chat_msg(message);
+                            if let Err(err) = client.send(Message::SendData(msg)) {
+                                eprintln!("{:?}", err);
+                            }
+                        }
+                    };
+                    let geolocation: Option<NewGeolocation> =
+                        match sm64js_common::fetch_geolocation(account.last_ip.clone()).await {
+                            Ok(geolocation) => geolocation,
+                            Err(err) => {
+                                eprintln!("{:?}", err);
+                                None
+                            }
+                        };
+                    let ban_reason = reason.clone();
+                    // the connection and queries block, so they must not run on the server arbiter
+                    let result = web::block(move || -> Result<_> {
+                        let conn = pool.get()?;
+                        conn.transaction::<_, sm64js_db::DbError, _>(|| {
+                            sm64js_db::ban_account(
+                                &conn,
+                                geolocation,
+                                account.last_ip.clone(),
+                                ban_reason.clone(),
+                                expires_at,
+                                Some(account.id),
+                            )?;
+                            sm64js_db::log_moderation_action(
+                                &conn,
+                                NewModerationAction {
+                                    target_account_id: Some(account.id),
+                                    target_ip: Some(account.last_ip.clone()),
+                                    expires_at,
+                                    reason: ban_reason.clone(),
+                                    ..NewModerationAction::new(
+                                        ModerationActionKind::Ban,
+                                        ModerationSource::ChatCommand,
+                                        Some(moderator_id),
+                                    )
+                                },
+                            )
+                        })?;
+                        Ok(sm64js_db::get_account_info(&conn, account.id, true))
+                    })
+                    .await;
+                    let account_info = match result {
+                        Ok(account_info) => account_info,
+                        Err(err) => {
+                            eprintln!("{:?}", err);
+                            reply(format!(
+                                "{} has been kicked, but could not be banned",
+                                target_name
+                            ));
+                            return;
+                        }
                     };
+                    reply(format!("{} has been banned", target_name));
+                    if let Some(account_info) = account_info {
+                        sm64js_common::send_discord_moderation_message(
+                            format!("/ban player by {}", moderator),
+                            reason,
+                            expires_at,
+                            account_info,
+                        )
+                        .await;
+                    }
+                });
+                format!("Banning {}...", target.name)
+            }
+        };
+        Ok(ChatReply {
+            broadcast: None,
+            reply: Some(create_server_chat_msg(reply)),
+        })
+    }
+
+    /// Finds a player in the room of `socket_id` by socket id or case insensitive name
+    fn find_player_in_room(&self, socket_id: u32, target: &str) -> Result<Target, CommandError> {
+        let level = self
+            .clients
+            .get(&socket_id)
+            .and_then(|client| client.get_level())
+            .ok_or_else(|| CommandError::PlayerNotFound(target.to_string()))?;
+        let in_room = |player: &&Arc<RwLock<Player>>| player.read().get_level() == level;
 
-                    let mut msg = vec![];
-                    root_msg.encode(&mut msg).unwrap();
-                    Some(msg)
+        let target_socket_id = if let Some(target_socket_id) = target
+            .parse::<u32>()
+            .ok()
+            .filter(|id| self.players.get(id).filter(in_room).is_some())
+        {
+            target_socket_id
+        } else {
+            let mut matches = self.players.iter().filter(|(_, player)| {
+                in_room(player) && player.read().get_name().eq_ignore_ascii_case(target)
+            });
+            match (matches.next(), matches.next()) {
+                (Some((id, _)), None) => *id,
+                (Some(_), Some(_)) => {
+                    return Err(CommandError::AmbiguousPlayer(target.to_string()))
                 }
-                _ => None,
+                _ => return Err(CommandError::PlayerNotFound(target.to_string())),
             }
-        } else {
-            None
+        };
+
+        let account_id = self
+            .clients
+            .get(&target_socket_id)
+            .map(|client| client.get_account_id())
+            .ok_or_else(|| CommandError::PlayerNotFound(target.to_string()))?;
+        let name = self.players[&target_socket_id].read().get_name().clone();
+        Ok(Target { account_id, name })
+    }
+
+    /// Checks whether the moderator is allowed to punish for the given duration.
+    ///
+    /// Returns when the punishment expires, or `None` if it is permanent.
+    fn get_punishment_expiry(
+        auth_info: &AuthInfo,
+        command: &'static str,
+        expires_in: Option<time::Duration>,
+        temp_permission: fn(Duration) -> Permission,
+        perm_permission: Permission,
+    ) -> Result<Option<NaiveDateTime>, CommandError> {
+        let expires_in = expires_in
+            .map(Duration::from_std)
+            .transpose()
+            .map_err(|_| CommandError::DurationNotAllowed(command))?;
+        let permission = expires_in.map(temp_permission).unwrap_or(perm_permission);
+        if !auth_info.has_permission(&permission) {
+            return Err(CommandError::DurationNotAllowed(command));
+        }
+        expires_in
+            .map(|exp| {
+                Utc::now()
+                    .naive_utc()
+                    .checked_add_signed(exp)
+                    .ok_or(CommandError::DurationNotAllowed(command))
+            })
+            .transpose()
+    }
+
+    /// Records a warning, shows it to the player
+    /// and punishes the account, if it reaches a step of the escalation.
+    ///
+    /// Fails, if the warning could not be recorded.
+    /// Errors of the escalation are only logged, because the warning has been issued already.
+    fn warn_account(
+        &self,
+        conn: &PgConnection,
+        account_id: i32,
+        reason: Option<String>,
+        issued_by: Option<i32>,
+        source: ModerationSource,
+        ctx: &mut Context<Self>,
+    ) -> Result<()> {
+        conn.transaction::<_, sm64js_db::DbError, _>(|| {
+            sm64js_db::warn_account(conn, account_id, reason.clone(), issued_by)?;
+            sm64js_db::log_moderation_action(
+                conn,
+                NewModerationAction {
+                    target_account_id: Some(account_id),
+                    reason: reason.clone(),
+                    ..NewModerationAction::new(ModerationActionKind::Warn, source, issued_by)
+                },
+            )
+        })?;
+        self.send_announcement_to_account(
+            account_id,
+            match reason {
+                Some(reason) => format!("You have been warned for {}", reason),
+                None => "You have been warned by a moderator".to_string(),
+            },
+        );
+
+        let window = self
+            .chat_history
+            .read()
+            .get_filters()
+            .max_escalation_window();
+        let now = Utc::now().naive_utc();
+        let warnings = match sm64js_db::get_warnings_since(conn, account_id, now - window) {
+            Ok(warnings) => warnings,
+            Err(err) => {
+                eprintln!("{:?}", err);
+                return Ok(());
+            }
+        };
+        let warnings: Vec<_> = warnings.iter().map(|warning| warning.created_at).collect();
+        let step = self
+            .chat_history
+            .read()
+            .get_filters()
+            .get_escalation(&warnings, now)
+            .cloned();
+        if let Some(step) = step {
+            self.escalate(conn, account_id, step, ctx);
+        }
+        Ok(())
+    }
+
+    /// Mutes or bans an account that collected too many warnings.
+    ///
+    /// Active punishments that last at least as long are kept.
+    fn escalate(
+        &self,
+        conn: &PgConnection,
+        account_id: i32,
+        step: EscalationStep,
+        ctx: &mut Context<Self>,
+    ) {
+        let (action, minutes) = match step.punishment {
+            EscalationPunishment::Mute { minutes } => (ModerationActionKind::Mute, minutes),
+            EscalationPunishment::Ban { minutes } => (ModerationActionKind::Ban, minutes),
+        };
+        let expires_at = Utc::now().naive_utc() + Duration::minutes(minutes as i64);
+        let reason = format!("{} warnings within {}h", step.warnings, step.window_hours);
+
+        let outlasts =
+            |existing: Option<NaiveDateTime>| existing.map(|exp| exp >= expires_at).unwrap_or(true);
+
+        let result = conn.transaction::<_, sm64js_db::DbError, _>(|| {
+            match step.punishment {
+                EscalationPunishment::Mute { .. } => {
+                    let mute = sm64js_db::is_account_muted(conn, account_id)?;
+                    if matches!(mute, Some(mute) if outlasts(mute.expires_at)) {
+                        return Ok(false);
+                    }
+                    sm64js_db::mute_account(
+                        conn,
+                        Some(reason.clone()),
+                        Some(expires_at),
+                        account_id,
+                    )?;
+                }
+                EscalationPunishment::Ban { .. } => {
+                    let ban = sm64js_db::is_account_banned(conn, account_id)?;
+                    if matches!(ban, Some(ban) if outlasts(ban.expires_at)) {
+                        return Ok(false);
+                    }
+                    let account = sm64js_db::get_account(conn, account_id)?;
+                    sm64js_db::ban_account(
+                        conn,
+                        None,
+                        account.last_ip,
+                        Some(reason.clone()),
+                        Some(expires_at),
+                        Some(account_id),
+                    )?;
+                }
+            }
+            sm64js_db::log_moderation_action(
+                conn,
+                NewModerationAction {
+                    target_account_id: Some(account_id),
+                    expires_at: Some(expires_at),
+                    reason: Some(reason.clone()),
+                    ..NewModerationAction::new(action, ModerationSource::Escalation, None)
+                },
+            )?;
+            Ok(true)
+        });
+        match result {
+            Ok(true) => {}
+            Ok(false) => return,
+            Err(err) => {
+                eprintln!("{:?}", err);
+                return;
+            }
+        }
+        match step.punishment {
+            EscalationPunishment::Mute { minutes } => self.send_announcement_to_account(
+                account_id,
+                format!(
+                    "You have been muted for {}min due to too many warnings",
+                    minutes
+                ),
+            ),
+            EscalationPunishment::Ban { .. } => ctx.notify(KickClientByAccountId { account_id }),
+        }
+
+        Self::send_discord_moderation_message(
+            conn,
+            format!("Automatic {} after too many warnings", action.as_str()),
+            Some(reason),
+            Some(expires_at),
+            account_id,
+        );
+    }
+
+    /// Shows an announcement to the client of an account, if it is connected
+    fn send_announcement_to_account(&self, account_id: i32, message: String) {
+        if let Some(client) = self.get_client_by_account_id(account_id) {
+            let msg = Self::create_uncompressed_msg(sm64_js_msg::Message::AnnouncementMsg(
+                AnnouncementMsg {
+                    message,
+                    timer: 300,
+                },
+            ));
+            if let Err(err) = client.send(Message::SendData(msg)) {
+                eprintln!("{:?}", err);
+            }
+        }
+    }
+
+    fn log_moderation_action(conn: &PgConnection, action: NewModerationAction) {
+        if let Err(err) = sm64js_db::log_moderation_action(conn, action) {
+            eprintln!("{:?}", err);
+        }
+    }
+
+    fn send_discord_moderation_message(
+        conn: &PgConnection,
+        action: String,
+        reason: Option<String>,
+        expires_at: Option<NaiveDateTime>,
+        account_id: i32,
+    ) {
+        if let Some(account_info) = sm64js_db::get_account_info(conn, account_id, true) {
+            actix::spawn(async move {
+                sm64js_common::send_discord_moderation_message(
+                    action,
+                    reason,
+                    expires_at,
+                    account_info,
+                )
+                .await;
+            });
         }
     }
 
@@ -573,11 +1587,12 @@ impl Sm64JsServer {
         socket_id: u32,
         mut chat_msg: ChatMsg,
         auth_info: AuthInfo,
-    ) -> Result<Option<Vec<u8>>, Vec<u8>> {
+        ctx: &mut Context<Self>,
+    ) -> ChatReply {
         let account_id = if let Some(client) = self.clients.get(&socket_id) {
             client.get_account_id()
         } else {
-            return Ok(None);
+            return ChatReply::default();
         };
         let conn = self.pool.get().unwrap();
         if let Ok(Some(mute)) = sm64js_db::is_account_muted(&conn, account_id) {
@@ -601,93 +1616,114 @@ impl Sm64JsServer {
             let mut msg = vec![];
             root_msg.encode(&mut msg).unwrap();
 
-            return Err(msg);
+            return ChatReply {
+                broadcast: None,
+                reply: Some(msg),
+            };
         }
         drop(conn);
 
         let username = player.read().get_name().clone();
-        let root_msg = match player.write().add_chat_message(
+        let chat_result = player.write().add_chat_message(
             self.pool.clone(),
             self.chat_history.clone(),
             &chat_msg.message,
             self.rooms.clone(),
-        ) {
-            ChatResult::Ok((message, is_spam)) => {
-                if is_spam || message.is_empty() {
-                    None
-                } else {
-                    chat_msg.message = message;
-                    chat_msg.is_admin = auth_info.is_in_game_admin();
-                    chat_msg.socket_id = socket_id;
-                    chat_msg.sender = username;
-                    Some(RootMsg {
-                        message: Some(root_msg::Message::UncompressedSm64jsMsg(Sm64JsMsg {
-                            message: Some(sm64_js_msg::Message::ChatMsg(chat_msg)),
-                        })),
-                    })
-                }
-            }
-            ChatResult::Err(err) => match err {
-                ChatError::Spam => {
-                    chat_msg.message =
-                            "Chat message ignored: You have to wait longer between sending chat messages"
-                                .to_string();
-                    chat_msg.sender = "[Server]".to_string();
-                    chat_msg.is_server = true;
-
-                    let msg = Sm64JsServer::create_uncompressed_msg(sm64_js_msg::Message::ChatMsg(
-                        chat_msg,
-                    ));
-
-                    return Err(msg);
-                }
-                ChatError::ExcessiveSpam => {
+        );
+        chat_msg.is_admin = auth_info.is_in_game_admin();
+        chat_msg.socket_id = socket_id;
+        chat_msg.sender = username;
+        match chat_result {
+            ChatResult::Ok(message) => ChatReply {
+                broadcast: Self::create_player_chat_msg(chat_msg, message),
+                reply: None,
+            },
+            ChatResult::Filtered(filtered) => {
+                let reply = Some(create_server_chat_msg(filtered.reply));
+                let chat_reply = match filtered.action {
+                    ChatAction::Warn => ChatReply {
+                        broadcast: Self::create_player_chat_msg(chat_msg, filtered.message),
+                        reply,
+                    },
+                    ChatAction::ShadowDrop => ChatReply {
+                        broadcast: None,
+                        reply: Self::create_player_chat_msg(chat_msg, filtered.message),
+                    },
+                    ChatAction::Mute { minutes } => {
+                        let conn = self.pool.get().unwrap();
+                        let expires_at = Utc::now().naive_utc() + Duration::minutes(minutes as i64);
+                        let reason =
+                            format!("automatic mute due to {}", filtered.error.get_reason());
+                        let result = conn.transaction::<_, sm64js_db::DbError, _>(|| {
+                            sm64js_db::mute_account(
+                                &conn,
+                                Some(reason.clone()),
+                                Some(expires_at),
+                                account_id,
+                            )?;
+                            sm64js_db::log_moderation_action(
+                                &conn,
+                                NewModerationAction {
+                                    target_account_id: Some(account_id),
+                                    expires_at: Some(expires_at),
+                                    reason: Some(reason),
+                                    ..NewModerationAction::new(
+                                        ModerationActionKind::Mute,
+                                        ModerationSource::ChatFilter,
+                                        None,
+                                    )
+                                },
+                            )
+                        });
+                        if let Err(err) = result {
+                            eprintln!("{:?}", err);
+                        }
+                        ChatReply {
+                            broadcast: None,
+                            reply,
+                        }
+                    }
+                    ChatAction::Drop | ChatAction::Escalate => ChatReply {
+                        broadcast: None,
+                        reply,
+                    },
+                };
+                if filtered.warn {
                     let conn = self.pool.get().unwrap();
-                    let expires_at = Utc::now().naive_utc()
-                        + Duration::from_std(time::Duration::from_secs(300)).unwrap();
-                    if let Err(err) = sm64js_db::mute_account(
+                    if let Err(err) = self.warn_account(
                         &conn,
-                        Some("automatic mute due to sending too many messages".to_string()),
-                        Some(expires_at),
                         account_id,
+                        Some(filtered.error.get_reason().to_string()),
+                        None,
+                        ModerationSource::ChatFilter,
+                        ctx,
                     ) {
                         eprintln!("{:?}", err);
-                    };
-
-                    chat_msg.message =
-                        "You have been muted for 5min due to sending way too many messages"
-                            .to_string();
-                    chat_msg.sender = "[Server]".to_string();
-                    chat_msg.is_server = true;
-
-                    let msg = Sm64JsServer::create_uncompressed_msg(sm64_js_msg::Message::ChatMsg(
-                        chat_msg,
-                    ));
-
-                    return Err(msg);
+                    }
                 }
-                ChatError::Screaming => {
-                    chat_msg.message = "COULD YOU PLEASE STOP SCREAMING?".to_string();
-                    chat_msg.sender = "[Server]".to_string();
-                    chat_msg.is_server = true;
-
-                    let msg = Sm64JsServer::create_uncompressed_msg(sm64_js_msg::Message::ChatMsg(
-                        chat_msg,
-                    ));
+                chat_reply
+            }
+            ChatResult::NotFound => ChatReply::default(),
+        }
+    }
 
-                    return Err(msg);
-                }
-            },
-            ChatResult::NotFound => None,
-        };
+    /// Returns `None`, if nothing is left of the message after censoring
+    fn create_player_chat_msg(mut chat_msg: ChatMsg, message: String) -> Option<Vec<u8>> {
+        if message.is_empty() {
+            return None;
+        }
+        chat_msg.message = message;
+        Some(Self::create_uncompressed_msg(
+            sm64_js_msg::Message::ChatMsg(chat_msg),
+        ))
+    }
 
-        Ok(if let Some(root_msg) = root_msg {
-            let mut msg = vec![];
-            root_msg.encode(&mut msg).unwrap();
-            Some(msg)
-        } else {
-            None
-        })
+    fn is_game_id_valid(game_id: &str) -> bool {
+        game_id.len() >= 3
+            && game_id.len() <= 32
+            && game_id
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
     }
 
     fn is_name_valid(name: &str) -> bool {
@@ -1,4 +1,9 @@
-use crate::server;
+use crate::{
+    metrics::METRICS,
+    rate_limit::{RateLimit, RateLimitedMsg, RateLimiter},
+    room_actor::{self, RoomActor},
+    server,
+};
 
 use actix::prelude::*;
 use actix_web_actors::ws;
@@ -9,7 +14,11 @@ use sm64js_proto::{
     initialization_msg, root_msg, sm64_js_msg, InitGameDataMsg, InitializationMsg, RootMsg,
     Sm64JsMsg,
 };
-use std::time::{Duration, Instant};
+use std::{
+    io::{self, Read},
+    time::{Duration, Instant},
+};
+use thiserror::Error;
 
 /// How often heartbeat pings are sent
 const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
@@ -29,8 +38,12 @@ pub struct Sm64JsWsSession {
     data_afk_check: Vec<f32>,
     data_loop_index: u8,
     addr: Addr<server::Sm64JsServer>,
+    /// Actor of the joined room, which handles all per-frame messages
+    room_actor: Option<Addr<RoomActor>>,
     auth_info: AuthInfo,
     ip: String,
+    decode_errors: u32,
+    rate_limiter: RateLimiter,
 }
 
 impl Actor for Sm64JsWsSession {
@@ -74,19 +87,11 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for Sm64JsWsSession {
                 self.hb = Instant::now();
             }
             Ok(ws::Message::Binary(bin)) => {
-                let data = RootMsg::decode(bin.clone()).unwrap();
-                let sm64js_msg: Sm64JsMsg = match data.message {
-                    Some(root_msg::Message::UncompressedSm64jsMsg(msg)) => msg,
-                    Some(root_msg::Message::CompressedSm64jsMsg(msg)) => {
-                        use flate2::write::ZlibDecoder;
-                        use std::io::Write;
-
-                        let mut decoder = ZlibDecoder::new(Vec::new());
-                        decoder.write_all(&msg).unwrap();
-                        let msg = decoder.finish().unwrap();
-                        Sm64JsMsg::decode(&msg[..]).unwrap()
-                    }
-                    None => {
+                let sm64js_msg = match Self::decode_msg(&bin) {
+                    Ok(Some(msg)) => msg,
+                    Ok(None) => return,
+                    Err(err) => {
+                        self.handle_decode_error(err, ctx);
                         return;
                     }
                 };
@@ -95,28 +100,43 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for Sm64JsWsSession {
                         ctx.binary(bin);
                     }
                     Some(sm64_js_msg::Message::MarioMsg(mario_msg)) => {
+                        if !self.check_rate_limit(RateLimitedMsg::Mario, ctx) {
+                            return;
+                        }
                         self.data_loop_index += 1;
                         self.hb_data = Instant::now();
                         if self.data_loop_index >= 30 {
                             self.data = mario_msg.pos.clone();
                             self.data_loop_index = 0;
                         }
-                        self.addr.do_send(server::SetData {
-                            socket_id: self.id,
-                            data: mario_msg,
-                        });
+                        if let Some(room) = &self.room_actor {
+                            room.do_send(room_actor::SetData {
+                                socket_id: self.id,
+                                data: mario_msg,
+                            });
+                        }
                     }
                     Some(sm64_js_msg::Message::AttackMsg(attack_msg)) => {
-                        self.addr.do_send(server::SendAttack {
-                            socket_id: self.id,
-                            attack_msg,
-                        })
+                        if !self.check_rate_limit(RateLimitedMsg::Attack, ctx) {
+                            return;
+                        }
+                        if let Some(room) = &self.room_actor {
+                            room.do_send(room_actor::SendAttack {
+                                socket_id: self.id,
+                                attack_msg,
+                            });
+                        }
                     }
                     Some(sm64_js_msg::Message::GrabMsg(grab_flag_msg)) => {
-                        self.addr.do_send(server::SendGrabFlag {
-                            socket_id: self.id,
-                            grab_flag_msg,
-                        })
+                        if !self.check_rate_limit(RateLimitedMsg::GrabFlag, ctx) {
+                            return;
+                        }
+                        if let Some(room) = &self.room_actor {
+                            room.do_send(room_actor::SendGrabFlag {
+                                socket_id: self.id,
+                                grab_flag_msg,
+                            });
+                        }
                     }
                     Some(sm64_js_msg::Message::ChatMsg(chat_msg)) => {
                         self.addr
@@ -149,16 +169,19 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for Sm64JsWsSession {
                                     auth_info: self.auth_info.clone()
                                 })
                                 .into_actor(self)
-                                .then(move |res, _act, ctx| {
+                                .then(move |res, act, ctx| {
                                     match res {
                                         Ok(res) => {
-                                            let init_msg = if let Some(server::JoinGameAccepted { level, name }) = res {
+                                            let init_msg = if let Some(server::JoinGameAccepted { level, name, game_id, shard, room_actor }) = res {
+                                                act.room_actor = Some(room_actor);
                                                 InitializationMsg {
                                                     message: Some(initialization_msg::Message::InitGameDataMsg(InitGameDataMsg {
                                                         accepted: true,
                                                         level,
                                                         name,
                                                         socket_id,
+                                                        game_id,
+                                                        shard,
                                                 }))}
                                             } else {
                                                 InitializationMsg {
@@ -206,10 +229,15 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for Sm64JsWsSession {
                         }
                     }
                     Some(sm64_js_msg::Message::SkinMsg(skin_msg)) => {
-                        self.addr.do_send(server::SendSkin {
-                            socket_id: self.id,
-                            skin_msg,
-                        });
+                        if !self.check_rate_limit(RateLimitedMsg::Skin, ctx) {
+                            return;
+                        }
+                        if let Some(room) = &self.room_actor {
+                            room.do_send(room_actor::SendSkin {
+                                socket_id: self.id,
+                                skin_msg,
+                            });
+                        }
                     }
                     Some(sm64_js_msg::Message::ListMsg(_)) => {
                         // TODO clients don't send this
@@ -220,6 +248,15 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for Sm64JsWsSession {
                     Some(sm64_js_msg::Message::AnnouncementMsg(_)) => {
                         // TODO clients don't send this
                     }
+                    Some(sm64_js_msg::Message::DeltaListMsg(_)) => {
+                        // clients don't send this
+                    }
+                    Some(sm64_js_msg::Message::CtfMsg(_)) => {
+                        // clients don't send this
+                    }
+                    Some(sm64_js_msg::Message::RubberBandMsg(_)) => {
+                        // clients don't send this
+                    }
                     None => {}
                 }
             }
@@ -243,11 +280,84 @@ impl Sm64JsWsSession {
             data_afk_check: Vec::new(),
             data_loop_index: 0,
             addr,
+            room_actor: None,
             auth_info,
             ip,
+            decode_errors: 0,
+            rate_limiter: RateLimiter::new(),
         }
     }
 
+    fn decode_msg(bin: &[u8]) -> Result<Option<Sm64JsMsg>, DecodeError> {
+        let data = RootMsg::decode(bin).map_err(DecodeError::RootMsg)?;
+        match data.message {
+            Some(root_msg::Message::UncompressedSm64jsMsg(msg)) => Ok(Some(msg)),
+            Some(root_msg::Message::CompressedSm64jsMsg(msg)) => {
+                use flate2::read::ZlibDecoder;
+
+                let max_size = *sm64js_env::MAX_DECOMPRESSED_SIZE.get().unwrap();
+                let mut decompressed = Vec::new();
+                ZlibDecoder::new(&msg[..])
+                    .take(max_size as u64 + 1)
+                    .read_to_end(&mut decompressed)?;
+                if decompressed.len() > max_size {
+                    return Err(DecodeError::TooLarge(max_size));
+                }
+                let msg = Sm64JsMsg::decode(&decompressed[..]).map_err(DecodeError::Sm64JsMsg)?;
+                Ok(Some(msg))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Closes the connection, if a client keeps sending malformed messages.
+    fn handle_decode_error(&mut self, err: DecodeError, ctx: &mut <Self as Actor>::Context) {
+        self.decode_errors += 1;
+        METRICS.inc_malformed_messages();
+        eprintln!(
+            "socket {} sent malformed message ({}/{}): {}",
+            self.id,
+            self.decode_errors,
+            sm64js_env::MAX_DECODE_ERRORS.get().unwrap(),
+            err
+        );
+        if self.decode_errors >= *sm64js_env::MAX_DECODE_ERRORS.get().unwrap() {
+            ctx.close(Some(ws::CloseReason {
+                code: err.close_code(),
+                description: Some("too many malformed messages".to_string()),
+            }));
+            ctx.stop();
+        }
+    }
+
+    /// Returns whether a message may be processed.
+    ///
+    /// Clients that exceed their message budget get kicked.
+    fn check_rate_limit(
+        &mut self,
+        msg: RateLimitedMsg,
+        ctx: &mut <Self as Actor>::Context,
+    ) -> bool {
+        match self.rate_limiter.check(msg) {
+            RateLimit::Allowed => return true,
+            RateLimit::Limited { first } => {
+                if first {
+                    eprintln!("socket {} exceeded rate limit of {}", self.id, msg.name());
+                }
+            }
+            RateLimit::Exceeded => {
+                eprintln!(
+                    "socket {} exceeded its message budget and gets kicked",
+                    self.id
+                );
+                METRICS.inc_rate_limit_kicks();
+                ctx.address().do_send(server::Message::Kick);
+            }
+        }
+        METRICS.inc_rate_limited(msg);
+        false
+    }
+
     /// helper method that sends ping to client every second.
     ///
     /// also this method checks heartbeats from client
@@ -283,6 +393,34 @@ impl Handler<server::Message> for Sm64JsWsSession {
         match msg {
             server::Message::SendData(data) => ctx.binary(data),
             server::Message::Kick => ctx.stop(),
+            server::Message::Close(description) => {
+                ctx.close(Some(ws::CloseReason {
+                    code: ws::CloseCode::Restart,
+                    description: Some(description),
+                }));
+                ctx.stop();
+            }
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+enum DecodeError {
+    #[error("invalid root message: {0}")]
+    RootMsg(prost::DecodeError),
+    #[error("invalid zlib stream: {0}")]
+    Decompress(#[from] io::Error),
+    #[error("decompressed message exceeds {0} bytes")]
+    TooLarge(usize),
+    #[error("invalid compressed message: {0}")]
+    Sm64JsMsg(prost::DecodeError),
+}
+
+impl DecodeError {
+    fn close_code(&self) -> ws::CloseCode {
+        match self {
+            Self::TooLarge(_) => ws::CloseCode::Size,
+            _ => ws::CloseCode::Invalid,
         }
     }
 }
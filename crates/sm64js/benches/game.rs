@@ -3,9 +3,19 @@ use actix_rt::System;
 use criterion::{criterion_group, criterion_main, Criterion};
 use dashmap::DashMap;
 use parking_lot::RwLock;
-use sm64js_ws::{Client, Game, Player, Room};
+use sm64js_proto::MarioMsg;
+use sm64js_ws::{
+    AntiCheat, BroadcastData, CheatResponse, Client, Clients, Disconnect, Player, ProcessGameModes,
+    RecordRaceTime, Room, RoomActor, Rooms, RoomsConfig, METRICS,
+};
 use std::sync::Arc;
 
+/// Ticks over which the bandwidth of `Room::broadcast_data` is averaged
+const BANDWIDTH_TICKS: u32 = 100;
+
+/// Arbiters on which the room actors are running
+const ROOM_ARBITERS: usize = 4;
+
 struct ServerStub;
 
 impl Actor for ServerStub {
@@ -20,44 +30,182 @@ impl Handler<sm64js_ws::Message> for ServerStub {
     fn handle(&mut self, _msg: sm64js_ws::Message, _ctx: &mut Self::Context) -> Self::Result {}
 }
 
-fn broadcast_data(c: &mut Criterion) {
-    let _ = System::new();
+impl Handler<RecordRaceTime> for ServerStub {
+    type Result = ();
+
+    fn handle(&mut self, _msg: RecordRaceTime, _ctx: &mut Self::Context) -> Self::Result {}
+}
+
+impl Handler<Disconnect> for ServerStub {
+    type Result = ();
+
+    fn handle(&mut self, _msg: Disconnect, _ctx: &mut Self::Context) -> Self::Result {}
+}
 
-    let rooms = Room::init_rooms();
+/// Puts `clients_per_room` clients into each of the given rooms.
+fn init_rooms(
+    room_ids: &[u32],
+    clients_per_room: u32,
+    supports_delta: bool,
+) -> (Rooms, Arc<Clients>, Vec<Arc<RwLock<Player>>>) {
+    let rooms_config =
+        RoomsConfig::load(concat!(env!("CARGO_MANIFEST_DIR"), "/../../rooms.ron")).unwrap();
+    let rooms = Room::init_rooms(&rooms_config);
+    let clients = Arc::new(DashMap::new());
+    let mut players = vec![];
 
-    {
-        let mut room = rooms.get_mut(&1000).unwrap();
+    let server_stub = ServerStub {};
+    let session_addr = server_stub.start().recipient();
 
-        let clients = Arc::new(DashMap::new());
-        let server_stub = ServerStub {};
-        let session_addr = server_stub.start().recipient();
+    for (i, room_id) in room_ids.iter().enumerate() {
+        let mut room = rooms.get_mut(room_id).unwrap();
 
-        for i in 0..100u32 {
+        for j in 0..clients_per_room {
+            let socket_id = i as u32 * clients_per_room + j;
             let auth_info = sm64js_auth::AuthInfo(sm64js_db::AuthInfo {
                 account: sm64js_db::models::Account {
-                    id: i as i32,
+                    id: socket_id as i32,
                     ..Default::default()
                 },
                 ..Default::default()
             });
-            clients.insert(
-                i,
-                Client::new(session_addr.clone(), auth_info, "".to_string(), i),
-            );
+            let mut client =
+                Client::new(session_addr.clone(), auth_info, "".to_string(), socket_id);
+            client.set_level(*room_id);
+            client.set_supports_delta(supports_delta);
+            clients.insert(socket_id, client);
             let player = Arc::new(RwLock::new(Player::new(
                 clients.clone(),
-                i,
-                1000,
+                socket_id,
+                *room_id,
                 "".to_string(),
             )));
-            room.add_player(0, Arc::downgrade(&player));
+            room.add_player(socket_id, Arc::downgrade(&player));
+            players.push(player);
+        }
+    }
+
+    (rooms, clients, players)
+}
+
+/// Moves all Marios a bit, while most other fields stay the same.
+fn update_marios(clients: &Clients, tick: u32) {
+    for mut client in clients.iter_mut() {
+        let i = client.get_socket_id() as f32;
+        client.set_data(MarioMsg {
+            health: 2176,
+            action: 0x0C40_0201,
+            action_timer: tick % 16,
+            face_angle: vec![0, tick.wrapping_mul(64) as i32, 0],
+            pos: vec![i * 100. + tick as f32, 1000., i * 50.],
+            vel: vec![1., 0., 0.],
+            forward_vel: 1.,
+            raw_data: vec![0; 64],
+            ..Default::default()
+        });
+    }
+}
+
+fn broadcast_data(c: &mut Criterion) {
+    let _ = System::new();
+
+    let mut group = c.benchmark_group("Room::broadcast_data");
+    for (name, supports_delta) in [("zlib", false), ("delta", true)] {
+        let (rooms, clients, _players) = init_rooms(&[1000], 100, supports_delta);
+        let room = rooms.get(&1000).unwrap();
+        let mut tick = 0;
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                update_marios(&clients, tick);
+                tick = tick.wrapping_add(1);
+                room.broadcast_data()
+            })
+        });
+
+        let bytes_before = METRICS.get_snapshot().broadcast_bytes;
+        for _ in 0..BANDWIDTH_TICKS {
+            update_marios(&clients, tick);
+            tick = tick.wrapping_add(1);
+            room.broadcast_data().unwrap();
         }
+        let bytes = METRICS.get_snapshot().broadcast_bytes - bytes_before;
+        println!(
+            "Room::broadcast_data/{}: {} bytes per tick to {} clients",
+            name,
+            bytes / BANDWIDTH_TICKS as u64,
+            clients.len()
+        );
     }
+    group.finish();
+}
+
+/// Processes a room like its `RoomActor` does in a single tick.
+fn process_tick(room: &Room) -> usize {
+    let race_times = room.process_game_modes();
+    room.broadcast_data().unwrap();
+    race_times.len()
+}
+
+/// Compares processing all rooms on a single thread with sending the tick to the room actors,
+/// which are distributed across arbiters like in `Sm64JsServer`.
+fn process_rooms(c: &mut Criterion) {
+    let mut system = actix::System::new("process_rooms");
 
-    c.bench_function("Game::broadcast_data", |b| {
-        b.iter(|| Game::broadcast_data(rooms.clone()))
+    let room_ids = [4, 5, 6, 7, 8, 9, 10, 16];
+    let (rooms, clients, _players) = init_rooms(&room_ids, 50, true);
+    let mut group = c.benchmark_group("RoomActor tick");
+    let mut tick = 0;
+    group.bench_function("single thread", |b| {
+        b.iter(|| {
+            update_marios(&clients, tick);
+            tick = tick.wrapping_add(1);
+            rooms.iter().map(|room| process_tick(&room)).sum::<usize>()
+        })
+    });
+
+    let server_stub = ServerStub {}.start();
+    let anticheat = Arc::new(AntiCheat::new(CheatResponse::Flag, vec![]));
+    let arbiters: Vec<_> = (0..ROOM_ARBITERS).map(|_| Arbiter::new()).collect();
+    let room_actors: Vec<_> = room_ids
+        .iter()
+        .zip(arbiters.iter().cycle())
+        .map(|(room_id, arbiter)| {
+            let room_actor = RoomActor::new(
+                *room_id,
+                rooms.clone(),
+                clients.clone(),
+                server_stub.clone().recipient(),
+                server_stub.clone().recipient(),
+                anticheat.clone(),
+                Arc::new(DashMap::new()),
+            );
+            RoomActor::start_in_arbiter(arbiter, |_| room_actor)
+        })
+        .collect();
+    group.bench_function("room actors", |b| {
+        b.iter(|| {
+            update_marios(&clients, tick);
+            tick = tick.wrapping_add(1);
+            let game_modes: Vec<_> = room_actors
+                .iter()
+                .map(|room_actor| room_actor.send(ProcessGameModes))
+                .collect();
+            let broadcasts: Vec<_> = room_actors
+                .iter()
+                .map(|room_actor| room_actor.send(BroadcastData))
+                .collect();
+            system.block_on(async {
+                for request in game_modes {
+                    request.await.unwrap();
+                }
+                for request in broadcasts {
+                    request.await.unwrap();
+                }
+            })
+        })
     });
+    group.finish();
 }
 
-criterion_group!(benches, broadcast_data);
+criterion_group!(benches, broadcast_data, process_rooms);
 criterion_main!(benches);
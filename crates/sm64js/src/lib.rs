@@ -6,7 +6,7 @@ mod websocket;
 use actix::prelude::*;
 use actix_cors::Cors;
 use actix_http::cookie::SameSite;
-use actix_web::{dev::Server, middleware, App, HttpServer};
+use actix_web::{middleware, App, HttpServer};
 use diesel::{
     r2d2::{self, ConnectionManager},
     PgConnection,
@@ -15,9 +15,14 @@ use paperclip::{
     actix::{web, OpenApiExt},
     v2::models::{DefaultApiRaw, Info, Tag},
 };
-use sm64js_common::{ChatHistory, ChatHistoryData};
-use sm64js_env::{COOKIE_SAME_SITE_NONE, DATABASE_URL};
-use sm64js_ws::{Game, Room, Sm64JsServer};
+use sm64js_common::{ChatFiltersConfig, ChatHistory, ChatHistoryData};
+use sm64js_env::{
+    CHAT_FILTERS_CONFIG, COOKIE_SAME_SITE_NONE, DATABASE_URL, ROOMS_CONFIG, SHUTDOWN_COUNTDOWN,
+};
+use sm64js_ws::{
+    ChatWriter, FlushChat, Game, Room, RoomActors, RoomsConfig, Shutdown, Sm64JsServer,
+};
+use std::time::Duration;
 
 embed_migrations!("../sm64js-db/migrations");
 
@@ -31,7 +36,7 @@ const LOG_LEVEL: &str = "actix_server=debug,actix_web=debug";
 #[cfg(not(debug_assertions))]
 const LOG_LEVEL: &str = "actix_server=info,actix_web=warn";
 
-pub fn main() -> std::io::Result<Server> {
+pub async fn main() -> std::io::Result<()> {
     use actix_session::CookieSession;
     use parking_lot::RwLock;
     use std::env;
@@ -50,10 +55,30 @@ pub fn main() -> std::io::Result<Server> {
     );
     let conn = pool.get().unwrap();
     embedded_migrations::run(&conn).unwrap();
-    let chat_history: ChatHistoryData = web::Data::new(RwLock::new(ChatHistory::default()));
-    let rooms = Room::init_rooms();
-    let server = Sm64JsServer::new(pool.clone(), chat_history.clone(), rooms.clone()).start();
-    Game::run(server.clone(), rooms);
+    let chat_filters_config = ChatFiltersConfig::load(CHAT_FILTERS_CONFIG.get().unwrap())
+        .unwrap_or_else(|err| panic!("Failed to load chat filter config: {}", err));
+    let chat_history: ChatHistoryData =
+        web::Data::new(RwLock::new(ChatHistory::new(chat_filters_config)));
+    let chat_writer = {
+        let pool = pool.clone();
+        let chat_history = chat_history.clone();
+        ChatWriter::start_in_arbiter(&Arbiter::new(), move |_| {
+            ChatWriter::new(pool, chat_history)
+        })
+    };
+    let rooms_config = RoomsConfig::load(ROOMS_CONFIG.get().unwrap())
+        .unwrap_or_else(|err| panic!("Failed to load room config: {}", err));
+    let rooms = Room::init_rooms(&rooms_config);
+    let room_actors = RoomActors::default();
+    let server = Sm64JsServer::new(
+        pool.clone(),
+        chat_history.clone(),
+        rooms.clone(),
+        rooms_config,
+        room_actors.clone(),
+    )
+    .start();
+    let game = Game::run(server.clone(), rooms, room_actors);
 
     // TODO fetch Google Discovery document and cache it
     // let request = awc::Client::default()
@@ -64,7 +89,8 @@ pub fn main() -> std::io::Result<Server> {
     //     panic!("Could not fetch Google Discovery document");
     // }
 
-    Ok(HttpServer::new(move || {
+    let shutdown_server = server.clone();
+    let http_server = HttpServer::new(move || {
         let spec = DefaultApiRaw {
             tags: vec![
                 Tag {
@@ -117,7 +143,6 @@ A session cookie will then be stored in the user's browser that can be used to f
         App::new()
             .wrap_api_with_spec(spec)
             .app_data(pool.clone())
-            .app_data(chat_history.clone())
             .data(server.clone())
             .wrap(middleware::Logger::default())
             .with_json_spec_at("/apispec")
@@ -150,5 +175,52 @@ A session cookie will then be stored in the user's browser that can be used to f
             .service(actix_files::Files::new("/", DIST_FOLDER).index_file("index.html"))
     })
     .bind("0.0.0.0:3060")?
-    .run())
+    .disable_signals()
+    .run();
+
+    actix::spawn({
+        let http_server = http_server.clone();
+        async move {
+            wait_for_shutdown_signal().await;
+            eprintln!("Shutting down");
+            let countdown = Duration::from_secs(*SHUTDOWN_COUNTDOWN.get().unwrap());
+            if let Err(err) = shutdown_server.send(Shutdown { countdown }).await {
+                eprintln!("{:?}", err);
+            }
+            if let Err(err) = chat_writer.send(FlushChat).await {
+                eprintln!("{:?}", err);
+            }
+            http_server.stop(true).await;
+        }
+    });
+
+    let res = http_server.await;
+    game.stop();
+    res
+}
+
+/// Resolves on SIGINT or SIGTERM
+async fn wait_for_shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use actix_web::rt::signal::unix::{signal, SignalKind};
+
+        let mut sigterm = match signal(SignalKind::terminate()) {
+            Ok(sigterm) => sigterm,
+            Err(err) => {
+                eprintln!("{:?}", err);
+                let _ = actix_web::rt::signal::ctrl_c().await;
+                return;
+            }
+        };
+        futures::future::select(
+            Box::pin(actix_web::rt::signal::ctrl_c()),
+            Box::pin(sigterm.recv()),
+        )
+        .await;
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = actix_web::rt::signal::ctrl_c().await;
+    }
 }
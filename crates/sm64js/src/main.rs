@@ -1,34 +1,35 @@
-use rustrict::{add_word, Type};
+use rustrict::{Trie, Type};
 
 #[actix_web::main]
 pub async fn main() -> std::io::Result<()> {
     unsafe {
-        add_word("can't it", Type::SAFE);
-        add_word("cant it", Type::SAFE);
-        add_word("butt", Type::SAFE);
-        add_word("crap", Type::SAFE);
-        add_word("damn", Type::SAFE);
-        add_word("dic", Type::SAFE); // DiC entertainment
-        add_word("fat", Type::SAFE);
-        add_word("frick", Type::SAFE);
-        add_word("isgay", Type::SAFE);
-        add_word("gay", Type::SAFE);
-        add_word("hell", Type::SAFE);
-        add_word("to hell", Type::SAFE);
-        add_word("h i liter", Type::SAFE);
-        add_word("hoe", Type::SAFE);
-        add_word("homo", Type::SAFE);
-        add_word("naked", Type::SAFE);
-        add_word("naughty", Type::SAFE);
-        add_word("poggers", Type::SAFE);
-        add_word("splix", Type::SAFE);
-        add_word("suck", Type::SAFE);
-        add_word("sucks", Type::SAFE);
-        add_word("stfu", Type::SAFE);
-        add_word("to hell", Type::SAFE);
-        add_word("ur mom", Type::SAFE);
+        let trie = Trie::customize_default();
+        trie.set("can't it", Type::SAFE);
+        trie.set("cant it", Type::SAFE);
+        trie.set("butt", Type::SAFE);
+        trie.set("crap", Type::SAFE);
+        trie.set("damn", Type::SAFE);
+        trie.set("dic", Type::SAFE); // DiC entertainment
+        trie.set("fat", Type::SAFE);
+        trie.set("frick", Type::SAFE);
+        trie.set("isgay", Type::SAFE);
+        trie.set("gay", Type::SAFE);
+        trie.set("hell", Type::SAFE);
+        trie.set("to hell", Type::SAFE);
+        trie.set("h i liter", Type::SAFE);
+        trie.set("hoe", Type::SAFE);
+        trie.set("homo", Type::SAFE);
+        trie.set("naked", Type::SAFE);
+        trie.set("naughty", Type::SAFE);
+        trie.set("poggers", Type::SAFE);
+        trie.set("splix", Type::SAFE);
+        trie.set("suck", Type::SAFE);
+        trie.set("sucks", Type::SAFE);
+        trie.set("stfu", Type::SAFE);
+        trie.set("to hell", Type::SAFE);
+        trie.set("ur mom", Type::SAFE);
 
-        add_word("mierda", Type::PROFANE);
+        trie.set("mierda", Type::PROFANE);
     }
-    sm64js::main()?.await
+    sm64js::main().await
 }
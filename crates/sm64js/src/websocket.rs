@@ -1,8 +1,8 @@
-use actix::Addr;
+use actix::{Addr, MailboxError};
 use actix_web::{dev::Body, http::StatusCode, HttpRequest, HttpResponse, ResponseError};
 use actix_web_actors::ws;
 use paperclip::actix::{api_v2_errors, api_v2_operation, web};
-use sm64js_auth::Identity;
+use sm64js_auth::{Identity, Permission};
 use sm64js_common::get_ip_from_req;
 use sm64js_db::{models::Ban, DbPool};
 use sm64js_ws::{Sm64JsServer, Sm64JsWsSession};
@@ -17,6 +17,15 @@ pub async fn index(
     identity: Identity,
 ) -> Result<HttpResponse, WsError> {
     let auth_info = identity.get_auth_info();
+
+    let status = srv.send(sm64js_ws::GetServerStatus).await?;
+    if status.shutting_down {
+        return Err(WsError::ShuttingDown);
+    }
+    if status.maintenance && !auth_info.has_permission(&Permission::JoinDuringMaintenance) {
+        return Err(WsError::Maintenance);
+    }
+
     let conn = pool.get().unwrap();
 
     if let Some(ban) = sm64js_db::is_account_banned(&conn, auth_info.get_account_id())? {
@@ -42,6 +51,12 @@ pub enum WsError {
     Banned(Ban),
     #[error("[DbError]: {0}")]
     DbError(#[from] sm64js_db::DbError),
+    #[error("[ShuttingDown]: server is restarting")]
+    ShuttingDown,
+    #[error("[Maintenance]: server is in maintenance mode")]
+    Maintenance,
+    #[error("[MailboxError]: {0}")]
+    Mailbox(#[from] MailboxError),
 }
 
 impl ResponseError for WsError {
@@ -51,6 +66,10 @@ impl ResponseError for WsError {
             Self::Actix(_) => HttpResponse::new(StatusCode::INTERNAL_SERVER_ERROR),
             Self::Banned(_) => HttpResponse::new(StatusCode::FORBIDDEN),
             Self::DbError(err) => return err.error_response(),
+            Self::ShuttingDown | Self::Maintenance => {
+                HttpResponse::new(StatusCode::SERVICE_UNAVAILABLE)
+            }
+            Self::Mailbox(_) => HttpResponse::new(StatusCode::INTERNAL_SERVER_ERROR),
         };
         res.set_body(Body::from(format!("{}", self)))
     }